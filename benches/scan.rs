@@ -0,0 +1,153 @@
+//! Scan benchmarks on a synthetic home directory.
+//!
+//! Run with `cargo bench`. The tree has tens of thousands of plain directories with a
+//! few hundred repositories in between, a mix of clean, dirty and unpushed ones.
+//! Compare `scan` with `check` to see how much of a scan goes to walking directories,
+//! or run `nasty-boii --stats` on a real tree. `check/in_sync` against
+//! `check/in_sync_opened` shows what reading the ref files instead of opening clean
+//! repositories saves.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use git2::{Repository, Signature};
+use nasty_boii::check::CheckSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+/// Top level directories, each with `SUBDIRS` directories of `LEAVES` directories
+const TOP_DIRS: usize = 100;
+const SUBDIRS: usize = 10;
+const LEAVES: usize = 20;
+/// Every n-th subdirectory holds a repository
+const REPO_EVERY: usize = 4;
+
+struct Tree {
+    dir: TempDir,
+    repos: Vec<PathBuf>,
+    /// Repositories whose branch is where its upstream is
+    in_sync: Vec<PathBuf>,
+}
+
+impl Tree {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let mut repos = Vec::new();
+        let mut in_sync = Vec::new();
+        for top in 0..TOP_DIRS {
+            for sub in 0..SUBDIRS {
+                let subdir = dir.path().join(format!("d{top}")).join(format!("s{sub}"));
+                for leaf in 0..LEAVES {
+                    fs::create_dir_all(subdir.join(format!("l{leaf}"))).unwrap();
+                }
+                let n = top * SUBDIRS + sub;
+                if n.is_multiple_of(REPO_EVERY) {
+                    let repo = subdir.join("repo");
+                    if create_repo(&repo, n / REPO_EVERY) {
+                        in_sync.push(repo.clone());
+                    }
+                    repos.push(repo);
+                }
+            }
+        }
+        Self {
+            dir,
+            repos,
+            in_sync,
+        }
+    }
+
+    fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+/// Creates a repository with a commit, dirty for every third and with an upstream for
+/// every second `n`, so the checks see different states. Returns whether it has an
+/// upstream, which is at the same commit.
+fn create_repo(path: &Path, n: usize) -> bool {
+    let repo = Repository::init(path).unwrap();
+    fs::write(path.join("README.md"), "# Bench\n").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("README.md")).unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("Bench", "bench@example.com").unwrap();
+    let commit = repo
+        .commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Initial commit",
+            &tree,
+            &[],
+        )
+        .unwrap();
+
+    if n.is_multiple_of(2) {
+        repo.remote("origin", "https://example.com/bench.git")
+            .unwrap();
+        repo.reference("refs/remotes/origin/main", commit, true, "bench")
+            .unwrap();
+        let mut config = repo.config().unwrap();
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        config
+            .set_str(&format!("branch.{branch}.remote"), "origin")
+            .unwrap();
+        config
+            .set_str(&format!("branch.{branch}.merge"), "refs/heads/main")
+            .unwrap();
+    }
+    if n.is_multiple_of(3) {
+        fs::write(path.join("README.md"), "# Changed\n").unwrap();
+    }
+    n.is_multiple_of(2)
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let tree = Tree::new();
+    let data_dir = tempfile::tempdir().unwrap();
+
+    let mut group = c.benchmark_group("scan");
+    group.sample_size(10);
+    group.bench_function("walk_and_check", |b| {
+        b.iter(|| {
+            let output = Command::new(env!("CARGO_BIN_EXE_nasty-boii"))
+                .args(["--no-history", "--count"])
+                .arg(tree.path())
+                .env("NASTY_BOII_DATA_DIR", data_dir.path())
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+        });
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("check");
+    group.sample_size(10);
+    let checks = CheckSet::default();
+    group.bench_function("default_checks", |b| {
+        b.iter(|| {
+            for repo in &tree.repos {
+                checks.check(repo).unwrap();
+            }
+        });
+    });
+    group.bench_function("in_sync", |b| {
+        b.iter(|| {
+            for repo in &tree.in_sync {
+                checks.check(repo).unwrap();
+            }
+        });
+    });
+    group.bench_function("in_sync_opened", |b| {
+        b.iter(|| {
+            for repo in &tree.in_sync {
+                checks.run_all(&Repository::open(repo).unwrap()).unwrap();
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_scan);
+criterion_main!(benches);
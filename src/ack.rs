@@ -0,0 +1,193 @@
+use crate::db::Db;
+use crate::paths;
+use anyhow::{bail, Context, Result};
+use git2::Repository;
+use rusqlite::params;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A repository the user knows is nasty and doesn't want to be reminded of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ack {
+    pub path: PathBuf,
+    /// HEAD when the repository was acknowledged; new commits end the acknowledgement
+    pub head: String,
+    /// End of the acknowledgement in seconds since the Unix epoch
+    pub until: Option<i64>,
+    pub reason: Option<String>,
+}
+
+/// Acknowledged repositories, stored in the database.
+#[derive(Debug)]
+pub struct Acks<'a> {
+    db: &'a Db,
+}
+
+impl<'a> Acks<'a> {
+    #[must_use]
+    pub fn new(db: &'a Db) -> Self {
+        Self { db }
+    }
+
+    /// Acknowledges the repository at `repo_path` in its current state, replacing an
+    /// earlier acknowledgement.
+    ///
+    /// # Errors
+    /// Returns an error if the path is not a git repository with a HEAD or the
+    /// acknowledgement cannot be stored.
+    pub fn add(&self, repo_path: &Path, until: Option<i64>, reason: Option<&str>) -> Result<Ack> {
+        let path = paths::canonicalize(repo_path)
+            .context(format!("Failed to resolve path: {}", repo_path.display()))?;
+        let Some(head) = head_oid(&path) else {
+            bail!("Not a git repository with a HEAD: {}", path.display());
+        };
+
+        self.db
+            .conn()
+            .execute(
+                "INSERT OR REPLACE INTO acks (path, head, until, reason) VALUES (?1, ?2, ?3, ?4)",
+                params![path.to_string_lossy(), head, until, reason],
+            )
+            .context("Failed to acknowledge repository")?;
+        Ok(Ack {
+            path,
+            head,
+            until,
+            reason: reason.map(str::to_string),
+        })
+    }
+
+    /// Removes the acknowledgement of `repo_path`.
+    ///
+    /// # Errors
+    /// Returns an error if the path is not acknowledged.
+    pub fn remove(&self, repo_path: &Path) -> Result<PathBuf> {
+        let path = paths::canonicalize(repo_path).unwrap_or_else(|_| repo_path.to_path_buf());
+        let removed = self
+            .db
+            .conn()
+            .execute(
+                "DELETE FROM acks WHERE path = ?1",
+                params![path.to_string_lossy()],
+            )
+            .context("Failed to remove acknowledgement")?;
+        if removed == 0 {
+            bail!("Not acknowledged: {}", path.display());
+        }
+        Ok(path)
+    }
+
+    /// All acknowledgements, sorted by path.
+    ///
+    /// # Errors
+    /// Returns an error if the acknowledgements cannot be read.
+    pub fn list(&self) -> Result<Vec<Ack>> {
+        let mut stmt = self
+            .db
+            .conn()
+            .prepare("SELECT path, head, until, reason FROM acks ORDER BY path")?;
+        let acks = stmt
+            .query_map([], |row| {
+                Ok(Ack {
+                    path: PathBuf::from(row.get::<_, String>(0)?),
+                    head: row.get(1)?,
+                    until: row.get(2)?,
+                    reason: row.get(3)?,
+                })
+            })?
+            .collect::<Result<_, _>>()
+            .context("Failed to read acknowledgements")?;
+        Ok(acks)
+    }
+
+    /// Loads all acknowledgements for looking up scanned repositories.
+    ///
+    /// # Errors
+    /// Returns an error if the acknowledgements cannot be read.
+    pub fn load(&self) -> Result<AckList> {
+        Ok(AckList {
+            acks: self
+                .list()?
+                .into_iter()
+                .map(|ack| (ack.path.clone(), ack))
+                .collect(),
+        })
+    }
+}
+
+/// Acknowledgements by canonical path.
+#[derive(Debug, Default)]
+pub struct AckList {
+    acks: HashMap<PathBuf, Ack>,
+}
+
+impl AckList {
+    /// Whether the repository at `repo_path` is acknowledged at time `now`, i.e. the
+    /// acknowledgement hasn't expired and HEAD hasn't moved since.
+    #[must_use]
+    pub fn covers(&self, repo_path: &Path, now: i64) -> bool {
+        if self.acks.is_empty() {
+            return false;
+        }
+        let Some(ack) = paths::canonicalize(repo_path)
+            .ok()
+            .and_then(|path| self.acks.get(&path))
+        else {
+            return false;
+        };
+        ack.until.is_none_or(|until| now < until) && head_oid(repo_path).as_ref() == Some(&ack.head)
+    }
+}
+
+fn head_oid(repo_path: &Path) -> Option<String> {
+    let repo = Repository::open(repo_path).ok()?;
+    let oid = repo.head().ok()?.target()?;
+    Some(oid.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn git(repo_path: &Path, args: &[&str]) {
+        Command::new("git")
+            .args(args)
+            .current_dir(repo_path)
+            .output()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_ack_until_head_moves() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        Command::new("git")
+            .args(["init"])
+            .arg(&repo_path)
+            .output()
+            .unwrap();
+        git(&repo_path, &["config", "user.name", "Test User"]);
+        git(&repo_path, &["config", "user.email", "test@example.com"]);
+        git(
+            &repo_path,
+            &["commit", "--allow-empty", "-m", "Initial commit"],
+        );
+        let db = Db::open(&temp_dir.path().join("nasty-boii.db")).unwrap();
+        let acks = Acks::new(&db);
+
+        acks.add(&repo_path, None, Some("experiment")).unwrap();
+        assert!(acks.load().unwrap().covers(&repo_path, 0));
+
+        git(&repo_path, &["commit", "--allow-empty", "-m", "More work"]);
+        assert!(!acks.load().unwrap().covers(&repo_path, 0));
+
+        acks.add(&repo_path, Some(100), None).unwrap();
+        assert_eq!(acks.list().unwrap().len(), 1);
+        assert!(acks.load().unwrap().covers(&repo_path, 99));
+        assert!(!acks.load().unwrap().covers(&repo_path, 100));
+
+        acks.remove(&repo_path).unwrap();
+        assert!(acks.remove(&repo_path).is_err());
+    }
+}
@@ -0,0 +1,441 @@
+use crate::report::{conflicted_files, is_dirty, operation_in_progress};
+use crate::{describe_ahead, head_state, lfs, open_repo, refs, Comparison, RepoStatus};
+use anyhow::{Context, Result};
+use git2::{BranchType, Oid, Repository};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Kind of risk an audit detector can find in a repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindingKind {
+    Unpushed,
+    MissingHead,
+    Dirty,
+    Conflicted,
+    OperationInProgress,
+    Stashes,
+    UnpushedTags,
+    UnpushedLfs,
+    UnpushedNotes,
+    DanglingCommits,
+    Submodules,
+    Worktrees,
+}
+
+impl FindingKind {
+    pub const ALL: [Self; 12] = [
+        Self::Unpushed,
+        Self::MissingHead,
+        Self::Dirty,
+        Self::Conflicted,
+        Self::OperationInProgress,
+        Self::Stashes,
+        Self::UnpushedTags,
+        Self::UnpushedLfs,
+        Self::UnpushedNotes,
+        Self::DanglingCommits,
+        Self::Submodules,
+        Self::Worktrees,
+    ];
+}
+
+impl FromStr for FindingKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.to_string() == s)
+            .ok_or_else(|| format!("unknown finding kind {s:?}"))
+    }
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Unpushed => "unpushed",
+            Self::MissingHead => "missing-head",
+            Self::Dirty => "dirty",
+            Self::Conflicted => "conflicted",
+            Self::OperationInProgress => "operation-in-progress",
+            Self::Stashes => "stashes",
+            Self::UnpushedTags => "unpushed-tags",
+            Self::UnpushedLfs => "unpushed-lfs",
+            Self::UnpushedNotes => "unpushed-notes",
+            Self::DanglingCommits => "dangling-commits",
+            Self::Submodules => "submodules",
+            Self::Worktrees => "worktrees",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub detail: String,
+    /// When the risk arose in seconds since the Unix epoch, if known: the time of the
+    /// oldest unpushed commit or stash
+    pub since: Option<i64>,
+}
+
+/// Result of running every detector against one repository.
+#[derive(Debug)]
+pub struct AuditReport {
+    pub path: PathBuf,
+    pub findings: Vec<Finding>,
+}
+
+impl AuditReport {
+    /// Runs all detectors against the repository at `repo_path`, comparing its branches
+    /// as `comparison` says.
+    ///
+    /// # Errors
+    /// Returns an error if the repository cannot be opened or if git operations fail.
+    pub fn run(repo_path: &Path, comparison: &Comparison) -> Result<Self> {
+        Self::run_on(open_repo(repo_path)?, repo_path, comparison)
+    }
+
+    /// Runs all detectors against an open repository, reported as `repo_path`.
+    ///
+    /// # Errors
+    /// Returns an error if git operations fail.
+    pub fn run_on(mut repo: Repository, repo_path: &Path, comparison: &Comparison) -> Result<Self> {
+        let mut findings = Vec::new();
+        let mut add = |kind, detail: String| {
+            findings.push(Finding {
+                kind,
+                detail,
+                since: None,
+            });
+        };
+
+        let state = head_state(&repo, comparison)?;
+        match (state.status, state.ahead) {
+            (RepoStatus::MissingHead, _) => {
+                add(
+                    FindingKind::MissingHead,
+                    "Repository has no HEAD".to_string(),
+                );
+            }
+            (RepoStatus::HasUnpushed, ahead) => add(
+                FindingKind::Unpushed,
+                describe_ahead(state.branch.as_deref().unwrap_or("-"), ahead, comparison),
+            ),
+            // Other kinds of unpushed work are reported by the detectors below
+            _ => {}
+        }
+
+        if is_dirty(&repo)? {
+            add(
+                FindingKind::Dirty,
+                "Working tree has uncommitted changes".to_string(),
+            );
+        }
+
+        let conflicted = conflicted_files(&repo)?;
+        if conflicted > 0 {
+            add(
+                FindingKind::Conflicted,
+                format!("{conflicted} file(s) with unresolved merge conflicts"),
+            );
+        }
+
+        if let Some(operation) = operation_in_progress(&repo) {
+            add(
+                FindingKind::OperationInProgress,
+                format!("{operation} in progress"),
+            );
+        }
+
+        let stashes = count_stashes(&mut repo)?;
+        if stashes > 0 {
+            add(FindingKind::Stashes, format!("{stashes} stash(es)"));
+        }
+
+        let tags = unpushed_tags(&repo)?;
+        if !tags.is_empty() {
+            add(
+                FindingKind::UnpushedTags,
+                format!("Tags not on any remote branch: {}", tags.join(", ")),
+            );
+        }
+
+        for finding in lfs::unpushed_large_files(&repo)? {
+            add(FindingKind::UnpushedLfs, finding);
+        }
+
+        let notes = refs::unpushed_notes(&repo)?;
+        if !notes.is_empty() {
+            add(
+                FindingKind::UnpushedNotes,
+                format!("Notes not on any remote: {}", notes.join(", ")),
+            );
+        }
+
+        let dangling = count_reflog_only_commits(&repo)?;
+        if dangling > 0 {
+            add(
+                FindingKind::DanglingCommits,
+                format!("{dangling} commit(s) only reachable from the reflog"),
+            );
+        }
+
+        let submodules = nasty_submodules(&repo, comparison)?;
+        if !submodules.is_empty() {
+            add(
+                FindingKind::Submodules,
+                format!("Submodules at risk: {}", submodules.join(", ")),
+            );
+        }
+
+        let worktrees = nasty_worktrees(&repo, comparison)?;
+        if !worktrees.is_empty() {
+            add(
+                FindingKind::Worktrees,
+                format!("Worktrees at risk: {}", worktrees.join(", ")),
+            );
+        }
+
+        for finding in &mut findings {
+            finding.since = match finding.kind {
+                FindingKind::Unpushed => oldest_unpushed_commit(&repo),
+                FindingKind::Stashes => oldest_stash(&repo),
+                _ => None,
+            };
+        }
+
+        Ok(Self {
+            path: repo_path.to_path_buf(),
+            findings,
+        })
+    }
+
+    /// A repository passes the audit if no detector found anything.
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+fn count_stashes(repo: &mut Repository) -> Result<usize> {
+    let mut count = 0;
+    repo.stash_foreach(|_, _, _| {
+        count += 1;
+        true
+    })
+    .context("Failed to list stashes")?;
+    Ok(count)
+}
+
+/// Commit time of the oldest commit of HEAD that no remote-tracking branch contains.
+fn oldest_unpushed_commit(repo: &Repository) -> Option<i64> {
+    let mut walk = repo.revwalk().ok()?;
+    walk.push_head().ok()?;
+    for branch in repo.branches(Some(BranchType::Remote)).ok()?.flatten() {
+        if let Some(target) = branch.0.get().target() {
+            let _ = walk.hide(target);
+        }
+    }
+    walk.filter_map(|oid| repo.find_commit(oid.ok()?).ok())
+        .map(|commit| commit.time().seconds())
+        .min()
+}
+
+fn oldest_stash(repo: &Repository) -> Option<i64> {
+    let reflog = repo.reflog("refs/stash").ok()?;
+    reflog
+        .iter()
+        .map(|entry| entry.committer().when().seconds())
+        .min()
+}
+
+/// Tags pointing at commits that no remote-tracking branch contains.
+pub(crate) fn unpushed_tags(repo: &Repository) -> Result<Vec<String>> {
+    let remote_oids: Vec<Oid> = repo
+        .branches(Some(BranchType::Remote))
+        .context("Failed to list remote branches")?
+        .filter_map(|branch| branch.ok()?.0.get().target())
+        .collect();
+
+    let mut tags = Vec::new();
+    let names = repo.tag_names(None).context("Failed to list tags")?;
+    for name in names.iter().flatten() {
+        let Ok(commit) = repo
+            .revparse_single(&format!("refs/tags/{name}"))
+            .and_then(|obj| obj.peel_to_commit())
+        else {
+            continue;
+        };
+        let commit_oid = commit.id();
+        let pushed = remote_oids.iter().any(|&remote_oid| {
+            remote_oid == commit_oid
+                || repo
+                    .graph_descendant_of(remote_oid, commit_oid)
+                    .unwrap_or(false)
+        });
+        if !pushed {
+            tags.push(name.to_string());
+        }
+    }
+    Ok(tags)
+}
+
+/// Commits that are only reachable from reflog entries, e.g. after a reset or
+/// deleting a branch. These are what `git fsck` would eventually report as dangling.
+fn count_reflog_only_commits(repo: &Repository) -> Result<usize> {
+    let mut walk = repo.revwalk().context("Failed to create revwalk")?;
+
+    let mut ref_names = vec!["HEAD".to_string()];
+    for reference in repo.references().context("Failed to list references")? {
+        let reference = reference.context("Failed to read reference")?;
+        if let Some(target) = reference.resolve().ok().and_then(|r| r.target()) {
+            // Ignore refs pointing at non-commits, such as annotated tags of trees
+            let _ = walk.hide(target);
+        }
+        // Older stashes live in the stash reflog and are reported separately
+        if let Some(name) = reference.name().filter(|name| *name != "refs/stash") {
+            ref_names.push(name.to_string());
+        }
+    }
+
+    let mut pushed_any = false;
+    for name in &ref_names {
+        let Ok(reflog) = repo.reflog(name) else {
+            continue;
+        };
+        for entry in reflog.iter() {
+            let oid = entry.id_new();
+            // Objects may have been garbage collected since
+            if !oid.is_zero() && walk.push(oid).is_ok() {
+                pushed_any = true;
+            }
+        }
+    }
+    if !pushed_any {
+        return Ok(0);
+    }
+
+    Ok(walk.filter_map(std::result::Result::ok).count())
+}
+
+/// Whether a nested repository (submodule or worktree) has unpushed or uncommitted work.
+fn is_nasty(repo: &Repository, comparison: &Comparison) -> bool {
+    let unpushed =
+        head_state(repo, comparison).is_ok_and(|state| state.status == RepoStatus::HasUnpushed);
+    unpushed || is_dirty(repo).unwrap_or(false)
+}
+
+fn nasty_submodules(repo: &Repository, comparison: &Comparison) -> Result<Vec<String>> {
+    let submodules = repo.submodules().context("Failed to list submodules")?;
+    Ok(submodules
+        .iter()
+        .filter(|submodule| submodule.open().is_ok_and(|sub| is_nasty(&sub, comparison)))
+        .map(|submodule| submodule.path().display().to_string())
+        .collect())
+}
+
+fn nasty_worktrees(repo: &Repository, comparison: &Comparison) -> Result<Vec<String>> {
+    let names = repo.worktrees().context("Failed to list worktrees")?;
+    Ok(names
+        .iter()
+        .flatten()
+        .filter(|name| {
+            repo.find_worktree(name)
+                .and_then(|worktree| Repository::open_from_worktree(&worktree))
+                .is_ok_and(|wt_repo| is_nasty(&wt_repo, comparison))
+        })
+        .map(str::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn git(repo_path: &Path, args: &[&str]) {
+        Command::new("git")
+            .args(args)
+            .current_dir(repo_path)
+            .output()
+            .unwrap();
+    }
+
+    fn setup_pushed_repo(temp_dir: &Path) -> PathBuf {
+        let repo_path = temp_dir.join("repo");
+        let remote_path = temp_dir.join("remote.git");
+        Command::new("git")
+            .args(["init", "--bare"])
+            .arg(&remote_path)
+            .output()
+            .unwrap();
+        Command::new("git")
+            .args(["init"])
+            .arg(&repo_path)
+            .output()
+            .unwrap();
+        git(&repo_path, &["config", "user.name", "Test User"]);
+        git(&repo_path, &["config", "user.email", "test@example.com"]);
+        std::fs::write(repo_path.join("test.txt"), "content").unwrap();
+        git(&repo_path, &["add", "."]);
+        git(&repo_path, &["commit", "-m", "Initial commit"]);
+        git(
+            &repo_path,
+            &["remote", "add", "origin", remote_path.to_str().unwrap()],
+        );
+        git(&repo_path, &["push", "-u", "origin", "main"]);
+        repo_path
+    }
+
+    fn kinds(report: &AuditReport) -> Vec<FindingKind> {
+        report.findings.iter().map(|f| f.kind).collect()
+    }
+
+    #[test]
+    fn test_audit_clean_repo_passes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = setup_pushed_repo(temp_dir.path());
+
+        let report = AuditReport::run(&repo_path, &Comparison::default()).unwrap();
+        assert!(report.passed(), "{:?}", report.findings);
+    }
+
+    #[test]
+    fn test_audit_detects_stash_tag_and_dirty() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = setup_pushed_repo(temp_dir.path());
+
+        std::fs::write(repo_path.join("test.txt"), "stashed").unwrap();
+        git(&repo_path, &["stash"]);
+        std::fs::write(repo_path.join("test.txt"), "local").unwrap();
+        git(&repo_path, &["commit", "-am", "Tagged commit"]);
+        git(&repo_path, &["tag", "v1.0"]);
+        git(&repo_path, &["reset", "--hard", "origin/main"]);
+        std::fs::write(repo_path.join("test.txt"), "changed").unwrap();
+
+        let report = AuditReport::run(&repo_path, &Comparison::default()).unwrap();
+        assert_eq!(
+            kinds(&report),
+            vec![
+                FindingKind::Dirty,
+                FindingKind::Stashes,
+                FindingKind::UnpushedTags,
+            ]
+        );
+        assert!(!report.passed());
+    }
+
+    #[test]
+    fn test_audit_detects_reflog_only_commits() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = setup_pushed_repo(temp_dir.path());
+
+        std::fs::write(repo_path.join("test.txt"), "lost").unwrap();
+        git(&repo_path, &["commit", "-am", "Lost commit"]);
+        git(&repo_path, &["reset", "--hard", "origin/main"]);
+
+        let report = AuditReport::run(&repo_path, &Comparison::default()).unwrap();
+        assert_eq!(kinds(&report), vec![FindingKind::DanglingCommits]);
+    }
+}
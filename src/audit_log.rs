@@ -0,0 +1,314 @@
+//! Append-only log of commands run in repositories, so that batch operations such as
+//! `--exec 'git push'` over hundreds of repositories can be reviewed afterwards.
+//!
+//! Each entry records where the command ran, how it exited and the checked-out branch
+//! before and after, i.e. how many commits were pushed to or fetched from which
+//! remote. Entries are JSON lines in `audit.log` in the data directory. The log is
+//! never rewritten, only appended to.
+
+use crate::open_repo;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use git2::{BranchType, Oid, Reference, Repository};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// The checked-out branch of a repository and its upstream.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BranchState {
+    pub branch: Option<String>,
+    /// URL of the remote of the upstream branch
+    pub remote_url: Option<String>,
+    /// Commit the branch points to
+    pub head: Option<String>,
+    /// Commit the upstream branch points to, `None` without upstream
+    pub upstream: Option<String>,
+}
+
+impl BranchState {
+    /// The state of the repository at `repo_path`, empty on a detached HEAD or if it
+    /// cannot be opened.
+    #[must_use]
+    pub fn of(repo_path: &Path) -> Self {
+        open_repo(repo_path)
+            .map(|repo| Self::read(&repo))
+            .unwrap_or_default()
+    }
+
+    fn read(repo: &Repository) -> Self {
+        let Some(head) = repo.head().ok().filter(Reference::is_branch) else {
+            return Self::default();
+        };
+        let upstream = head
+            .shorthand()
+            .and_then(|name| repo.find_branch(name, BranchType::Local).ok())
+            .and_then(|branch| branch.upstream().ok())
+            .and_then(|upstream| upstream.get().target());
+        let remote_url = head
+            .name()
+            .and_then(|name| repo.branch_upstream_remote(name).ok())
+            .and_then(|remote| remote.as_str().map(str::to_string))
+            .and_then(|remote| repo.find_remote(&remote).ok())
+            .and_then(|remote| remote.url().map(str::to_string));
+        Self {
+            branch: head.shorthand().map(str::to_string),
+            remote_url,
+            head: head.target().map(|oid| oid.to_string()),
+            upstream: upstream.map(|oid| oid.to_string()),
+        }
+    }
+}
+
+/// A command that ran in a repository.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// When the command finished, seconds since the epoch
+    pub time: i64,
+    pub repo: PathBuf,
+    pub command: String,
+    /// Exit code, `None` if the command could not be started or was killed by a signal
+    pub exit_code: Option<i32>,
+    pub before: BranchState,
+    pub after: BranchState,
+    /// Commits that were local before and are on the upstream now
+    pub pushed: usize,
+    /// Commits that are on the upstream now and were not local before
+    pub fetched: usize,
+}
+
+impl Entry {
+    /// An entry for `command`, which ran in `repo_path` when it was in the state
+    /// `before`.
+    #[must_use]
+    pub fn new(
+        repo_path: &Path,
+        command: String,
+        exit_code: Option<i32>,
+        before: BranchState,
+    ) -> Self {
+        let repo = open_repo(repo_path).ok();
+        let after = repo.as_ref().map(BranchState::read).unwrap_or_default();
+        let (pushed, fetched) = repo
+            .and_then(|repo| new_upstream_commits(&repo, &before, &after).ok())
+            .unwrap_or_default();
+        Self {
+            time: Utc::now().timestamp(),
+            repo: repo_path.to_path_buf(),
+            command,
+            exit_code,
+            before,
+            after,
+            pushed,
+            fetched,
+        }
+    }
+}
+
+/// Commits the upstream gained from `before` to `after`, split into those that were
+/// local before (pushed) and the others (fetched).
+fn new_upstream_commits(
+    repo: &Repository,
+    before: &BranchState,
+    after: &BranchState,
+) -> Result<(usize, usize)> {
+    let Some(upstream) = &after.upstream else {
+        return Ok((0, 0));
+    };
+    let mut walk = repo.revwalk()?;
+    walk.push(Oid::from_str(upstream)?)?;
+    if let Some(old) = &before.upstream {
+        walk.hide(Oid::from_str(old)?)?;
+    }
+    let head = before.head.as_deref().map(Oid::from_str).transpose()?;
+    let (mut pushed, mut fetched) = (0, 0);
+    for oid in walk {
+        let oid = oid?;
+        let was_local = head.is_some_and(|head| {
+            head == oid || repo.graph_descendant_of(head, oid).unwrap_or(false)
+        });
+        if was_local {
+            pushed += 1;
+        } else {
+            fetched += 1;
+        }
+    }
+    Ok((pushed, fetched))
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = DateTime::from_timestamp(self.time, 0).map_or_else(String::new, |time| {
+            time.with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::Secs, false)
+        });
+        let status = self
+            .exit_code
+            .map_or_else(|| "failed".to_string(), |code| format!("exit {code}"));
+        write!(
+            f,
+            "{time}  {}  {status}  {}",
+            self.repo.display(),
+            self.command
+        )?;
+        if let Some(branch) = &self.after.branch {
+            let remote = self.after.remote_url.as_deref().unwrap_or("no upstream");
+            write!(f, "\n    {branch} -> {remote}")?;
+            if self.pushed > 0 {
+                write!(f, ", {} commits pushed", self.pushed)?;
+            }
+            if self.fetched > 0 {
+                write!(f, ", {} commits fetched", self.fetched)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The audit log file.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The audit log in the data directory.
+    ///
+    /// # Errors
+    /// Returns an error if the data directory cannot be determined.
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::new(crate::data_dir()?.join("audit.log")))
+    }
+
+    /// Appends `entry` to the log.
+    ///
+    /// Each entry is written at once, so entries from parallel commands don't
+    /// interleave.
+    ///
+    /// # Errors
+    /// Returns an error if the log cannot be written.
+    pub fn append(&self, entry: &Entry) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).context("Failed to create data directory")?;
+        }
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    /// All entries, oldest first. Lines that cannot be parsed are skipped.
+    ///
+    /// # Errors
+    /// Returns an error if the log exists but cannot be read.
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.path.display()))
+            }
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.with_context(|| format!("Failed to read {}", self.path.display()))?;
+            if let Ok(entry) = serde_json::from_str(&line) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{RepositoryInitOptions, Signature};
+
+    fn commit(repo: &Repository, update_ref: &str, message: &str) -> Oid {
+        let signature = Signature::now("Test User", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let parent = repo
+            .revparse_single(update_ref)
+            .ok()
+            .and_then(|object| object.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(
+            Some(update_ref),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_entry() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_opts(
+            temp_dir.path(),
+            RepositoryInitOptions::new().initial_head("main"),
+        )
+        .unwrap();
+        repo.remote("origin", "git@github.com:acme/app.git")
+            .unwrap();
+        let base = commit(&repo, "HEAD", "Base");
+        repo.reference("refs/remotes/origin/main", base, false, "")
+            .unwrap();
+        repo.find_branch("main", BranchType::Local)
+            .unwrap()
+            .set_upstream(Some("origin/main"))
+            .unwrap();
+        commit(&repo, "HEAD", "Unpushed");
+        let tip = commit(&repo, "HEAD", "Unpushed too");
+
+        // As if pushed
+        let before = BranchState::of(temp_dir.path());
+        repo.reference("refs/remotes/origin/main", tip, true, "")
+            .unwrap();
+        let push = Entry::new(temp_dir.path(), "git push".to_string(), Some(0), before);
+        assert_eq!((push.pushed, push.fetched), (2, 0));
+        assert_eq!(push.after.branch.as_deref(), Some("main"));
+        assert!(push
+            .to_string()
+            .ends_with("main -> git@github.com:acme/app.git, 2 commits pushed"));
+
+        // As if fetched
+        let before = BranchState::of(temp_dir.path());
+        commit(&repo, "refs/remotes/origin/main", "Pushed elsewhere");
+        let fetch = Entry::new(temp_dir.path(), "git fetch".to_string(), Some(0), before);
+        assert_eq!((fetch.pushed, fetch.fetched), (0, 1));
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(temp_dir.path().join("data").join("audit.log"));
+        assert!(log.entries().unwrap().is_empty());
+
+        let entry = Entry::new(
+            Path::new("/nonexistent"),
+            "git push".to_string(),
+            None,
+            BranchState::default(),
+        );
+        log.append(&entry).unwrap();
+        log.append(&entry).unwrap();
+        assert_eq!(log.entries().unwrap(), [entry.clone(), entry]);
+    }
+}
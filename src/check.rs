@@ -0,0 +1,965 @@
+//! Pluggable checks for work that would be lost if a repository was deleted.
+//!
+//! A [`CheckSet`] runs [`Check`]s in order. The status of a repository is the status
+//! of the first check that finds something. Library users can add their own checks:
+//!
+//! ```
+//! use anyhow::Result;
+//! use git2::Repository;
+//! use nasty_boii::check::{Check, CheckSet};
+//!
+//! struct Todo;
+//!
+//! impl Check for Todo {
+//!     fn name(&self) -> &str {
+//!         "todo"
+//!     }
+//!
+//!     fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+//!         let todo = repo.workdir().is_some_and(|dir| dir.join("TODO").exists());
+//!         Ok(todo.then(|| "TODO file exists".to_string()).into_iter().collect())
+//!     }
+//! }
+//!
+//! let mut checks = CheckSet::default();
+//! checks.add(Box::new(Todo));
+//! ```
+
+use crate::hosting::HostedRepo;
+use crate::report::{
+    conflicted_files, is_dirty, is_partial, operation_in_progress, upstream_remote,
+};
+use crate::{
+    common_dir, describe_ahead, fallback_upstream, head_state, lfs, open_repo, ref_files, refs,
+    PushDefault, RepoStatus,
+};
+use anyhow::{bail, Context, Result};
+use git2::{BranchType, Index, Repository};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// A single check, e.g. whether HEAD is ahead of its upstream.
+pub trait Check: Send + Sync {
+    /// Name used to select the check with `--checks`, e.g. `ahead`.
+    fn name(&self) -> &str;
+
+    /// Status of a repository for which this check finds something.
+    fn status(&self) -> RepoStatus {
+        RepoStatus::HasUnpushed
+    }
+
+    /// Runs the check and returns human-readable findings, empty if the repository passes.
+    ///
+    /// # Errors
+    /// Returns an error if git operations fail.
+    fn run(&self, repo: &Repository) -> Result<Vec<String>>;
+
+    /// Like [`Check::run`], comparing the checked-out branch as `comparison` says. A
+    /// [`CheckSet`] runs its checks through this; the default ignores `comparison`.
+    ///
+    /// # Errors
+    /// Returns an error if git operations fail.
+    fn run_comparing(&self, repo: &Repository, comparison: &Comparison) -> Result<Vec<String>> {
+        let _ = comparison;
+        self.run(repo)
+    }
+
+    /// Runs the check from the files in the git directory alone, for a repository whose
+    /// checked-out branch points at the same commit as its upstream. Returns `None` if
+    /// the check needs the opened repository, which is the default.
+    ///
+    /// Opening a repository costs more than checking a clean one, so a [`CheckSet`]
+    /// whose checks all answer from the files doesn't open it. `git_dir` holds `HEAD`
+    /// and the index, `common_dir` the refs, objects and config; they differ in linked
+    /// worktrees.
+    fn run_on_files(&self, _git_dir: &Path, _common_dir: &Path) -> Option<Vec<String>> {
+        None
+    }
+}
+
+/// Outcome of running a [`CheckSet`] against one repository.
+#[derive(Debug, PartialEq)]
+pub struct CheckResults {
+    pub status: RepoStatus,
+    /// Checked-out branch, `None` for detached or missing HEAD
+    pub branch: Option<String>,
+    /// Findings by check name, only for checks that found something
+    pub findings: BTreeMap<String, Vec<String>>,
+}
+
+/// What the checked-out branch is compared with, set on a [`CheckSet`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Comparison {
+    /// Revision compared against instead of the upstream or push remote, e.g.
+    /// `origin/main`
+    pub against: Option<String>,
+    /// How branches without a configured upstream are compared
+    pub push_default: PushDefault,
+}
+
+/// Ordered collection of checks.
+pub struct CheckSet {
+    checks: Vec<Box<dyn Check>>,
+    comparison: Comparison,
+    /// Working directories of bare repositories, by git directory
+    work_trees: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl Default for CheckSet {
+    /// The checks run by a plain scan: merge conflicts, unfinished operations, unpushed
+    /// commits, large files and running large file transfers.
+    fn default() -> Self {
+        Self {
+            checks: vec![
+                Box::new(Conflicts),
+                Box::new(Operation),
+                Box::new(Ahead),
+                Box::new(LargeFiles),
+                Box::new(Transfers),
+            ],
+            comparison: Comparison::default(),
+            work_trees: BTreeMap::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for CheckSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+impl CheckSet {
+    /// All built-in checks, in the order they are run by default.
+    #[must_use]
+    pub fn builtin() -> Vec<Box<dyn Check>> {
+        vec![
+            Box::new(Conflicts),
+            Box::new(Operation),
+            Box::new(Ahead),
+            Box::new(LargeFiles),
+            Box::new(Transfers),
+            Box::new(Notes),
+            Box::new(Refs),
+            Box::new(Dirty),
+            Box::new(Stashes),
+            Box::new(Tags),
+            Box::new(DefaultBranch),
+            Box::new(Shallow),
+            Box::new(OnHost),
+        ]
+    }
+
+    /// Selects built-in checks by name, keeping the given order.
+    ///
+    /// # Errors
+    /// Returns an error for unknown or duplicate names.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self> {
+        let mut set = Self {
+            checks: Vec::new(),
+            comparison: Comparison::default(),
+            work_trees: BTreeMap::new(),
+        };
+        let mut builtin = Self::builtin();
+        for name in names {
+            let name = name.as_ref();
+            if set.contains(name) {
+                bail!("Check {name} is selected twice");
+            }
+            let Some(index) = builtin.iter().position(|check| check.name() == name) else {
+                bail!(
+                    "Unknown check {name}, expected one of: {}",
+                    Self::builtin()
+                        .iter()
+                        .map(|check| check.name().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            };
+            set.checks.push(builtin.remove(index));
+        }
+        Ok(set)
+    }
+
+    /// Appends a check, replacing an existing one with the same name.
+    pub fn add(&mut self, check: Box<dyn Check>) {
+        self.checks
+            .retain(|existing| existing.name() != check.name());
+        self.checks.push(check);
+    }
+
+    /// Appends a built-in check unless it is already part of the set.
+    ///
+    /// # Errors
+    /// Returns an error if there is no built-in check with this name.
+    pub fn require(&mut self, name: &str) -> Result<()> {
+        if !self.contains(name) {
+            self.checks.extend(Self::from_names(&[name])?.checks);
+        }
+        Ok(())
+    }
+
+    /// Compares the checked-out branch against `revision`, e.g. `origin/main`, instead
+    /// of its upstream or push remote. `None` compares against the upstream.
+    #[must_use]
+    pub fn against(mut self, revision: Option<String>) -> Self {
+        self.comparison.against = revision;
+        self
+    }
+
+    /// Compares branches without a configured upstream as `push_default` says.
+    #[must_use]
+    pub fn push_default(mut self, push_default: PushDefault) -> Self {
+        self.comparison.push_default = push_default;
+        self
+    }
+
+    /// What the checked-out branch is compared with.
+    #[must_use]
+    pub fn comparison(&self) -> &Comparison {
+        &self.comparison
+    }
+
+    /// Checks the repository at `git_dir` with `work_tree` as its working directory, like
+    /// `git --git-dir=GIT_DIR --work-tree=WORK_TREE`. For bare repositories tracking
+    /// files elsewhere, e.g. dotfiles in `$HOME`.
+    #[must_use]
+    pub fn work_tree(mut self, git_dir: PathBuf, work_tree: PathBuf) -> Self {
+        self.work_trees.insert(git_dir, work_tree);
+        self
+    }
+
+    /// Opens the repository at `repo_path` with the working directory set by
+    /// [`CheckSet::work_tree`], if any.
+    pub(crate) fn open(&self, repo_path: &Path) -> Result<Repository> {
+        let repo = open_repo(repo_path)?;
+        if let Some(work_tree) = self.work_trees.get(repo_path) {
+            repo.set_workdir(work_tree, false).context(format!(
+                "Failed to use {} as working directory",
+                work_tree.display()
+            ))?;
+        }
+        Ok(repo)
+    }
+
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.checks.iter().any(|check| check.name() == name)
+    }
+
+    #[must_use]
+    pub fn names(&self) -> Vec<&str> {
+        self.checks.iter().map(|check| check.name()).collect()
+    }
+
+    /// Determines the status of the repository at `repo_path`, stopping at the first
+    /// check that finds something.
+    ///
+    /// # Errors
+    /// Returns an error if the repository cannot be opened or if git operations fail.
+    pub fn status(&self, repo_path: &Path) -> Result<RepoStatus> {
+        Ok(self.check(repo_path)?.status)
+    }
+
+    /// Like [`CheckSet::status`], also returning the branch and the findings of the
+    /// first check that found something.
+    ///
+    /// # Errors
+    /// Returns an error if the repository cannot be opened or if git operations fail.
+    pub fn check(&self, repo_path: &Path) -> Result<CheckResults> {
+        if let Some(results) = self.check_files(repo_path) {
+            return Ok(results);
+        }
+        let repo = self.open(repo_path)?;
+        self.run(&repo, true)
+    }
+
+    /// Like [`CheckSet::check`] without opening the repository, `None` unless the
+    /// checked-out branch is in sync with its upstream and all checks can tell from the
+    /// files in the git directory.
+    fn check_files(&self, repo_path: &Path) -> Option<CheckResults> {
+        if self.comparison.against.is_some() || self.work_trees.contains_key(repo_path) {
+            return None;
+        }
+        let (git_dir, common_dir) = ref_files::git_dirs(repo_path)?;
+        let branch = ref_files::branch_in_sync(&git_dir, &common_dir)?;
+        let mut results = CheckResults {
+            status: RepoStatus::Clean,
+            branch: Some(branch),
+            findings: BTreeMap::new(),
+        };
+        for check in &self.checks {
+            let findings = check.run_on_files(&git_dir, &common_dir)?;
+            if !findings.is_empty() {
+                results.status = check.status();
+                results.findings.insert(check.name().to_string(), findings);
+                break;
+            }
+        }
+        Some(results)
+    }
+
+    /// Runs all checks against an open repository.
+    ///
+    /// # Errors
+    /// Returns an error if git operations fail.
+    pub fn run_all(&self, repo: &Repository) -> Result<CheckResults> {
+        self.run(repo, false)
+    }
+
+    fn run(&self, repo: &Repository, stop_at_first: bool) -> Result<CheckResults> {
+        let state = head_state(repo, &self.comparison)?;
+        let mut results = CheckResults {
+            status: RepoStatus::Clean,
+            branch: state.branch,
+            findings: BTreeMap::new(),
+        };
+        // Without HEAD there is nothing to compare, so checks would only add noise
+        if state.status == RepoStatus::MissingHead {
+            results.status = RepoStatus::MissingHead;
+            return Ok(results);
+        }
+
+        for check in &self.checks {
+            let findings = check
+                .run_comparing(repo, &self.comparison)
+                .with_context(|| format!("Check {} failed", check.name()))?;
+            if findings.is_empty() {
+                continue;
+            }
+            if results.status == RepoStatus::Clean {
+                results.status = check.status();
+            }
+            results.findings.insert(check.name().to_string(), findings);
+            if stop_at_first {
+                break;
+            }
+        }
+        Ok(results)
+    }
+}
+
+/// Unresolved merge conflicts in the index. Resolving them by hand is work that exists
+/// nowhere else, and it may be half done.
+struct Conflicts;
+
+impl Check for Conflicts {
+    fn name(&self) -> &'static str {
+        "conflicts"
+    }
+
+    fn status(&self) -> RepoStatus {
+        RepoStatus::Conflicted
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        let count = conflicted_files(repo)?;
+        Ok(if count > 0 {
+            vec![format!("{count} conflicted file(s)")]
+        } else {
+            Vec::new()
+        })
+    }
+
+    fn run_on_files(&self, git_dir: &Path, _common_dir: &Path) -> Option<Vec<String>> {
+        let index = Index::open(&git_dir.join("index")).ok()?;
+        (!index.has_conflicts()).then(Vec::new)
+    }
+}
+
+/// A rebase, merge or similar operation that was started but not finished. HEAD may
+/// look clean meanwhile, e.g. detached on the commit a rebase stopped at.
+struct Operation;
+
+impl Check for Operation {
+    fn name(&self) -> &'static str {
+        "operation"
+    }
+
+    fn status(&self) -> RepoStatus {
+        RepoStatus::OperationInProgress
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        Ok(operation_in_progress(repo)
+            .map(|operation| format!("{operation} in progress"))
+            .into_iter()
+            .collect())
+    }
+
+    /// Without any of the state files an operation leaves, there is none. Which one is
+    /// in progress is left to git2.
+    fn run_on_files(&self, git_dir: &Path, _common_dir: &Path) -> Option<Vec<String>> {
+        const STATE_FILES: [&str; 5] = [
+            "rebase-apply",
+            "rebase-merge",
+            "MERGE_HEAD",
+            "REVERT_HEAD",
+            "CHERRY_PICK_HEAD",
+        ];
+        (!STATE_FILES.iter().any(|file| git_dir.join(file).exists())).then(Vec::new)
+    }
+}
+
+/// The checked-out branch has commits that are not on its upstream, or no upstream.
+struct Ahead;
+
+impl Check for Ahead {
+    fn name(&self) -> &'static str {
+        "ahead"
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        self.run_comparing(repo, &Comparison::default())
+    }
+
+    fn run_comparing(&self, repo: &Repository, comparison: &Comparison) -> Result<Vec<String>> {
+        let state = head_state(repo, comparison)?;
+        if state.status != RepoStatus::HasUnpushed {
+            return Ok(Vec::new());
+        }
+        let branch = state.branch.as_deref().unwrap_or("-");
+        Ok(vec![
+            match (&state.push_remote, state.ahead, state.push_ahead) {
+                (Some(remote), _, Some(ahead)) => {
+                    format!("Branch {branch} is {ahead} commit(s) ahead of {remote}/{branch}")
+                }
+                (Some(remote), _, None) => format!("Branch {branch} was never pushed to {remote}"),
+                (None, ahead, _) => describe_ahead(branch, ahead, comparison),
+            },
+        ])
+    }
+
+    fn run_on_files(&self, _git_dir: &Path, _common_dir: &Path) -> Option<Vec<String>> {
+        // Only asked when the branch is in sync
+        Some(Vec::new())
+    }
+}
+
+/// git-lfs objects or git-annex content that may not be on any remote.
+struct LargeFiles;
+
+impl Check for LargeFiles {
+    fn name(&self) -> &'static str {
+        "lfs"
+    }
+
+    fn status(&self) -> RepoStatus {
+        RepoStatus::HasUnpushedLfs
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        lfs::unpushed_large_files(repo)
+    }
+
+    fn run_on_files(&self, _git_dir: &Path, common_dir: &Path) -> Option<Vec<String>> {
+        (!lfs::has_large_files(common_dir)).then(Vec::new)
+    }
+}
+
+/// git-lfs or git-annex transfers that are still running, e.g. a half-finished upload
+/// of content whose pointers are already pushed.
+struct Transfers;
+
+impl Check for Transfers {
+    fn name(&self) -> &'static str {
+        "transfers"
+    }
+
+    fn status(&self) -> RepoStatus {
+        RepoStatus::TransferInProgress
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        Ok(lfs::transfers_in_progress(&common_dir(repo)))
+    }
+
+    fn run_on_files(&self, _git_dir: &Path, common_dir: &Path) -> Option<Vec<String>> {
+        Some(lfs::transfers_in_progress(common_dir))
+    }
+}
+
+/// Local git notes that are not on any remote.
+struct Notes;
+
+impl Check for Notes {
+    fn name(&self) -> &'static str {
+        "notes"
+    }
+
+    fn status(&self) -> RepoStatus {
+        RepoStatus::HasUnpushedNotes
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        refs::unpushed_notes(repo)
+    }
+}
+
+/// Local refs outside of `refs/heads` that no remote-tracking ref contains.
+struct Refs;
+
+impl Check for Refs {
+    fn name(&self) -> &'static str {
+        "refs"
+    }
+
+    fn status(&self) -> RepoStatus {
+        RepoStatus::HasUnpushedRefs
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        refs::unpushed_refs(repo)
+    }
+}
+
+/// Uncommitted or untracked changes in the working tree.
+struct Dirty;
+
+impl Check for Dirty {
+    fn name(&self) -> &'static str {
+        "dirty"
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        Ok(if is_dirty(repo)? {
+            vec!["Working tree has uncommitted changes".to_string()]
+        } else {
+            Vec::new()
+        })
+    }
+}
+
+/// Stashed changes, which are never pushed.
+struct Stashes;
+
+impl Check for Stashes {
+    fn name(&self) -> &'static str {
+        "stashes"
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        let count = repo.reflog("refs/stash").map_or(0, |reflog| reflog.len());
+        Ok(if count > 0 {
+            vec![format!("{count} stash(es)")]
+        } else {
+            Vec::new()
+        })
+    }
+}
+
+/// Tags pointing at commits that no remote-tracking branch contains.
+struct Tags;
+
+impl Check for Tags {
+    fn name(&self) -> &'static str {
+        "tags"
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        crate::audit::unpushed_tags(repo)
+    }
+}
+
+/// The checked-out branch is not the default branch of its remote, e.g. a clone left
+/// on a feature branch that was merged long ago.
+///
+/// The default branch is taken from `refs/remotes/<remote>/HEAD`, which `git clone`
+/// and `git remote set-head` create. Repositories without it are not flagged.
+struct DefaultBranch;
+
+impl Check for DefaultBranch {
+    fn name(&self) -> &'static str {
+        "default-branch"
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        self.run_comparing(repo, &Comparison::default())
+    }
+
+    fn run_comparing(&self, repo: &Repository, comparison: &Comparison) -> Result<Vec<String>> {
+        let Some(branch) = head_state(repo, comparison)?.branch else {
+            return Ok(Vec::new());
+        };
+        let remote =
+            upstream_remote(repo, &branch, comparison).unwrap_or_else(|| "origin".to_string());
+        let remote_prefix = format!("refs/remotes/{remote}/");
+        let default = repo
+            .find_reference(&format!("{remote_prefix}HEAD"))
+            .ok()
+            .and_then(|head| {
+                head.symbolic_target()?
+                    .strip_prefix(&remote_prefix)
+                    .map(str::to_string)
+            });
+        Ok(match default {
+            Some(default) if default != branch => vec![format!(
+                "Branch {branch} is not the default branch {default} of {remote}"
+            )],
+            _ => Vec::new(),
+        })
+    }
+}
+
+/// Shallow or partial clones, which cannot serve as a backup of the history.
+struct Shallow;
+
+impl Check for Shallow {
+    fn name(&self) -> &'static str {
+        "shallow"
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        let mut findings = Vec::new();
+        if repo.is_shallow() {
+            findings.push("Shallow clone, history is incomplete".to_string());
+        }
+        if is_partial(repo) {
+            findings.push("Partial clone, objects are only on the promisor remote".to_string());
+        }
+        Ok(findings)
+    }
+}
+
+/// An upstream tip that GitHub or GitLab doesn't have, e.g. after a force-push.
+///
+/// Asks the hosting service's API, see [`crate::hosting`]. Other remotes pass.
+struct OnHost;
+
+impl Check for OnHost {
+    fn name(&self) -> &'static str {
+        "on-host"
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        self.run_comparing(repo, &Comparison::default())
+    }
+
+    fn run_comparing(&self, repo: &Repository, comparison: &Comparison) -> Result<Vec<String>> {
+        let Some(branch_name) = head_state(repo, comparison)?.branch else {
+            return Ok(Vec::new());
+        };
+        let Some(upstream) = repo
+            .find_branch(&branch_name, BranchType::Local)?
+            .upstream()
+            .ok()
+            .or_else(|| fallback_upstream(repo, &branch_name, comparison))
+        else {
+            return Ok(Vec::new());
+        };
+        let Some(remote) = upstream_remote(repo, &branch_name, comparison) else {
+            return Ok(Vec::new());
+        };
+        let Some(hosted) = repo
+            .find_remote(&remote)
+            .ok()
+            .and_then(|remote| HostedRepo::from_url(remote.url()?))
+        else {
+            return Ok(Vec::new());
+        };
+        let Some(oid) = upstream.get().target() else {
+            return Ok(Vec::new());
+        };
+        if hosted.has_commit(&oid.to_string())? {
+            return Ok(Vec::new());
+        }
+        Ok(vec![format!(
+            "Upstream {} at {:.7} does not exist on {}",
+            upstream.name()?.unwrap_or("-"),
+            oid,
+            hosted.host()
+        )])
+    }
+}
+
+/// A user-supplied script, e.g. to flag `.env` files that are not backed up.
+///
+/// The script runs in the working directory with the repository path as its only
+/// argument and a JSON object with `path`, `branch` and `ahead` on stdin. A non-zero
+/// exit status flags the repository, with the script's output (or the exit status)
+/// as the finding.
+#[derive(Debug)]
+pub struct ScriptCheck {
+    name: String,
+    script: PathBuf,
+}
+
+impl ScriptCheck {
+    /// Creates a check named `hook:<file name>` that runs `script`.
+    ///
+    /// Relative paths like `./check.sh` are resolved against the current directory,
+    /// bare names are looked up in `PATH`.
+    #[must_use]
+    pub fn new(script: &Path) -> Self {
+        let file_name = script.file_name().map_or_else(
+            || script.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        Self {
+            name: format!("hook:{file_name}"),
+            script: if script.components().count() > 1 {
+                std::path::absolute(script).unwrap_or_else(|_| script.to_path_buf())
+            } else {
+                script.to_path_buf()
+            },
+        }
+    }
+}
+
+impl Check for ScriptCheck {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&self, repo: &Repository) -> Result<Vec<String>> {
+        self.run_comparing(repo, &Comparison::default())
+    }
+
+    fn run_comparing(&self, repo: &Repository, comparison: &Comparison) -> Result<Vec<String>> {
+        let path = repo.workdir().unwrap_or_else(|| repo.path());
+        let state = head_state(repo, comparison)?;
+        let context = json!({
+            "path": path,
+            "branch": state.branch,
+            "ahead": state.ahead,
+        });
+
+        let mut child = Command::new(&self.script)
+            .arg(path)
+            .current_dir(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .context(format!("Failed to run {}", self.script.display()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // Scripts that don't read the context may exit before it is written
+            let _ = writeln!(stdin, "{context}");
+        }
+        let output = child
+            .wait_with_output()
+            .context(format!("Failed to run {}", self.script.display()))?;
+        if output.status.success() {
+            return Ok(Vec::new());
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<String> = stdout
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+        if lines.is_empty() {
+            return Ok(vec![format!("{} failed with {}", self.name, output.status)]);
+        }
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn git(repo_path: &Path, args: &[&str]) {
+        Command::new("git")
+            .args(args)
+            .current_dir(repo_path)
+            .output()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_check_in_sync_without_opening() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        git(
+            temp_dir.path(),
+            &["init", "--bare", "-b", "main", "remote.git"],
+        );
+        git(temp_dir.path(), &["clone", "-q", "remote.git", "repo"]);
+        git(
+            &repo_path,
+            &[
+                "-c",
+                "user.name=Test User",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "-q",
+                "--allow-empty",
+                "-m",
+                "Initial",
+            ],
+        );
+        git(&repo_path, &["push", "-q", "origin", "main"]);
+        let checks = CheckSet::default();
+        assert_eq!(checks.status(&repo_path).unwrap(), RepoStatus::Clean);
+
+        // git2 cannot open the repository without a refs directory, the ref files still
+        // tell from packed-refs
+        git(&repo_path, &["pack-refs", "--all"]);
+        std::fs::remove_dir_all(repo_path.join(".git/refs")).unwrap();
+        let results = checks.check(&repo_path).unwrap();
+        assert_eq!(results.status, RepoStatus::Clean);
+        assert_eq!(results.branch.as_deref(), Some("main"));
+        assert!(CheckSet::from_names(&["notes"])
+            .unwrap()
+            .check(&repo_path)
+            .is_err());
+
+        // An operation in progress needs the repository to tell which
+        std::fs::write(repo_path.join(".git/MERGE_HEAD"), "").unwrap();
+        assert!(checks.check(&repo_path).is_err());
+        std::fs::remove_file(repo_path.join(".git/MERGE_HEAD")).unwrap();
+
+        // Nor are the ref files trusted without the commit they point to
+        std::fs::create_dir(repo_path.join(".git/refs")).unwrap();
+        assert_eq!(checks.status(&repo_path).unwrap(), RepoStatus::Clean);
+        std::fs::rename(
+            repo_path.join(".git/objects"),
+            temp_dir.path().join("objects"),
+        )
+        .unwrap();
+        assert!(checks.check(&repo_path).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_script_check() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        Command::new("git")
+            .args(["init"])
+            .arg(&repo_path)
+            .output()
+            .unwrap();
+        let script = temp_dir.path().join("env-check.sh");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\ngrep -q '\"ahead\":null' || exit 2\n[ -e .env ] && echo 'Found .env' && exit 1\nexit 0\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let check = ScriptCheck::new(&script);
+        assert_eq!(check.name(), "hook:env-check.sh");
+        let repo = Repository::open(&repo_path).unwrap();
+        assert!(check.run(&repo).unwrap().is_empty());
+        std::fs::write(repo_path.join(".env"), "SECRET=1").unwrap();
+        assert_eq!(check.run(&repo).unwrap(), vec!["Found .env".to_string()]);
+    }
+
+    #[test]
+    fn test_from_names() {
+        let checks = CheckSet::from_names(&["dirty", "ahead"]).unwrap();
+        assert_eq!(checks.names(), vec!["dirty", "ahead"]);
+        assert!(CheckSet::from_names(&["nope"]).is_err());
+        assert!(CheckSet::from_names(&["ahead", "ahead"]).is_err());
+    }
+
+    #[test]
+    fn test_default_branch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        Command::new("git")
+            .args(["init"])
+            .arg(&repo_path)
+            .output()
+            .unwrap();
+        git(&repo_path, &["config", "user.name", "Test User"]);
+        git(&repo_path, &["config", "user.email", "test@example.com"]);
+        git(
+            &repo_path,
+            &["commit", "--allow-empty", "-m", "Initial commit"],
+        );
+        git(
+            &repo_path,
+            &["update-ref", "refs/remotes/origin/main", "HEAD"],
+        );
+        git(
+            &repo_path,
+            &[
+                "symbolic-ref",
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/main",
+            ],
+        );
+
+        let repo = Repository::open(&repo_path).unwrap();
+        assert!(DefaultBranch.run(&repo).unwrap().is_empty());
+        git(&repo_path, &["checkout", "-b", "feature"]);
+        assert_eq!(
+            DefaultBranch.run(&repo).unwrap(),
+            vec!["Branch feature is not the default branch main of origin".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_operation() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        Command::new("git")
+            .args(["init"])
+            .arg(&repo_path)
+            .output()
+            .unwrap();
+        git(&repo_path, &["config", "user.name", "Test User"]);
+        git(&repo_path, &["config", "user.email", "test@example.com"]);
+        git(&repo_path, &["commit", "--allow-empty", "-m", "First"]);
+        git(&repo_path, &["commit", "--allow-empty", "-m", "Second"]);
+        let repo = Repository::open(&repo_path).unwrap();
+        assert!(Operation.run(&repo).unwrap().is_empty());
+
+        // Stops with a detached HEAD, which is clean on its own
+        git(&repo_path, &["rebase", "--exec", "false", "HEAD~1"]);
+        let checks = CheckSet::default();
+        let results = checks.run_all(&repo).unwrap();
+        assert_eq!(results.status, RepoStatus::OperationInProgress);
+        assert_eq!(results.branch, None);
+        assert_eq!(
+            results.findings["operation"],
+            vec!["rebase in progress".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_status_of_first_failing_check() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        Command::new("git")
+            .args(["init"])
+            .arg(&repo_path)
+            .output()
+            .unwrap();
+        git(&repo_path, &["config", "user.name", "Test User"]);
+        git(&repo_path, &["config", "user.email", "test@example.com"]);
+        git(
+            &repo_path,
+            &["commit", "--allow-empty", "-m", "Initial commit"],
+        );
+        std::fs::write(repo_path.join("untracked.txt"), "content").unwrap();
+
+        let checks = CheckSet::from_names(&["dirty", "ahead"]).unwrap();
+        let repo = Repository::open(&repo_path).unwrap();
+        let results = checks.run_all(&repo).unwrap();
+        assert_eq!(results.status, RepoStatus::HasUnpushed);
+        assert_eq!(
+            results.findings.keys().collect::<Vec<_>>(),
+            vec!["ahead", "dirty"]
+        );
+        assert_eq!(
+            CheckSet::from_names(&["stashes"])
+                .unwrap()
+                .status(&repo_path)
+                .unwrap(),
+            RepoStatus::Clean
+        );
+    }
+}
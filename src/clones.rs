@@ -0,0 +1,164 @@
+//! Comparing clones of the same project, e.g. before consolidating duplicate clones.
+//!
+//! Clones belong to the same project if they have the same [`identity`]. A branch of a
+//! clone has commits the others lack if its tip is in none of the other clones. Each
+//! clone has objects of its own, so commits are looked up by id.
+
+use crate::open_repo;
+use crate::report::identity;
+use anyhow::{Context, Result};
+use git2::{BranchType, Oid, Repository};
+use std::path::{Path, PathBuf};
+
+/// A project cloned in several places.
+#[derive(Debug)]
+pub struct Project {
+    pub identity: String,
+    /// Sorted by path
+    pub clones: Vec<ProjectClone>,
+}
+
+impl Project {
+    /// Whether a clone has commits that the others lack.
+    #[must_use]
+    pub fn diverged(&self) -> bool {
+        self.clones
+            .iter()
+            .any(|clone| !clone.unique_branches.is_empty())
+    }
+}
+
+/// One clone of a [`Project`].
+#[derive(Debug)]
+pub struct ProjectClone {
+    pub path: PathBuf,
+    /// Local branches with commits that no other clone has, sorted
+    pub unique_branches: Vec<String>,
+}
+
+/// The identity of the project the repository at `repo_path` is a clone of, `None` if
+/// it has no `origin`.
+///
+/// # Errors
+/// Returns an error if the repository cannot be opened.
+pub fn identify(repo_path: &Path) -> Result<Option<String>> {
+    Ok(identity(&open_repo(repo_path)?, None))
+}
+
+/// Compares the clones at `paths` of the project `identity`.
+///
+/// # Errors
+/// Returns an error if a repository cannot be opened or its branches cannot be read.
+pub fn compare(identity: String, mut paths: Vec<PathBuf>) -> Result<Project> {
+    paths.sort();
+    let repos = paths
+        .iter()
+        .map(|path| open_repo(path))
+        .collect::<Result<Vec<_>>>()?;
+    let clones = paths
+        .iter()
+        .zip(&repos)
+        .enumerate()
+        .map(|(i, (path, repo))| {
+            let mut unique_branches = Vec::new();
+            for (name, tip) in branch_tips(repo)? {
+                let elsewhere = repos
+                    .iter()
+                    .enumerate()
+                    .any(|(j, other)| j != i && other.find_commit(tip).is_ok());
+                if !elsewhere {
+                    unique_branches.push(name);
+                }
+            }
+            unique_branches.sort();
+            Ok(ProjectClone {
+                path: path.clone(),
+                unique_branches,
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(Project { identity, clones })
+}
+
+/// Names and tip commits of the local branches.
+fn branch_tips(repo: &Repository) -> Result<Vec<(String, Oid)>> {
+    let mut tips = Vec::new();
+    for branch in repo
+        .branches(Some(BranchType::Local))
+        .context("Failed to list branches")?
+    {
+        let (branch, _) = branch?;
+        if let (Some(name), Some(tip)) = (branch.name()?, branch.get().target()) {
+            tips.push((name.to_string(), tip));
+        }
+    }
+    Ok(tips)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+
+    fn commit(repo: &Repository, message: &str) -> Oid {
+        let signature = Signature::now("Test User", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compare() {
+        let dir = tempfile::tempdir().unwrap();
+        let init = |name: &str| {
+            let repo = Repository::init_opts(
+                dir.path().join(name),
+                git2::RepositoryInitOptions::new().initial_head("main"),
+            )
+            .unwrap();
+            repo.remote("origin", "git@github.com:acme/app.git")
+                .unwrap();
+            repo
+        };
+        let a = init("a");
+        let b = init("b");
+        let c = init("c");
+        // b and c have the same commit, as if both were cloned from a
+        let oid = commit(&a, "Initial");
+        let odb = a.odb().unwrap();
+        for repo in [&b, &c] {
+            let target = repo.odb().unwrap();
+            for id in [oid, a.find_commit(oid).unwrap().tree_id()] {
+                let object = odb.read(id).unwrap();
+                target.write(object.kind(), object.data()).unwrap();
+            }
+            repo.reference("refs/heads/main", oid, false, "").unwrap();
+        }
+        commit(&b, "Only in b");
+
+        assert_eq!(
+            identify(&dir.path().join("a")).unwrap().as_deref(),
+            Some("github.com/acme/app")
+        );
+        let paths = ["c", "b", "a"].map(|name| dir.path().join(name)).to_vec();
+        let project = compare("github.com/acme/app".to_string(), paths).unwrap();
+        assert!(project.diverged());
+        let unique: Vec<_> = project
+            .clones
+            .iter()
+            .map(|clone| clone.unique_branches.as_slice())
+            .collect();
+        assert_eq!(unique, [&[][..], &["main".to_string()][..], &[][..]]);
+    }
+}
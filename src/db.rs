@@ -0,0 +1,200 @@
+use anyhow::{Context, Result};
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Schema migrations, applied in order. `PRAGMA user_version` tracks how many have run.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE registry (
+        path TEXT PRIMARY KEY
+    );
+    CREATE TABLE scans (
+        id INTEGER PRIMARY KEY,
+        time INTEGER NOT NULL,
+        root TEXT NOT NULL
+    );
+    CREATE TABLE scan_repos (
+        scan_id INTEGER NOT NULL REFERENCES scans(id) ON DELETE CASCADE,
+        path TEXT NOT NULL,
+        PRIMARY KEY (scan_id, path)
+    );
+    CREATE INDEX scans_root ON scans(root, id);
+",
+    "
+    CREATE TABLE acks (
+        path TEXT PRIMARY KEY,
+        head TEXT NOT NULL,
+        until INTEGER,
+        reason TEXT
+    );
+",
+    // Per-day summary that outlives the pruned scans, backfilled from the history
+    "
+    CREATE TABLE scan_days (
+        day TEXT NOT NULL,
+        root TEXT NOT NULL,
+        nasty INTEGER NOT NULL,
+        PRIMARY KEY (day, root)
+    );
+    INSERT OR REPLACE INTO scan_days (day, root, nasty)
+        SELECT date(time, 'unixepoch'), root,
+            (SELECT COUNT(*) FROM scan_repos WHERE scan_id = scans.id)
+        FROM scans ORDER BY id;
+",
+    // Time of the last scan covered by `digest --since last-run`, per root
+    "
+    CREATE TABLE digests (
+        root TEXT PRIMARY KEY,
+        time INTEGER NOT NULL
+    );
+",
+    "
+    CREATE TABLE labels (
+        path TEXT NOT NULL REFERENCES registry(path) ON DELETE CASCADE,
+        label TEXT NOT NULL,
+        PRIMARY KEY (path, label)
+    );
+",
+    // Progress of running scans for --resume, paths relative to the search path
+    "
+    CREATE TABLE checkpoint_repos (
+        root TEXT NOT NULL,
+        path TEXT NOT NULL,
+        nasty INTEGER NOT NULL,
+        PRIMARY KEY (root, path)
+    );
+    CREATE TABLE checkpoint_subtrees (
+        root TEXT NOT NULL,
+        path TEXT NOT NULL,
+        PRIMARY KEY (root, path)
+    );
+",
+];
+
+/// Embedded database holding the registry, scan history and acknowledgements.
+#[derive(Debug)]
+pub struct Db {
+    conn: Connection,
+}
+
+/// Result of an ad-hoc query: column names and rows of rendered values.
+#[derive(Debug, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Db {
+    /// Location of the database in the data directory.
+    ///
+    /// # Errors
+    /// Returns an error if the data directory cannot be determined.
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(crate::data_dir()?.join("nasty-boii.db"))
+    }
+
+    /// Opens the database at the default location.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be opened or migrated.
+    pub fn open_default() -> Result<Self> {
+        Self::open(&Self::default_path()?)
+    }
+
+    /// Opens or creates the database at `path` and applies pending migrations.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be opened or migrated.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .context(format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let conn = Connection::open(path)
+            .context(format!("Failed to open database: {}", path.display()))?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        // Several nasty-boii processes may use the database at the same time
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+
+        let db = Self { conn };
+        db.migrate()?;
+        Ok(db)
+    }
+
+    fn migrate(&self) -> Result<()> {
+        let version: usize = self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = self.conn.unchecked_transaction()?;
+            tx.execute_batch(migration)
+                .context(format!("Failed to apply database migration {}", i + 1))?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn conn(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Runs arbitrary SQL, e.g. for ad-hoc reports over the scan history.
+    ///
+    /// # Errors
+    /// Returns an error if the statement is invalid or fails.
+    pub fn query(&self, sql: &str) -> Result<QueryResult> {
+        let mut stmt = self.conn.prepare(sql).context("Failed to prepare query")?;
+        let columns: Vec<String> = stmt
+            .column_names()
+            .iter()
+            .map(|c| (*c).to_string())
+            .collect();
+
+        let mut rows = Vec::new();
+        let mut query = stmt.query([])?;
+        while let Some(row) = query.next()? {
+            let mut values = Vec::with_capacity(columns.len());
+            for i in 0..columns.len() {
+                values.push(match row.get_ref(i)? {
+                    ValueRef::Null => String::new(),
+                    ValueRef::Integer(n) => n.to_string(),
+                    ValueRef::Real(f) => f.to_string(),
+                    ValueRef::Text(t) => String::from_utf8_lossy(t).into_owned(),
+                    ValueRef::Blob(b) => format!("<{} bytes>", b.len()),
+                });
+            }
+            rows.push(values);
+        }
+        Ok(QueryResult { columns, rows })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_migrates_once() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("data").join("nasty-boii.db");
+
+        let db = Db::open(&path).unwrap();
+        db.query("INSERT INTO registry (path) VALUES ('/src/a')")
+            .unwrap();
+        drop(db);
+
+        // Reopening must not re-run migrations or lose data
+        let db = Db::open(&path).unwrap();
+        let result = db.query("SELECT path FROM registry").unwrap();
+        assert_eq!(
+            result,
+            QueryResult {
+                columns: vec!["path".to_string()],
+                rows: vec![vec!["/src/a".to_string()]],
+            }
+        );
+    }
+}
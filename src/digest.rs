@@ -0,0 +1,225 @@
+//! Short summaries of the scan history for mail or chat, e.g. sent from cron.
+//!
+//! A digest compares the latest scan of a directory against the last scan covered by
+//! the previous digest, or against the last scan before a given time.
+
+use crate::db::Db;
+use crate::history::ScanRecord;
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use rusqlite::{params, OptionalExtension};
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// Changes between two scans, and how long the remaining repos have been nasty.
+#[derive(Debug, PartialEq)]
+pub struct Digest {
+    pub root: String,
+    /// Time of the scan compared against, `None` if there is no earlier scan
+    pub from: Option<i64>,
+    /// Time of the latest scan
+    pub to: i64,
+    pub new: Vec<PathBuf>,
+    pub fixed: Vec<PathBuf>,
+    pub still_nasty: Vec<StillNasty>,
+}
+
+/// A repository that was nasty in both scans.
+#[derive(Debug, PartialEq)]
+pub struct StillNasty {
+    pub path: PathBuf,
+    /// Time of the first scan in an unbroken run of scans finding it nasty
+    pub since: i64,
+    /// Whether the run goes back to the oldest scan kept, so it may be longer
+    pub at_least: bool,
+}
+
+impl Digest {
+    /// Digest of the last of `records`, all of the same root and oldest first, against
+    /// the last one at or before `since`, or against the one before it without `since`.
+    /// `None` without records.
+    #[must_use]
+    pub fn new(records: &[ScanRecord], since: Option<i64>) -> Option<Self> {
+        let (latest, earlier) = records.split_last()?;
+        let baseline = match since {
+            Some(since) => records.iter().rev().find(|record| record.time <= since),
+            None => earlier.last(),
+        };
+        let empty = BTreeSet::new();
+        let before = baseline.map_or(&empty, |record| &record.repos);
+
+        let still_nasty = latest
+            .repos
+            .intersection(before)
+            .map(|path| {
+                let run = records
+                    .iter()
+                    .rev()
+                    .take_while(|record| record.repos.contains(path))
+                    .count();
+                StillNasty {
+                    path: path.clone(),
+                    since: records[records.len() - run].time,
+                    at_least: run == records.len(),
+                }
+            })
+            .collect();
+        Some(Self {
+            root: latest.root.clone(),
+            from: baseline.map(|record| record.time),
+            to: latest.time,
+            new: latest.repos.difference(before).cloned().collect(),
+            fixed: before.difference(&latest.repos).cloned().collect(),
+            still_nasty,
+        })
+    }
+}
+
+impl Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.from {
+            Some(from) => writeln!(
+                f,
+                "Nasty repos in {} from {} to {}",
+                self.root,
+                format_time(from),
+                format_time(self.to)
+            )?,
+            None => writeln!(
+                f,
+                "Nasty repos in {} at {}",
+                self.root,
+                format_time(self.to)
+            )?,
+        }
+        if self.new.is_empty() && self.fixed.is_empty() && self.still_nasty.is_empty() {
+            return writeln!(f, "\nAll clean.");
+        }
+        for (heading, paths) in [("New", &self.new), ("Fixed", &self.fixed)] {
+            if !paths.is_empty() {
+                writeln!(f, "\n{heading} ({}):", paths.len())?;
+                for path in paths {
+                    writeln!(f, "  {}", path.display())?;
+                }
+            }
+        }
+        if !self.still_nasty.is_empty() {
+            writeln!(f, "\nStill nasty ({}):", self.still_nasty.len())?;
+            for repo in &self.still_nasty {
+                let days = (self.to - repo.since) / DAY;
+                let age = match (days, repo.at_least) {
+                    (0, _) => "less than a day".to_string(),
+                    (1, false) => "1 day".to_string(),
+                    (days, false) => format!("{days} days"),
+                    (days, true) => format!("at least {days} day(s)"),
+                };
+                writeln!(f, "  {} ({age})", repo.path.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format_time(time: i64) -> String {
+    DateTime::from_timestamp(time, 0).map_or_else(
+        || time.to_string(),
+        |time| time.format("%Y-%m-%d %H:%M UTC").to_string(),
+    )
+}
+
+/// Which scans previous digests covered, stored in the database.
+#[derive(Debug)]
+pub struct Digests<'a> {
+    db: &'a Db,
+}
+
+impl<'a> Digests<'a> {
+    #[must_use]
+    pub fn new(db: &'a Db) -> Self {
+        Self { db }
+    }
+
+    /// Time of the latest scan of `root` covered by the previous digest.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be read.
+    pub fn last_run(&self, root: &str) -> Result<Option<i64>> {
+        self.db
+            .conn()
+            .query_row("SELECT time FROM digests WHERE root = ?1", [root], |row| {
+                row.get(0)
+            })
+            .optional()
+            .context("Failed to read last digest")
+    }
+
+    /// Remembers that a digest covered the scans of `root` up to `time`.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be written.
+    pub fn set_last_run(&self, root: &str, time: i64) -> Result<()> {
+        self.db
+            .conn()
+            .execute(
+                "INSERT OR REPLACE INTO digests (root, time) VALUES (?1, ?2)",
+                params![root, time],
+            )
+            .context("Failed to record digest")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(time: i64, repos: &[&str]) -> ScanRecord {
+        ScanRecord {
+            time,
+            root: "/src".to_string(),
+            repos: repos.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn test_digest() {
+        let records = [
+            record(0, &["/src/a"]),
+            record(DAY, &["/src/a", "/src/b", "/src/c"]),
+            record(3 * DAY, &["/src/a", "/src/c", "/src/d"]),
+        ];
+        let digest = Digest::new(&records, Some(DAY)).unwrap();
+        assert_eq!(digest.new, vec![PathBuf::from("/src/d")]);
+        assert_eq!(digest.fixed, vec![PathBuf::from("/src/b")]);
+        assert_eq!(
+            digest.to_string(),
+            "Nasty repos in /src from 1970-01-02 00:00 UTC to 1970-01-04 00:00 UTC\n\
+             \nNew (1):\n  /src/d\n\
+             \nFixed (1):\n  /src/b\n\
+             \nStill nasty (2):\n  /src/a (at least 3 day(s))\n  /src/c (2 days)\n"
+        );
+    }
+
+    #[test]
+    fn test_digest_without_baseline() {
+        let digest = Digest::new(&[record(0, &[])], None).unwrap();
+        assert_eq!(digest.from, None);
+        assert!(digest.to_string().ends_with("\nAll clean.\n"));
+        assert_eq!(Digest::new(&[], None), None);
+    }
+
+    #[test]
+    fn test_last_run() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Db::open(&temp_dir.path().join("nasty-boii.db")).unwrap();
+        let digests = Digests::new(&db);
+
+        assert_eq!(digests.last_run("/src").unwrap(), None);
+        digests.set_last_run("/src", 1).unwrap();
+        digests.set_last_run("/src", 2).unwrap();
+        assert_eq!(digests.last_run("/src").unwrap(), Some(2));
+    }
+}
@@ -0,0 +1,139 @@
+//! Recognizing git repositories on disk.
+//!
+//! A repository is found through its working directory, which holds either a `.git`
+//! directory or, for linked worktrees, submodules and `git init --separate-git-dir`,
+//! a `.git` file pointing at the git directory. Bare repositories and separate git
+//! directories are git directories on their own.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// How a repository is laid out on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Working directory with a `.git` directory
+    GitDir,
+    /// Working directory with a `.git` file, e.g. a linked worktree or submodule
+    GitFile,
+    /// Git directory on its own, e.g. a bare repository or a separate git directory
+    Bare,
+}
+
+/// The layout of the repository at `dir`, `None` if `dir` is not a repository.
+///
+/// `dir` is a working directory or, for bare repositories, the git directory itself.
+/// The `.git` directory of a working directory is not a repository by itself.
+#[must_use]
+pub fn detect(dir: &Path) -> Option<Layout> {
+    let dot_git = dir.join(".git");
+    if is_git_dir(&dot_git) {
+        Some(Layout::GitDir)
+    } else if is_git_file(&dot_git) {
+        Some(Layout::GitFile)
+    } else if is_bare_repo(dir) {
+        Some(Layout::Bare)
+    } else {
+        None
+    }
+}
+
+/// The working directory of the repository that `dir` is inside of, `None` if `dir` is
+/// in none or is a repository itself. The returned path is canonical.
+#[must_use]
+pub fn enclosing(dir: &Path) -> Option<PathBuf> {
+    if detect(dir).is_some() {
+        return None;
+    }
+    let dir = crate::paths::canonicalize(dir).ok()?;
+    dir.ancestors()
+        .skip(1)
+        .find(|ancestor| matches!(detect(ancestor), Some(Layout::GitDir | Layout::GitFile)))
+        .map(Path::to_path_buf)
+}
+
+/// Whether `path` is a git directory, i.e. has a `HEAD` file and either `objects` and
+/// `refs` directories or, for linked worktrees, a `commondir` file.
+#[must_use]
+pub fn is_git_dir(path: &Path) -> bool {
+    path.join("HEAD").is_file()
+        && (path.join("commondir").is_file()
+            || (path.join("objects").is_dir() && path.join("refs").is_dir()))
+}
+
+/// Whether `path` is a gitfile, i.e. a file starting with `gitdir: <path>`.
+#[must_use]
+pub fn is_git_file(path: &Path) -> bool {
+    // Gitfiles are a single short line, anything larger is something else
+    const MAX_GIT_FILE_SIZE: u64 = 4096;
+
+    let is_small_file = fs::symlink_metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.len() <= MAX_GIT_FILE_SIZE);
+    is_small_file && fs::read_to_string(path).is_ok_and(|content| content.starts_with("gitdir: "))
+}
+
+/// Whether `path` is a git directory on its own, e.g. created by `git init --bare` or
+/// `git init --separate-git-dir`, rather than the `.git` directory of a working directory.
+#[must_use]
+pub fn is_bare_repo(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name != ".git") && is_git_dir(path)
+}
+
+/// When the repository with working directory `dir` was last worked on, as far as is
+/// cheap to tell: the later modification time of the index, which most git commands
+/// write, and of `dir` itself, which changes when files are added or removed. The
+/// index of a `.git` file layout is not looked up.
+#[must_use]
+pub fn last_activity(dir: &Path) -> Option<SystemTime> {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    modified(&dir.join(".git").join("index")).max(modified(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn git(dir: &Path, args: &[&str]) {
+        Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_detect_layouts() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let base = temp_dir.path();
+        git(base, &["init", "repo"]);
+        git(base, &["init", "--bare", "bare.git"]);
+        git(
+            base,
+            &["init", "--separate-git-dir", "separate.git", "separate"],
+        );
+        let repo = base.join("repo");
+        git(&repo, &["config", "user.name", "Test User"]);
+        git(&repo, &["config", "user.email", "test@example.com"]);
+        git(&repo, &["commit", "--allow-empty", "-m", "Initial commit"]);
+        git(&repo, &["worktree", "add", "../worktree"]);
+
+        assert_eq!(detect(&repo), Some(Layout::GitDir));
+        assert_eq!(detect(&base.join("worktree")), Some(Layout::GitFile));
+        assert_eq!(detect(&base.join("separate")), Some(Layout::GitFile));
+        assert_eq!(detect(&base.join("bare.git")), Some(Layout::Bare));
+        assert_eq!(detect(&base.join("separate.git")), Some(Layout::Bare));
+        assert_eq!(detect(&repo.join(".git")), None);
+        assert_eq!(detect(base), None);
+
+        assert!(is_git_dir(&repo.join(".git/worktrees/worktree")));
+        assert!(is_git_file(&base.join("worktree/.git")));
+        assert!(!is_git_file(&repo.join(".git")));
+
+        fs::create_dir_all(repo.join("src/bin")).unwrap();
+        let canonical = crate::paths::canonicalize(&repo).unwrap();
+        assert_eq!(enclosing(&repo.join("src/bin")), Some(canonical));
+        assert_eq!(enclosing(&repo), None);
+        assert_eq!(enclosing(base), None);
+    }
+}
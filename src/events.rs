@@ -0,0 +1,111 @@
+//! Events of a running scan, for output, progress, checkpoints and anything else that
+//! follows a scan as it goes.
+//!
+//! The scan emits events to an [`EventBus`], which hands each of them to all
+//! subscribers in the order they subscribed. Repositories are checked in parallel, so
+//! subscribers are called from several threads at once.
+
+use std::fmt;
+use std::path::Path;
+
+/// Something that happened during a scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event<'a> {
+    /// A repository was found, in the order the search path is walked
+    RepoDiscovered { path: &'a Path },
+    /// A repository was checked; `nasty` tells whether it is reported
+    RepoChecked { path: &'a Path, nasty: bool },
+    /// The scan is over, all repositories were checked unless it was interrupted
+    ScanFinished {
+        checked: usize,
+        nasty: usize,
+        interrupted: bool,
+    },
+}
+
+/// Receives the events of a scan.
+pub trait Subscriber: Send + Sync {
+    fn handle(&self, event: &Event<'_>);
+}
+
+impl<F> Subscriber for F
+where
+    F: Fn(&Event<'_>) + Send + Sync,
+{
+    fn handle(&self, event: &Event<'_>) {
+        self(event);
+    }
+}
+
+/// Hands the events of a scan to its subscribers.
+#[derive(Default)]
+pub struct EventBus<'a> {
+    subscribers: Vec<Box<dyn Subscriber + 'a>>,
+}
+
+impl<'a> EventBus<'a> {
+    /// Hands all later events to `subscriber`.
+    pub fn subscribe(&mut self, subscriber: impl Subscriber + 'a) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    pub fn emit(&self, event: &Event<'_>) {
+        for subscriber in &self.subscribers {
+            subscriber.handle(event);
+        }
+    }
+}
+
+impl fmt::Debug for EventBus<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("subscribers", &self.subscribers.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_event_bus() {
+        let nasty = Mutex::new(Vec::new());
+        let finished = Mutex::new(None);
+        let mut events = EventBus::default();
+        events.subscribe(|event: &Event<'_>| {
+            if let Event::RepoChecked { path, nasty: true } = event {
+                nasty.lock().unwrap().push(path.to_path_buf());
+            }
+        });
+        events.subscribe(|event: &Event<'_>| {
+            if let Event::ScanFinished { checked, .. } = event {
+                *finished.lock().unwrap() = Some(*checked);
+            }
+        });
+
+        events.emit(&Event::RepoDiscovered {
+            path: Path::new("/src/a"),
+        });
+        events.emit(&Event::RepoChecked {
+            path: Path::new("/src/a"),
+            nasty: true,
+        });
+        events.emit(&Event::RepoChecked {
+            path: Path::new("/src/b"),
+            nasty: false,
+        });
+        events.emit(&Event::ScanFinished {
+            checked: 2,
+            nasty: 1,
+            interrupted: false,
+        });
+        drop(events);
+
+        assert_eq!(nasty.into_inner().unwrap(), [PathBuf::from("/src/a")]);
+        assert_eq!(finished.into_inner().unwrap(), Some(2));
+    }
+}
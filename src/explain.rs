@@ -0,0 +1,168 @@
+//! Step-by-step trace of how the status of a repository is decided, for debugging
+//! false positives.
+
+use crate::{fallback_upstream, head_state, CheckSet, Comparison, PushDefault, RepoStatus};
+use anyhow::Result;
+use git2::{BranchType, Oid, Repository};
+use std::path::Path;
+
+/// Lines explaining the status of the repository at `repo_path` under `checks`.
+///
+/// # Errors
+/// Returns an error if the repository cannot be opened or if git operations fail.
+pub fn explain(repo_path: &Path, checks: &CheckSet) -> Result<Vec<String>> {
+    let repo = checks.open(repo_path)?;
+    let mut lines = vec![format!(
+        "Repository: {} (git directory {})",
+        repo_path.display(),
+        repo.path().display()
+    )];
+    lines.push(head(&repo));
+
+    let against = &checks.comparison().against;
+    let state = head_state(&repo, checks.comparison())?;
+    if let Some(branch) = &state.branch {
+        lines.push(upstream(&repo, branch, checks.comparison()));
+        if let (Some(ahead), Some(behind)) = (state.ahead, state.behind) {
+            lines.push(format!("Ahead/behind: {ahead} ahead, {behind} behind"));
+        }
+        if let Some(remote) = &state.push_remote {
+            lines.push(match state.push_ahead {
+                Some(ahead) => {
+                    format!("Push remote: {remote}, {ahead} commit(s) ahead of {remote}/{branch}")
+                }
+                None => format!("Push remote: {remote}, but {remote}/{branch} does not exist"),
+            });
+        }
+        let compared = against.as_deref().unwrap_or("the upstream");
+        lines.push(format!(
+            "Branch decision: {}",
+            match (&state.push_remote, state.ahead, state.push_ahead) {
+                (Some(_), _, Some(0)) => "clean, everything is on the push remote".to_string(),
+                (Some(_), _, _) => "unpushed, commits are missing on the push remote".to_string(),
+                (None, Some(0), _) => format!("clean, nothing ahead of {compared}"),
+                (None, Some(ahead), _) => format!("unpushed, {ahead} commit(s) ahead"),
+                (None, None, _) => match against {
+                    Some(against) => format!("unpushed, {against} does not exist"),
+                    None => "unpushed, there is no upstream to compare against".to_string(),
+                },
+            }
+        ));
+    }
+
+    let results = checks.run_all(&repo)?;
+    if state.status == RepoStatus::MissingHead {
+        lines.push("Checks: skipped without HEAD".to_string());
+    } else {
+        lines.push(format!("Checks: {}", checks.names().join(", ")));
+        for name in checks.names() {
+            match results.findings.get(name) {
+                Some(findings) => {
+                    for finding in findings {
+                        lines.push(format!("  {name}: fired: {finding}"));
+                    }
+                }
+                None => lines.push(format!("  {name}: passed")),
+            }
+        }
+    }
+    lines.push(format!(
+        "Status: {}{}",
+        results.status,
+        match results.status {
+            RepoStatus::Clean => ", no check fired",
+            RepoStatus::MissingHead => ", HEAD does not resolve",
+            _ => ", from the first check that fired",
+        }
+    ));
+    Ok(lines)
+}
+
+fn head(repo: &Repository) -> String {
+    match repo.head() {
+        Ok(head) if head.is_branch() => format!(
+            "HEAD: {} at {}",
+            head.name().unwrap_or("-"),
+            short(head.target())
+        ),
+        Ok(head) => format!("HEAD: detached at {}, not compared", short(head.target())),
+        Err(e) => format!("HEAD: does not resolve ({})", e.message()),
+    }
+}
+
+fn upstream(repo: &Repository, branch: &str, comparison: &Comparison) -> String {
+    if let Some(against) = &comparison.against {
+        return match repo.revparse_single(against) {
+            Ok(object) => format!(
+                "Upstream: ignored, comparing against {against} at {} (--against)",
+                short(object.peel_to_commit().ok().map(|commit| commit.id()))
+            ),
+            Err(_) => format!("Upstream: ignored, but {against} does not exist (--against)"),
+        };
+    }
+    let configured = repo
+        .find_branch(branch, BranchType::Local)
+        .ok()
+        .and_then(|local| local.upstream().ok());
+    if let Some(upstream) = configured {
+        format!(
+            "Upstream: {} at {} (branch.{branch}.remote and branch.{branch}.merge)",
+            upstream.name().ok().flatten().unwrap_or("-"),
+            short(upstream.get().target())
+        )
+    } else if let Some(fallback) = fallback_upstream(repo, branch, comparison) {
+        format!(
+            "Upstream: none configured, assuming {} at {} (--assume-push-default current)",
+            fallback.name().ok().flatten().unwrap_or("-"),
+            short(fallback.get().target())
+        )
+    } else if comparison.push_default == PushDefault::Current {
+        format!("Upstream: none configured and origin/{branch} does not exist")
+    } else {
+        "Upstream: none configured".to_string()
+    }
+}
+
+fn short(oid: Option<Oid>) -> String {
+    oid.map_or_else(|| "-".to_string(), |oid| oid.to_string()[..7].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_explain_without_upstream() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(args)
+                .current_dir(repo_path)
+                .output()
+                .unwrap();
+        };
+        git(&["init", "-b", "main"]);
+        git(&["config", "user.name", "Test User"]);
+        git(&["config", "user.email", "test@example.com"]);
+        git(&["commit", "--allow-empty", "-m", "Initial commit"]);
+
+        let lines = explain(repo_path, &CheckSet::default()).unwrap();
+        assert!(lines[1].starts_with("HEAD: refs/heads/main at "));
+        assert_eq!(
+            lines[2..],
+            [
+                "Upstream: none configured",
+                "Branch decision: unpushed, there is no upstream to compare against",
+                "Checks: conflicts, operation, ahead, lfs, transfers",
+                "  conflicts: passed",
+                "  operation: passed",
+                "  ahead: fired: Branch main has no upstream",
+                "  lfs: passed",
+                "  transfers: passed",
+                "Status: unpushed, from the first check that fired",
+            ]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Scanning other machines over SSH.
+//!
+//! Each host runs a scan with `--format json`, either with a nasty-boii already
+//! installed there or with a copy of this binary uploaded for the scan, e.g. a static
+//! musl build. The reports are tagged with their host so they can be combined.
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// How nasty-boii is run on the remote hosts.
+#[derive(Debug, Clone)]
+pub enum Remote {
+    /// Command of an installed nasty-boii, e.g. `nasty-boii` or `~/.cargo/bin/nasty-boii`
+    Installed(String),
+    /// Binary copied to a temporary file on the host and removed after the scan
+    Upload(PathBuf),
+}
+
+/// Scans the same directory on several hosts.
+#[derive(Debug, Clone)]
+pub struct Fleet {
+    /// SSH command and options, the host and remote command are appended
+    pub ssh: Vec<String>,
+    pub remote: Remote,
+    /// Directory to scan, passed to the remote shell as is so `~` expands there
+    pub root: String,
+}
+
+impl Fleet {
+    /// Scans `host` and returns its JSON reports, each with a `host` field added.
+    ///
+    /// # Errors
+    /// Returns an error if the host cannot be reached or the scan fails there.
+    pub fn scan(&self, host: &str) -> Result<Vec<Value>> {
+        let executable = match &self.remote {
+            Remote::Installed(command) => command.clone(),
+            Remote::Upload(binary) => self.upload(host, binary)?,
+        };
+        // Logs go to stderr so they don't mix with the JSON on stdout
+        let mut command_line = format!(
+            "{executable} --no-history --log-file /dev/stderr --format json {}",
+            self.root
+        );
+        if matches!(self.remote, Remote::Upload(_)) {
+            command_line = format!("{command_line}; status=$?; rm -f {executable}; exit $status");
+        }
+
+        let output = self
+            .ssh_command(host, &command_line)
+            .stdin(Stdio::null())
+            .output()
+            .context("Failed to run ssh")?;
+        if !output.status.success() {
+            bail!(
+                "Scan failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let reports: Vec<Value> =
+            serde_json::from_slice(&output.stdout).context("Failed to parse scan results")?;
+        Ok(reports
+            .into_iter()
+            .map(|mut report| {
+                if let Value::Object(fields) = &mut report {
+                    fields.insert("host".to_string(), host.into());
+                }
+                report
+            })
+            .collect())
+    }
+
+    /// Copies `binary` to a temporary file on `host` and returns its path there.
+    fn upload(&self, host: &str, binary: &Path) -> Result<String> {
+        let binary_file =
+            File::open(binary).with_context(|| format!("Failed to open {}", binary.display()))?;
+        let output = self
+            .ssh_command(
+                host,
+                "f=$(mktemp /tmp/nasty-boii.XXXXXX) && cat > \"$f\" && chmod +x \"$f\" && echo \"$f\"",
+            )
+            .stdin(binary_file)
+            .output()
+            .context("Failed to run ssh")?;
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !output.status.success() || path.is_empty() {
+            bail!(
+                "Upload failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(path)
+    }
+
+    fn ssh_command(&self, host: &str, command_line: &str) -> Command {
+        let (program, options) = self
+            .ssh
+            .split_first()
+            .map_or(("ssh", &[][..]), |(p, o)| (p.as_str(), o));
+        let mut command = Command::new(program);
+        command.args(options).arg(host).arg(command_line);
+        command
+    }
+}
+
+/// Hosts from a hosts file, one per line like `host` or `user@host`.
+///
+/// Blank lines and lines starting with `#` are skipped.
+#[must_use]
+pub fn parse_hosts(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Writes reports as plain `host:path` lines.
+///
+/// # Errors
+/// Returns an error if writing fails.
+pub fn write_plain(out: &mut impl Write, reports: &[Value]) -> std::io::Result<()> {
+    for report in reports {
+        writeln!(
+            out,
+            "{}:{}",
+            report["host"].as_str().unwrap_or_default(),
+            report["path"].as_str().unwrap_or_default()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hosts() {
+        assert_eq!(
+            parse_hosts("# lab\nlab-1\n\n  admin@lab-2  \n"),
+            vec!["lab-1", "admin@lab-2"]
+        );
+    }
+}
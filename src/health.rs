@@ -0,0 +1,104 @@
+//! Advice on repository maintenance, e.g. before migrating to a new machine.
+//!
+//! Advisories don't make a repository nasty: nothing is lost, it is just a good time to
+//! run `git gc`. Like `git gc --auto`, loose objects are estimated from a sample of the
+//! object directory instead of counting all of them.
+
+use crate::common_dir;
+use git2::Repository;
+use std::fs;
+use std::path::Path;
+
+/// Loose objects above which `git gc --auto` repacks, unless `gc.auto` says otherwise
+const DEFAULT_GC_AUTO: i64 = 6700;
+/// Packs above which `git gc --auto` consolidates them, unless `gc.autoPackLimit` says
+/// otherwise
+const DEFAULT_GC_AUTO_PACK_LIMIT: i64 = 50;
+
+/// Maintenance advisories for the repository at `repo_path`, empty if it is in shape or
+/// cannot be opened.
+#[must_use]
+pub fn advisories(repo_path: &Path) -> Vec<String> {
+    let Ok(repo) = Repository::open(repo_path) else {
+        return Vec::new();
+    };
+    let objects = common_dir(&repo).join("objects");
+    let config = repo.config().ok();
+    let limit = |name: &str, default: i64| {
+        config
+            .as_ref()
+            .and_then(|config| config.get_i64(name).ok())
+            .unwrap_or(default)
+    };
+    let mut advisories = Vec::new();
+
+    let loose = estimate_loose_objects(&objects);
+    let gc_auto = limit("gc.auto", DEFAULT_GC_AUTO);
+    if gc_auto > 0 && i64::try_from(loose).unwrap_or(i64::MAX) > gc_auto {
+        advisories.push(format!(
+            "About {loose} loose objects, git gc would pack them"
+        ));
+    }
+
+    let packs = count_entries(&objects.join("pack"), |name| {
+        Path::new(name).extension().is_some_and(|ext| ext == "pack")
+    });
+    let pack_limit = limit("gc.autoPackLimit", DEFAULT_GC_AUTO_PACK_LIMIT);
+    if pack_limit > 0 && i64::try_from(packs).unwrap_or(i64::MAX) > pack_limit {
+        advisories.push(format!("{packs} packs, git gc would combine them"));
+    }
+
+    if common_dir(&repo).join("gc.log").exists() {
+        advisories.push("Last automatic git gc failed, see gc.log in the git directory".into());
+    }
+    advisories
+}
+
+/// Loose objects in the repository, extrapolated from `objects/17` like git does.
+fn estimate_loose_objects(objects: &Path) -> usize {
+    count_entries(&objects.join("17"), |name| {
+        name.len() >= 38 && name.bytes().all(|byte| byte.is_ascii_hexdigit())
+    }) * 256
+}
+
+fn count_entries(dir: &Path, matches: impl Fn(&str) -> bool) -> usize {
+    fs::read_dir(dir).map_or(0, |entries| {
+        entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_str().is_some_and(&matches))
+            .count()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advisories() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        assert!(advisories(dir.path()).is_empty());
+
+        let sample = repo.path().join("objects").join("17");
+        fs::create_dir_all(&sample).unwrap();
+        for i in 0..30 {
+            fs::write(sample.join(format!("{i:038x}")), "").unwrap();
+        }
+        fs::write(
+            repo.path().join("gc.log"),
+            "error: too many unreachable objects",
+        )
+        .unwrap();
+        assert_eq!(
+            advisories(dir.path()),
+            vec![
+                "About 7680 loose objects, git gc would pack them".to_string(),
+                "Last automatic git gc failed, see gc.log in the git directory".to_string(),
+            ]
+        );
+
+        repo.config().unwrap().set_i64("gc.auto", 0).unwrap();
+        assert_eq!(advisories(dir.path()).len(), 1);
+    }
+}
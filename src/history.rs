@@ -0,0 +1,270 @@
+use crate::db::Db;
+use anyhow::{Context, Result};
+use rusqlite::params;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+/// Number of scans kept in the history.
+const MAX_RECORDS: usize = 100;
+
+/// Findings of a single scan.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanRecord {
+    /// Scan time in seconds since the Unix epoch
+    pub time: i64,
+    /// What was scanned, e.g. the canonical search path
+    pub root: String,
+    /// Canonical paths of the nasty repositories
+    pub repos: BTreeSet<PathBuf>,
+}
+
+/// Number of nasty repositories found on a day, by the last scan of that day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DayCount {
+    /// Day in UTC, `YYYY-MM-DD`
+    pub day: String,
+    pub nasty: usize,
+}
+
+/// Repositories that changed state between two scans.
+#[derive(Debug, Default, PartialEq)]
+pub struct ScanDiff {
+    pub became_nasty: Vec<PathBuf>,
+    pub cleaned_up: Vec<PathBuf>,
+}
+
+impl ScanRecord {
+    /// Compares this scan against an earlier one.
+    #[must_use]
+    pub fn diff(&self, previous: &ScanRecord) -> ScanDiff {
+        ScanDiff {
+            became_nasty: self.repos.difference(&previous.repos).cloned().collect(),
+            cleaned_up: previous.repos.difference(&self.repos).cloned().collect(),
+        }
+    }
+}
+
+/// Scan history, stored in the database.
+#[derive(Debug)]
+pub struct History<'a> {
+    db: &'a Db,
+}
+
+impl<'a> History<'a> {
+    #[must_use]
+    pub fn new(db: &'a Db) -> Self {
+        Self { db }
+    }
+
+    /// Appends a scan, dropping the oldest ones beyond the retention limit.
+    ///
+    /// The number of nasty repositories is also kept per day, which is never dropped.
+    ///
+    /// # Errors
+    /// Returns an error if the scan cannot be stored.
+    pub fn push(&self, record: &ScanRecord) -> Result<()> {
+        let tx = self.db.conn().unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO scans (time, root) VALUES (?1, ?2)",
+            params![record.time, record.root],
+        )?;
+        let scan_id = tx.last_insert_rowid();
+        tx.execute(
+            "INSERT INTO scan_days (day, root, nasty) VALUES (date(?1, 'unixepoch'), ?2, ?3)
+             ON CONFLICT (day, root) DO UPDATE SET nasty = excluded.nasty",
+            params![record.time, record.root, record.repos.len()],
+        )?;
+        {
+            let mut stmt = tx.prepare("INSERT INTO scan_repos (scan_id, path) VALUES (?1, ?2)")?;
+            for repo in &record.repos {
+                stmt.execute(params![scan_id, repo.to_string_lossy()])?;
+            }
+        }
+        tx.execute(
+            "DELETE FROM scans WHERE id NOT IN (SELECT id FROM scans ORDER BY id DESC LIMIT ?1)",
+            params![MAX_RECORDS],
+        )?;
+        tx.commit().context("Failed to record scan")
+    }
+
+    /// All scans, oldest first.
+    ///
+    /// # Errors
+    /// Returns an error if the history cannot be read.
+    pub fn records(&self) -> Result<Vec<ScanRecord>> {
+        self.select("SELECT id, time, root FROM scans ORDER BY id", [])
+    }
+
+    /// All scans of `root`, oldest first.
+    ///
+    /// # Errors
+    /// Returns an error if the history cannot be read.
+    pub fn records_of(&self, root: &str) -> Result<Vec<ScanRecord>> {
+        self.select(
+            "SELECT id, time, root FROM scans WHERE root = ?1 ORDER BY id",
+            [root],
+        )
+    }
+
+    /// The most recent scan, optionally restricted to `root`.
+    ///
+    /// # Errors
+    /// Returns an error if the history cannot be read.
+    pub fn latest(&self, root: Option<&str>) -> Result<Option<ScanRecord>> {
+        let records = match root {
+            Some(root) => self.select(
+                "SELECT id, time, root FROM scans WHERE root = ?1 ORDER BY id DESC LIMIT 1",
+                [root],
+            )?,
+            None => self.select(
+                "SELECT id, time, root FROM scans ORDER BY id DESC LIMIT 1",
+                [],
+            )?,
+        };
+        Ok(records.into_iter().next())
+    }
+
+    /// The two most recent scans of `root`, newest first.
+    ///
+    /// # Errors
+    /// Returns an error if the history cannot be read.
+    pub fn last_two(&self, root: &str) -> Result<(Option<ScanRecord>, Option<ScanRecord>)> {
+        let mut records = self
+            .select(
+                "SELECT id, time, root FROM scans WHERE root = ?1 ORDER BY id DESC LIMIT 2",
+                [root],
+            )?
+            .into_iter();
+        Ok((records.next(), records.next()))
+    }
+
+    /// Number of nasty repositories per day for scans of `root`, oldest first.
+    ///
+    /// # Errors
+    /// Returns an error if the history cannot be read.
+    pub fn days(&self, root: &str) -> Result<Vec<DayCount>> {
+        let conn = self.db.conn();
+        let mut stmt =
+            conn.prepare("SELECT day, nasty FROM scan_days WHERE root = ?1 ORDER BY day")?;
+        let days = stmt
+            .query_map([root], |row| {
+                Ok(DayCount {
+                    day: row.get(0)?,
+                    nasty: row.get(1)?,
+                })
+            })?
+            .collect::<Result<_, _>>()
+            .context("Failed to read scan history")?;
+        Ok(days)
+    }
+
+    fn select<P: rusqlite::Params>(&self, sql: &str, params: P) -> Result<Vec<ScanRecord>> {
+        let conn = self.db.conn();
+        let mut stmt = conn.prepare(sql)?;
+        let scans: Vec<(i64, i64, String)> = stmt
+            .query_map(params, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()
+            .context("Failed to read scan history")?;
+
+        let mut repos_stmt = conn.prepare("SELECT path FROM scan_repos WHERE scan_id = ?1")?;
+        scans
+            .into_iter()
+            .map(|(id, time, root)| {
+                let repos = repos_stmt
+                    .query_map([id], |row| row.get::<_, String>(0))?
+                    .map(|path| path.map(PathBuf::from))
+                    .collect::<Result<_, _>>()?;
+                Ok(ScanRecord { time, root, repos })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(time: i64, root: &str, repos: &[&str]) -> ScanRecord {
+        ScanRecord {
+            time,
+            root: root.to_string(),
+            repos: repos.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let previous = record(1, "/src", &["/src/a", "/src/b"]);
+        let current = record(2, "/src", &["/src/b", "/src/c"]);
+
+        assert_eq!(
+            current.diff(&previous),
+            ScanDiff {
+                became_nasty: vec![PathBuf::from("/src/c")],
+                cleaned_up: vec![PathBuf::from("/src/a")],
+            }
+        );
+    }
+
+    #[test]
+    fn test_push_and_last_two() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Db::open(&temp_dir.path().join("nasty-boii.db")).unwrap();
+
+        let history = History::new(&db);
+        history.push(&record(1, "/src", &["/src/a"])).unwrap();
+        history.push(&record(2, "/other", &[])).unwrap();
+        history.push(&record(3, "/src", &["/src/b"])).unwrap();
+
+        assert_eq!(history.records().unwrap().len(), 3);
+        assert_eq!(history.latest(None).unwrap().unwrap().time, 3);
+        assert_eq!(history.latest(Some("/other")).unwrap().unwrap().time, 2);
+        let (latest, previous) = history.last_two("/src").unwrap();
+        assert_eq!(latest.unwrap(), record(3, "/src", &["/src/b"]));
+        assert_eq!(previous.unwrap(), record(1, "/src", &["/src/a"]));
+    }
+
+    #[test]
+    fn test_days_keep_last_scan_of_day() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Db::open(&temp_dir.path().join("nasty-boii.db")).unwrap();
+        let history = History::new(&db);
+
+        history
+            .push(&record(0, "/src", &["/src/a", "/src/b"]))
+            .unwrap();
+        history.push(&record(3600, "/src", &["/src/a"])).unwrap();
+        history.push(&record(86_400, "/src", &[])).unwrap();
+        history
+            .push(&record(86_400, "/other", &["/other/a"]))
+            .unwrap();
+
+        let day = |day: &str, nasty| DayCount {
+            day: day.to_string(),
+            nasty,
+        };
+        assert_eq!(
+            history.days("/src").unwrap(),
+            vec![day("1970-01-01", 1), day("1970-01-02", 0)]
+        );
+    }
+
+    #[test]
+    fn test_push_drops_oldest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Db::open(&temp_dir.path().join("nasty-boii.db")).unwrap();
+        let history = History::new(&db);
+
+        for time in 0..=i64::try_from(MAX_RECORDS).unwrap() {
+            history.push(&record(time, "/src", &["/src/a"])).unwrap();
+        }
+        let records = history.records().unwrap();
+        assert_eq!(records.len(), MAX_RECORDS);
+        assert_eq!(records[0].time, 1);
+        // Repos of dropped scans are deleted with them
+        let orphans = db
+            .query("SELECT COUNT(*) FROM scan_repos WHERE scan_id NOT IN (SELECT id FROM scans)")
+            .unwrap();
+        assert_eq!(orphans.rows, vec![vec!["0".to_string()]]);
+    }
+}
@@ -0,0 +1,360 @@
+use crate::common_dir;
+use crate::paths::{fish_quote, sh_quote};
+use anyhow::{bail, Context, Result};
+use git2::{Config, Repository};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Shell snippet that wraps the usual power-off commands in a check of registered repos.
+///
+/// Works in bash and zsh. If `nasty-boii --registered` reports anything, the user is
+/// asked to confirm before the real command runs.
+#[must_use]
+pub fn shutdown_hook_script(exe: &Path) -> String {
+    format!(
+        r#"# Installed by `nasty-boii install-hook shutdown`
+__nasty_boii_guard() {{
+    __nasty_boii_repos=$({exe} --registered) || return 0
+    [ -z "$__nasty_boii_repos" ] && return 0
+    printf 'nasty-boii: unpushed changes in:\n%s\n' "$__nasty_boii_repos" >&2
+    printf 'Continue anyway? [y/N] ' >&2
+    read -r __nasty_boii_answer
+    case "$__nasty_boii_answer" in
+        y|Y) return 0 ;;
+        *) return 1 ;;
+    esac
+}}
+poweroff() {{ __nasty_boii_guard && command poweroff "$@"; }}
+reboot() {{ __nasty_boii_guard && command reboot "$@"; }}
+shutdown() {{ __nasty_boii_guard && command shutdown "$@"; }}
+halt() {{ __nasty_boii_guard && command halt "$@"; }}
+systemctl() {{
+    case "$1" in
+        poweroff|reboot|halt|kexec) __nasty_boii_guard || return 1 ;;
+    esac
+    command systemctl "$@"
+}}
+"#,
+        exe = sh_quote(exe)
+    )
+}
+
+/// Zsh snippet that warns when leaving a nasty repository.
+///
+/// On every directory change, the repository that contained the previous directory is
+/// checked with `nasty-boii check` unless the new directory is still inside it. Its
+/// output is shown if it exits with 1, i.e. the repository is nasty.
+#[must_use]
+pub fn zsh_leave_hook_script(exe: &Path) -> String {
+    format!(
+        r#"# Generated by `nasty-boii shell-hook zsh`
+__nasty_boii_leave() {{
+    local top nasty
+    top=$(command git -C "$OLDPWD" rev-parse --show-toplevel 2>/dev/null) || return 0
+    case "$PWD/" in "$top"/*) return 0 ;; esac
+    nasty=$({exe} check "$top" 2>/dev/null)
+    [ $? -eq 1 ] && printf 'nasty-boii: left %s\n' "$nasty" >&2
+    return 0
+}}
+autoload -Uz add-zsh-hook
+add-zsh-hook chpwd __nasty_boii_leave
+"#,
+        exe = sh_quote(exe)
+    )
+}
+
+/// Fish snippet that warns when leaving a nasty repository, like
+/// [`zsh_leave_hook_script`].
+#[must_use]
+pub fn fish_leave_hook_script(exe: &Path) -> String {
+    format!(
+        r#"# Generated by `nasty-boii shell-hook fish`
+set -g __nasty_boii_previous $PWD
+function __nasty_boii_leave --on-variable PWD
+    set -l previous $__nasty_boii_previous
+    set -g __nasty_boii_previous $PWD
+    set -l top (command git -C $previous rev-parse --show-toplevel 2>/dev/null); or return 0
+    string match -q -- "$top/*" "$PWD/"; and return 0
+    set -l nasty ({exe} check $top 2>/dev/null)
+    test $status -eq 1; and printf 'nasty-boii: left %s\n' (string join \n -- $nasty) >&2
+    return 0
+end
+"#,
+        exe = fish_quote(exe)
+    )
+}
+
+/// First line after the shebang of the git hooks nasty-boii installs, to tell them
+/// from hooks of other tools.
+const GIT_HOOK_MARKER: &str = "# Installed by `nasty-boii install-git-hook`";
+
+/// Hooks git runs from `core.hooksPath`, which a global install forwards to the hooks
+/// of each repository.
+///
+/// `push-to-checkout` is left out, since merely having it changes how
+/// `receive.denyCurrentBranch=updateInstead` updates the working directory.
+pub const GIT_HOOK_NAMES: &[&str] = &[
+    "applypatch-msg",
+    "pre-applypatch",
+    "post-applypatch",
+    "pre-commit",
+    "pre-merge-commit",
+    "prepare-commit-msg",
+    "commit-msg",
+    "post-commit",
+    "pre-rebase",
+    "post-checkout",
+    "post-merge",
+    "pre-push",
+    "pre-receive",
+    "update",
+    "proc-receive",
+    "post-receive",
+    "post-update",
+    "reference-transaction",
+    "pre-auto-gc",
+    "post-rewrite",
+    "sendemail-validate",
+    "post-index-change",
+];
+
+/// Shell lines that run the repository's own hook `name`, with the arguments and stdin
+/// of the hook they end.
+fn run_own_hook(name: &str) -> String {
+    format!(
+        r#"own="$(git rev-parse --git-common-dir)/hooks/{name}"
+if [ -x "$own" ] && ! [ "$own" -ef "$0" ]; then
+    exec "$own" "$@"
+fi
+exit 0
+"#
+    )
+}
+
+/// Git `post-commit` hook that registers the repository committed to, so that
+/// `--registered` scans and the shutdown hook cover it without a full scan.
+///
+/// Installed globally through `core.hooksPath`, it stands in for the hooks of each
+/// repository, so it runs the repository's own `post-commit` hook as well.
+#[must_use]
+pub fn post_commit_hook_script(exe: &Path) -> String {
+    format!(
+        r#"#!/bin/sh
+{GIT_HOOK_MARKER}
+{exe} register "$(git rev-parse --show-toplevel)" >/dev/null 2>&1
+{run_own}"#,
+        exe = sh_quote(exe),
+        run_own = run_own_hook("post-commit")
+    )
+}
+
+/// Git hook `name` that only runs the repository's own hook of that name, so that the
+/// hooks in `.git/hooks` keep working when `core.hooksPath` points elsewhere.
+#[must_use]
+pub fn forwarding_hook_script(name: &str) -> String {
+    format!("#!/bin/sh\n{GIT_HOOK_MARKER}\n{}", run_own_hook(name))
+}
+
+/// Installs a [`forwarding_hook_script`] in `hooks_dir` for each of [`GIT_HOOK_NAMES`]
+/// but `post-commit`, keeping hooks of other tools. Returns the names of the skipped
+/// hooks.
+///
+/// # Errors
+/// Returns an error if a hook cannot be written.
+pub fn install_forwarding_hooks(hooks_dir: &Path) -> Result<Vec<&'static str>> {
+    let mut skipped = Vec::new();
+    for &name in GIT_HOOK_NAMES {
+        if name == "post-commit" {
+            continue;
+        }
+        if is_foreign_hook(&hooks_dir.join(name)) {
+            skipped.push(name);
+            continue;
+        }
+        install_git_hook(hooks_dir, name, &forwarding_hook_script(name))?;
+    }
+    Ok(skipped)
+}
+
+/// Whether `hook_path` holds a hook that nasty-boii didn't install.
+fn is_foreign_hook(hook_path: &Path) -> bool {
+    fs::read_to_string(hook_path).is_ok_and(|current| !current.contains(GIT_HOOK_MARKER))
+}
+
+/// The directory git runs the hooks of the repository at `repo_path` from, `hooks` in
+/// its git directory unless `core.hooksPath` is set.
+///
+/// # Errors
+/// Returns an error if `repo_path` is not a git repository.
+pub fn repo_hooks_dir(repo_path: &Path) -> Result<PathBuf> {
+    let repo = Repository::open(repo_path)
+        .context(format!("Not a git repository: {}", repo_path.display()))?;
+    let hooks_path = repo
+        .config()
+        .and_then(|config| config.get_path("core.hooksPath"));
+    Ok(match (hooks_path, repo.workdir()) {
+        (Ok(hooks_path), Some(workdir)) => workdir.join(hooks_path),
+        (Ok(hooks_path), None) => hooks_path,
+        (Err(_), _) => common_dir(&repo).join("hooks"),
+    })
+}
+
+/// The directory of `core.hooksPath` in the global git config of `home`, which is set
+/// to `default` if it is unset.
+///
+/// # Errors
+/// Returns an error if the global git config cannot be read or written.
+pub fn global_hooks_dir(home: &Path, default: &Path) -> Result<PathBuf> {
+    let config_path = Config::find_global().unwrap_or_else(|_| home.join(".gitconfig"));
+    let mut config =
+        Config::open(&config_path).context(format!("Failed to open {}", config_path.display()))?;
+    if let Ok(hooks_dir) = config.get_path("core.hooksPath") {
+        return Ok(hooks_dir);
+    }
+    config
+        .set_str("core.hooksPath", &default.to_string_lossy())
+        .context(format!(
+            "Failed to set core.hooksPath in {}",
+            config_path.display()
+        ))?;
+    Ok(default.to_path_buf())
+}
+
+/// Writes `script` as the executable git hook `name` in `hooks_dir`, replacing a hook
+/// installed before but not the hook of another tool.
+///
+/// # Errors
+/// Returns an error if `hooks_dir` already has another hook of that name or the hook
+/// cannot be written.
+pub fn install_git_hook(hooks_dir: &Path, name: &str, script: &str) -> Result<PathBuf> {
+    let hook_path = hooks_dir.join(name);
+    if is_foreign_hook(&hook_path) {
+        bail!(
+            "{} already exists, add this line to it instead:\n{}",
+            hook_path.display(),
+            script.lines().nth(2).unwrap_or_default()
+        );
+    }
+    fs::create_dir_all(hooks_dir).context(format!(
+        "Failed to create directory: {}",
+        hooks_dir.display()
+    ))?;
+    fs::write(&hook_path, script)
+        .context(format!("Failed to write hook: {}", hook_path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&hook_path, fs::Permissions::from_mode(0o755)).context(format!(
+            "Failed to make hook executable: {}",
+            hook_path.display()
+        ))?;
+    }
+    Ok(hook_path)
+}
+
+/// Writes `script` to `script_path` and sources it from `profile`.
+///
+/// Returns `false` if the profile already sourced the script.
+///
+/// # Errors
+/// Returns an error if the script or profile cannot be written.
+pub fn install_profile_hook(profile: &Path, script_path: &Path, script: &str) -> Result<bool> {
+    if let Some(parent) = script_path.parent() {
+        fs::create_dir_all(parent)
+            .context(format!("Failed to create directory: {}", parent.display()))?;
+    }
+    fs::write(script_path, script)
+        .context(format!("Failed to write hook: {}", script_path.display()))?;
+
+    let source_line = format!(". {}", sh_quote(script_path));
+    let current = fs::read_to_string(profile).unwrap_or_default();
+    if current.lines().any(|line| line.trim() == source_line) {
+        return Ok(false);
+    }
+
+    let mut updated = current;
+    if !updated.is_empty() && !updated.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated.push_str(&source_line);
+    updated.push('\n');
+    fs::write(profile, updated).context(format!("Failed to update {}", profile.display()))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_profile_hook_is_idempotent() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let profile = temp_dir.path().join(".bashrc");
+        let script_path = temp_dir.path().join("hooks").join("shutdown.sh");
+        fs::write(&profile, "export EDITOR=vi").unwrap();
+
+        let script = shutdown_hook_script(Path::new("/usr/bin/nasty-boii"));
+        assert!(install_profile_hook(&profile, &script_path, &script).unwrap());
+        assert!(!install_profile_hook(&profile, &script_path, &script).unwrap());
+
+        let content = fs::read_to_string(&profile).unwrap();
+        assert_eq!(
+            content,
+            format!("export EDITOR=vi\n. '{}'\n", script_path.display())
+        );
+        assert!(fs::read_to_string(&script_path)
+            .unwrap()
+            .contains("'/usr/bin/nasty-boii' --registered"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hooks_quote_paths() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("it's here");
+        let exe = dir.join("nasty-boii");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&exe, "#!/bin/sh\necho \"registered $*\"\n").unwrap();
+        fs::set_permissions(&exe, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+
+        let profile = temp_dir.path().join(".profile");
+        let script = shutdown_hook_script(&exe);
+        install_profile_hook(&profile, &dir.join("shutdown.sh"), &script).unwrap();
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!(". {} && __nasty_boii_guard", sh_quote(&profile)))
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap();
+        // The fake executable reports itself as a nasty repo
+        assert!(String::from_utf8_lossy(&output.stderr).contains("registered --registered"));
+        assert!(fish_leave_hook_script(&exe).contains(r"('/"));
+    }
+
+    #[test]
+    fn test_install_git_hook_keeps_foreign_hooks() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let script = post_commit_hook_script(Path::new("/usr/bin/nasty-boii"));
+
+        let hook_path = install_git_hook(temp_dir.path(), "post-commit", &script).unwrap();
+        assert_eq!(fs::read_to_string(&hook_path).unwrap(), script);
+        // Installing again replaces the own hook
+        install_git_hook(temp_dir.path(), "post-commit", &script).unwrap();
+
+        fs::write(
+            &hook_path,
+            "#!/bin/sh
+make lint
+",
+        )
+        .unwrap();
+        let error = install_git_hook(temp_dir.path(), "post-commit", &script).unwrap_err();
+        assert!(error.to_string().ends_with(
+            "'/usr/bin/nasty-boii' register \"$(git rev-parse --show-toplevel)\" >/dev/null 2>&1"
+        ));
+        assert_eq!(
+            fs::read_to_string(&hook_path).unwrap(),
+            "#!/bin/sh\nmake lint\n"
+        );
+    }
+}
@@ -0,0 +1,414 @@
+//! Large file extensions that keep content outside of git objects: git-lfs and git-annex.
+//!
+//! A pushed branch does not mean its large files are on a remote, so these are
+//! checked separately. Everything is derived from local state, no remote is contacted.
+
+use crate::common_dir;
+use crate::refs::is_contained;
+use anyhow::{Context, Result};
+use git2::{ObjectType, Oid, Repository, TreeWalkMode, TreeWalkResult};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use walkdir::WalkDir;
+
+/// Age after which transfer files are considered left over from a crashed transfer.
+const STALE_TRANSFER: Duration = Duration::from_hours(1);
+
+/// git-lfs pointer files are small, larger blobs can be skipped without reading them.
+const MAX_POINTER_SIZE: usize = 1024;
+
+/// Content of a repository that may not be on any remote, as human-readable findings
+/// such as `2 LFS object(s) not on any remote`. Empty if the repository uses neither
+/// git-lfs nor git-annex.
+pub(crate) fn unpushed_large_files(repo: &Repository) -> Result<Vec<String>> {
+    let common_dir = common_dir(repo);
+    let mut findings = Vec::new();
+
+    let lfs_objects = local_lfs_objects(&common_dir);
+    if !lfs_objects.is_empty() {
+        let unreferenced = count_unpushed_lfs_objects(repo, lfs_objects)?;
+        if unreferenced > 0 {
+            findings.push(format!("{unreferenced} LFS object(s) not on any remote"));
+        }
+        if !has_lfs_pre_push_hook(repo, &common_dir) {
+            findings.push("LFS pre-push hook missing, pushes skip uploads".to_string());
+        }
+    }
+
+    if common_dir.join("annex").is_dir() {
+        findings.extend(unsynced_annex(repo)?);
+    }
+    Ok(findings)
+}
+
+/// Running git-lfs or git-annex transfers, as human-readable findings.
+///
+/// git-lfs keeps partial downloads in `lfs/tmp` (`lfs/incomplete` in old versions),
+/// git-annex records uploads and downloads in `annex/transfer/{upload,download}`.
+/// Files left behind by crashed transfers are ignored once they are older than
+/// [`STALE_TRANSFER`].
+pub(crate) fn transfers_in_progress(common_dir: &Path) -> Vec<String> {
+    let mut findings = Vec::new();
+
+    let lfs = ["tmp", "incomplete"]
+        .iter()
+        .map(|dir| recent_files(&common_dir.join("lfs").join(dir), 1))
+        .sum::<usize>();
+    if lfs > 0 {
+        findings.push(format!(
+            "git-lfs transfer in progress ({lfs} temporary file(s))"
+        ));
+    }
+
+    for direction in ["upload", "download"] {
+        // annex/transfer/<direction>/<remote uuid>/<key>, next to lck.<key> lock files
+        let annex = recent_files(&common_dir.join("annex/transfer").join(direction), 2);
+        if annex > 0 {
+            findings.push(format!(
+                "git-annex {direction} in progress ({annex} file(s))"
+            ));
+        }
+    }
+    findings
+}
+
+/// Files at `depth` below `dir` modified within [`STALE_TRANSFER`], except lock files.
+fn recent_files(dir: &Path, depth: usize) -> usize {
+    WalkDir::new(dir)
+        .min_depth(depth)
+        .max_depth(depth)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with("lck."))
+        .filter(|entry| {
+            entry
+                .metadata()
+                .ok()
+                .and_then(|metadata| metadata.modified().ok())
+                .is_some_and(|modified| modified.elapsed().is_ok_and(|age| age < STALE_TRANSFER))
+        })
+        .count()
+}
+
+/// Whether the repository with `common_dir` keeps git-lfs objects or git-annex content,
+/// which [`unpushed_large_files`] needs the repository to compare against the remotes.
+pub(crate) fn has_large_files(common_dir: &Path) -> bool {
+    !local_lfs_objects(common_dir).is_empty() || common_dir.join("annex").is_dir()
+}
+
+/// SHA-256 object IDs in `lfs/objects/<aa>/<bb>/<oid>`.
+fn local_lfs_objects(common_dir: &Path) -> HashSet<String> {
+    WalkDir::new(common_dir.join("lfs").join("objects"))
+        .min_depth(3)
+        .max_depth(3)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+        .filter(|name| name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit()))
+        .collect()
+}
+
+/// Removes LFS objects referenced by pointers in remote-tracking history from
+/// `objects` and returns how many are left.
+fn count_unpushed_lfs_objects(repo: &Repository, mut objects: HashSet<String>) -> Result<usize> {
+    let odb = repo.odb().context("Failed to open object database")?;
+    let mut walk = repo.revwalk().context("Failed to create revwalk")?;
+    walk.push_glob("refs/remotes")?;
+
+    for oid in walk {
+        if objects.is_empty() {
+            break;
+        }
+        let commit = repo.find_commit(oid?)?;
+        let parent_tree = commit.parent(0).ok().map(|p| p.tree()).transpose()?;
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+        for delta in diff.deltas() {
+            let blob_oid = delta.new_file().id();
+            if blob_oid.is_zero() {
+                continue;
+            }
+            match odb.read_header(blob_oid) {
+                Ok((size, ObjectType::Blob)) if size <= MAX_POINTER_SIZE => {}
+                _ => continue,
+            }
+            if let Some(pointer) = lfs_pointer_oid(repo.find_blob(blob_oid)?.content()) {
+                objects.remove(pointer);
+            }
+        }
+    }
+    Ok(objects.len())
+}
+
+/// The object ID of a git-lfs pointer file, see
+/// <https://github.com/git-lfs/git-lfs/blob/main/docs/spec.md>.
+fn lfs_pointer_oid(content: &[u8]) -> Option<&str> {
+    let content = std::str::from_utf8(content).ok()?;
+    if !content.starts_with("version https://git-lfs.github.com/spec/") {
+        return None;
+    }
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("oid sha256:"))
+}
+
+/// Whether pushes upload LFS objects, i.e. `git lfs install` set up the pre-push hook.
+fn has_lfs_pre_push_hook(repo: &Repository, common_dir: &Path) -> bool {
+    let hooks_dir = repo
+        .config()
+        .and_then(|config| config.get_path("core.hooksPath"))
+        .unwrap_or_else(|_| common_dir.join("hooks"));
+    fs::read_to_string(hooks_dir.join("pre-push"))
+        .is_ok_and(|hook| hook.contains("git lfs") || hook.contains("git-lfs"))
+}
+
+/// Findings for git-annex: an unsynced `git-annex` branch, which holds the location
+/// tracking, and annexed content that no other repository has a copy of.
+fn unsynced_annex(repo: &Repository) -> Result<Vec<String>> {
+    let Ok(branch) = repo.find_reference("refs/heads/git-annex") else {
+        return Ok(Vec::new());
+    };
+    let Some(local_oid) = branch.target() else {
+        return Ok(Vec::new());
+    };
+
+    let mut findings = Vec::new();
+    let remote_oids: Vec<Oid> = repo
+        .references_glob("refs/remotes/*/git-annex")
+        .context("Failed to list references")?
+        .filter_map(|reference| reference.ok()?.target())
+        .collect();
+    if !is_contained(repo, local_oid, &remote_oids) {
+        findings.push("git-annex branch not synced".to_string());
+    }
+
+    if let Ok(uuid) = repo
+        .config()
+        .and_then(|config| config.get_string("annex.uuid"))
+    {
+        let only_here = count_annex_keys_only_in(repo, local_oid, &uuid)?;
+        if only_here > 0 {
+            findings.push(format!(
+                "{only_here} annexed file(s) only in this repository"
+            ));
+        }
+    }
+    Ok(findings)
+}
+
+/// Counts keys whose location log in the `git-annex` branch lists `uuid` as the only
+/// repository with a copy.
+///
+/// Location logs live at `<hash>/<hash>/<key>.log` and contain lines like
+/// `1700000000.123s 1 <uuid>`, where the last entry per repository wins and `1`
+/// means the content is present.
+fn count_annex_keys_only_in(repo: &Repository, branch_oid: Oid, uuid: &str) -> Result<usize> {
+    let tree = repo.find_commit(branch_oid)?.tree()?;
+    let mut count = 0;
+    let mut error = None;
+    let result = tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        let is_location_log = !dir.is_empty()
+            && entry.kind() == Some(ObjectType::Blob)
+            && Path::new(entry.name().unwrap_or_default())
+                .extension()
+                .is_some_and(|ext| ext == "log");
+        if !is_location_log {
+            return TreeWalkResult::Ok;
+        }
+        match repo.find_blob(entry.id()) {
+            Ok(blob) => {
+                let log = String::from_utf8_lossy(blob.content());
+                let present = present_repositories(&log);
+                if present.len() == 1 && present.contains(uuid) {
+                    count += 1;
+                }
+                TreeWalkResult::Ok
+            }
+            Err(e) => {
+                error = Some(e);
+                TreeWalkResult::Abort
+            }
+        }
+    });
+    if let Some(e) = error {
+        return Err(e).context("Failed to read git-annex location log");
+    }
+    result.context("Failed to walk git-annex branch")?;
+    Ok(count)
+}
+
+/// Repositories that have a copy according to a git-annex location log.
+fn present_repositories(log: &str) -> HashSet<&str> {
+    let mut latest: HashMap<&str, (f64, bool)> = HashMap::new();
+    for line in log.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(time), Some(status), Some(uuid)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let Ok(time) = time.trim_end_matches('s').parse::<f64>() else {
+            continue;
+        };
+        if latest.get(uuid).is_none_or(|&(seen, _)| time >= seen) {
+            latest.insert(uuid, (time, status == "1"));
+        }
+    }
+    latest
+        .into_iter()
+        .filter(|(_, (_, present))| *present)
+        .map(|(uuid, _)| uuid)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    const LFS_OID: &str = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+
+    fn git(repo_path: &Path, args: &[&str]) {
+        Command::new("git")
+            .args(args)
+            .current_dir(repo_path)
+            .output()
+            .unwrap();
+    }
+
+    fn setup_repo(temp_dir: &Path) -> PathBuf {
+        let repo_path = temp_dir.join("repo");
+        let remote_path = temp_dir.join("remote.git");
+        Command::new("git")
+            .args(["init", "--bare"])
+            .arg(&remote_path)
+            .output()
+            .unwrap();
+        Command::new("git")
+            .args(["init"])
+            .arg(&repo_path)
+            .output()
+            .unwrap();
+        git(&repo_path, &["config", "user.name", "Test User"]);
+        git(&repo_path, &["config", "user.email", "test@example.com"]);
+        git(
+            &repo_path,
+            &["remote", "add", "origin", remote_path.to_str().unwrap()],
+        );
+        repo_path
+    }
+
+    #[test]
+    fn test_unpushed_lfs_objects() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = setup_repo(temp_dir.path());
+        let object_dir = repo_path.join(".git/lfs/objects/4d/7a");
+        fs::create_dir_all(&object_dir).unwrap();
+        fs::write(object_dir.join(LFS_OID), "large content").unwrap();
+        git(
+            &repo_path,
+            &["commit", "--allow-empty", "-m", "Initial commit"],
+        );
+        git(&repo_path, &["push", "-u", "origin", "main"]);
+
+        let repo = Repository::open(&repo_path).unwrap();
+        assert_eq!(
+            unpushed_large_files(&repo).unwrap(),
+            vec![
+                "1 LFS object(s) not on any remote".to_string(),
+                "LFS pre-push hook missing, pushes skip uploads".to_string(),
+            ]
+        );
+
+        fs::write(
+            repo_path.join("large.bin"),
+            format!("version https://git-lfs.github.com/spec/v1\noid sha256:{LFS_OID}\nsize 13\n"),
+        )
+        .unwrap();
+        git(&repo_path, &["add", "."]);
+        git(&repo_path, &["commit", "-m", "Add large file"]);
+        git(&repo_path, &["push"]);
+        fs::write(
+            repo_path.join(".git/hooks/pre-push"),
+            "#!/bin/sh\ngit lfs pre-push \"$@\"\n",
+        )
+        .unwrap();
+        assert!(unpushed_large_files(&repo).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unsynced_annex() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = setup_repo(temp_dir.path());
+        fs::create_dir(repo_path.join(".git/annex")).unwrap();
+        git(&repo_path, &["config", "annex.uuid", "here"]);
+
+        // Build a git-annex branch with one key only here and one also elsewhere
+        let repo = Repository::open(&repo_path).unwrap();
+        let mut inserted = Vec::new();
+        for (key, log) in [
+            ("SHA256E-s1--a.log", "1700000000.5s 1 here\n"),
+            (
+                "SHA256E-s1--b.log",
+                "1700000000s 1 here\n1700000001s 1 there\n",
+            ),
+        ] {
+            let blob = repo.blob(log.as_bytes()).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder.insert(key, blob, 0o100_644).unwrap();
+            inserted.push((key, builder.write().unwrap()));
+        }
+        let mut hash_dir = repo.treebuilder(None).unwrap();
+        for (i, (_, tree)) in inserted.iter().enumerate() {
+            hash_dir.insert(format!("d{i}"), *tree, 0o040_000).unwrap();
+        }
+        let hash_dir = hash_dir.write().unwrap();
+        let mut root = repo.treebuilder(None).unwrap();
+        root.insert("abc", hash_dir, 0o040_000).unwrap();
+        let root = repo.find_tree(root.write().unwrap()).unwrap();
+        let signature = git2::Signature::now("Test User", "test@example.com").unwrap();
+        repo.commit(
+            Some("refs/heads/git-annex"),
+            &signature,
+            &signature,
+            "update",
+            &root,
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            unpushed_large_files(&repo).unwrap(),
+            vec![
+                "git-annex branch not synced".to_string(),
+                "1 annexed file(s) only in this repository".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transfers_in_progress() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = setup_repo(temp_dir.path());
+        let common_dir = repo_path.join(".git");
+        assert!(transfers_in_progress(&common_dir).is_empty());
+
+        let lfs_tmp = repo_path.join(".git/lfs/tmp");
+        fs::create_dir_all(&lfs_tmp).unwrap();
+        fs::write(lfs_tmp.join(LFS_OID), "partial").unwrap();
+        let upload = repo_path.join(".git/annex/transfer/upload/remote-uuid");
+        fs::create_dir_all(&upload).unwrap();
+        fs::write(upload.join("SHA256E-s1--a"), "").unwrap();
+        fs::write(upload.join("lck.SHA256E-s1--a"), "").unwrap();
+        assert_eq!(
+            transfers_in_progress(&common_dir),
+            vec![
+                "git-lfs transfer in progress (1 temporary file(s))".to_string(),
+                "git-annex upload in progress (1 file(s))".to_string(),
+            ]
+        );
+    }
+}
@@ -1,33 +1,299 @@
+pub mod ack;
+pub mod audit;
+pub mod audit_log;
+pub mod check;
+pub mod clones;
+pub mod db;
+pub mod digest;
+pub mod discovery;
+pub mod events;
+pub mod explain;
+pub mod fleet;
+pub mod health;
+pub mod history;
+pub mod hooks;
+mod hosting;
+mod lfs;
+pub mod locale;
+pub mod offline;
+pub mod output;
+pub mod paths;
+pub mod policy;
+mod ref_files;
+mod refs;
+pub mod registry;
+pub mod report;
+pub mod resume;
+pub mod sample;
+pub mod scanner;
+pub mod template;
+pub mod trend;
+pub mod walk;
+
 use anyhow::{Context, Result};
-use git2::{BranchType, Repository};
-use std::path::Path;
+use git2::{Branch, BranchType, Oid, Repository};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Age after which git considers a `gc.pid` file stale, see `git help gc`.
+const GC_PID_EXPIRY: Duration = Duration::from_hours(12);
+
+pub use check::{CheckSet, Comparison};
+pub use report::RepoReport;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RepoStatus {
     Clean,
     HasUnpushed,
+    /// git-lfs or git-annex content is not pushed
+    HasUnpushedLfs,
+    /// Git notes are not pushed
+    HasUnpushedNotes,
+    /// Refs outside of `refs/heads` are not pushed
+    HasUnpushedRefs,
+    /// A git-lfs or git-annex transfer is running, so content may be half uploaded
+    TransferInProgress,
+    /// The index has unresolved merge conflicts, i.e. resolution work in progress
+    Conflicted,
+    /// A rebase, merge, cherry-pick, revert or `git am` was started but not finished
+    OperationInProgress,
     MissingHead,
 }
 
-/// Checks the status of a git repository.
+impl fmt::Display for RepoStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Clean => "clean",
+            Self::HasUnpushed => "unpushed",
+            Self::HasUnpushedLfs => "unpushed-lfs",
+            Self::HasUnpushedNotes => "unpushed-notes",
+            Self::HasUnpushedRefs => "unpushed-refs",
+            Self::TransferInProgress => "transfer-in-progress",
+            Self::Conflicted => "conflicted",
+            Self::OperationInProgress => "operation-in-progress",
+            Self::MissingHead => "missing-head",
+        })
+    }
+}
+
+/// How branches without a configured upstream are compared, like git's `push.default`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PushDefault {
+    /// Only a configured upstream counts, branches without one are unpushed
+    #[default]
+    Upstream,
+    /// Fall back to the branch of the same name on `origin`, where `git push` without
+    /// `-u` puts it with `push.default=current`
+    Current,
+}
+
+/// Describes how far `branch` is ahead of its upstream, or of the revision of
+/// [`Comparison::against`]. `ahead` is `None` if there is nothing to compare against.
+fn describe_ahead(branch: &str, ahead: Option<usize>, comparison: &Comparison) -> String {
+    match (&comparison.against, ahead) {
+        (Some(against), Some(ahead)) => {
+            format!("Branch {branch} is {ahead} commit(s) ahead of {against}")
+        }
+        (Some(against), None) => {
+            format!("Branch {branch} cannot be compared, {against} does not exist")
+        }
+        (None, Some(ahead)) => {
+            format!("Branch {branch} is {ahead} commit(s) ahead of its upstream")
+        }
+        (None, None) => format!("Branch {branch} has no upstream"),
+    }
+}
+
+/// The branch of the same name on `origin`, if `comparison` assumes
+/// [`PushDefault::Current`] and `branch` has no configured upstream.
+fn fallback_upstream<'r>(
+    repo: &'r Repository,
+    branch: &str,
+    comparison: &Comparison,
+) -> Option<Branch<'r>> {
+    if comparison.push_default != PushDefault::Current
+        || repo
+            .branch_upstream_name(&format!("refs/heads/{branch}"))
+            .is_ok()
+    {
+        return None;
+    }
+    repo.find_branch(&format!("origin/{branch}"), BranchType::Remote)
+        .ok()
+}
+
+/// Directory for persistent nasty-boii state, such as the repo registry.
+///
+/// Defaults to `nasty-boii` in the platform data directory (e.g. `~/.local/share`)
+/// and can be overridden with the `NASTY_BOII_DATA_DIR` environment variable.
+///
+/// # Errors
+/// Returns an error if no data directory can be determined for the platform.
+pub fn data_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("NASTY_BOII_DATA_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    Ok(dirs::data_dir()
+        .context("Failed to determine data directory")?
+        .join("nasty-boii"))
+}
+
+/// Checks the status of a git repository with the default [`CheckSet`].
 ///
 /// # Errors
 /// Returns an error if the repository cannot be opened or if git operations fail.
 pub fn check_repo_status(repo_path: &Path) -> Result<RepoStatus> {
-    let repo = Repository::open(repo_path).context(format!(
+    CheckSet::default().status(repo_path)
+}
+
+/// Commits the checked-out branch is ahead and behind its upstream, `None` without
+/// upstream or on a detached HEAD. The branch is compared as `checks` compare it.
+///
+/// # Errors
+/// Returns an error if the repository cannot be opened or if git operations fail.
+pub fn ahead_behind(repo_path: &Path, checks: &CheckSet) -> Result<Option<(usize, usize)>> {
+    let state = head_state(&open_repo(repo_path)?, checks.comparison())?;
+    Ok(state.ahead.zip(state.behind))
+}
+
+/// Subjects of up to `limit` commits on the checked-out branch that are not pushed,
+/// newest first. Without upstream, these are the commits on no remote branch. Empty on a
+/// detached or missing HEAD. The branch is compared as `checks` compare it.
+///
+/// # Errors
+/// Returns an error if the repository cannot be opened or if git operations fail.
+pub fn unpushed_commits(repo_path: &Path, limit: usize, checks: &CheckSet) -> Result<Vec<String>> {
+    let repo = open_repo(repo_path)?;
+    let Some(head) = repo.head().ok().filter(git2::Reference::is_branch) else {
+        return Ok(Vec::new());
+    };
+    let (Some(branch_name), Some(local_oid)) = (head.shorthand(), head.target()) else {
+        return Ok(Vec::new());
+    };
+    let mut walk = repo.revwalk().context("Failed to walk commits")?;
+    walk.push(local_oid)?;
+    match pushed_oid(&repo, branch_name, checks.comparison()) {
+        Some(pushed) => walk.hide(pushed)?,
+        None => walk.hide_glob("refs/remotes/*")?,
+    }
+    let mut subjects = Vec::new();
+    for oid in walk.take(limit) {
+        let commit = repo.find_commit(oid?)?;
+        subjects.push(commit.summary().unwrap_or_default().to_string());
+    }
+    Ok(subjects)
+}
+
+/// The commit `branch` is compared against, like in [`head_state`]: the revision of
+/// [`Comparison::against`], the branch on the push remote or the upstream.
+fn pushed_oid(repo: &Repository, branch: &str, comparison: &Comparison) -> Option<Oid> {
+    if let Some(against) = &comparison.against {
+        return Some(
+            repo.revparse_single(against)
+                .ok()?
+                .peel_to_commit()
+                .ok()?
+                .id(),
+        );
+    }
+    if let Some(push_remote) = push_remote(repo, branch) {
+        return repo
+            .find_branch(&format!("{push_remote}/{branch}"), BranchType::Remote)
+            .ok()?
+            .get()
+            .target();
+    }
+    repo.find_branch(branch, BranchType::Local)
+        .ok()?
+        .upstream()
+        .ok()
+        .or_else(|| fallback_upstream(repo, branch, comparison))?
+        .get()
+        .target()
+}
+
+/// Whether `git gc` or `git maintenance` is currently running in the repository.
+///
+/// Checking a repository while its objects are repacked can fail spuriously.
+#[must_use]
+pub fn maintenance_running(repo_path: &Path) -> bool {
+    let Ok(repo) = Repository::open(repo_path) else {
+        return false;
+    };
+    let common_dir = common_dir(&repo);
+    let gc_running = fs::metadata(common_dir.join("gc.pid"))
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age < GC_PID_EXPIRY));
+    gc_running || common_dir.join("objects").join("maintenance.lock").exists()
+}
+
+/// The git directory shared by all worktrees of a repository.
+fn common_dir(repo: &Repository) -> PathBuf {
+    let git_dir = repo.path();
+    fs::read_to_string(git_dir.join("commondir")).map_or_else(
+        |_| git_dir.to_path_buf(),
+        |common| git_dir.join(common.trim()),
+    )
+}
+
+fn open_repo(repo_path: &Path) -> Result<Repository> {
+    Repository::open(repo_path).context(format!(
         "Failed to open repository at {}",
         repo_path.display()
-    ))?;
+    ))
+}
+
+/// Status of the checked-out branch relative to its upstream.
+struct HeadState {
+    status: RepoStatus,
+    branch: Option<String>,
+    /// Commits ahead of upstream, `None` if there is no upstream to compare against
+    ahead: Option<usize>,
+    /// Commits on the upstream that are not local, `None` without upstream
+    behind: Option<usize>,
+    /// Remote pushed to instead of the upstream's, `None` without a push remote
+    push_remote: Option<String>,
+    /// Commits ahead of the branch on `push_remote`, `None` if it was never pushed there
+    push_ahead: Option<usize>,
+}
+
+impl HeadState {
+    fn new(status: RepoStatus) -> Self {
+        Self {
+            status,
+            branch: None,
+            ahead: None,
+            behind: None,
+            push_remote: None,
+            push_ahead: None,
+        }
+    }
+}
+
+fn head_state(repo: &Repository, comparison: &Comparison) -> Result<HeadState> {
+    // Most branches are where their upstream is, which the ref files tell faster than
+    // looking up references and config through git2
+    if comparison.against.is_none() {
+        if let Some(branch) = ref_files::branch_in_sync(repo.path(), &common_dir(repo)) {
+            let mut state = HeadState::new(RepoStatus::Clean);
+            state.branch = Some(branch);
+            state.ahead = Some(0);
+            state.behind = Some(0);
+            return Ok(state);
+        }
+    }
 
     // Get the current branch
     let Ok(head) = repo.head() else {
         // Failed to get HEAD (unborn or missing)
-        return Ok(RepoStatus::MissingHead);
+        return Ok(HeadState::new(RepoStatus::MissingHead));
     };
 
     if !head.is_branch() {
         // Not on a branch (detached HEAD), skip
-        return Ok(RepoStatus::Clean);
+        return Ok(HeadState::new(RepoStatus::Clean));
     }
 
     let branch_name = head.shorthand().context("Failed to get branch name")?;
@@ -36,39 +302,84 @@ pub fn check_repo_status(repo_path: &Path) -> Result<RepoStatus> {
         .find_branch(branch_name, BranchType::Local)
         .context("Failed to find local branch")?;
 
-    // Get the upstream branch
-    let Ok(upstream) = branch.upstream() else {
-        // No upstream branch configured, consider it as having unpushed changes
-        // if there are any commits
-        return Ok(RepoStatus::HasUnpushed);
-    };
+    let mut state = HeadState::new(RepoStatus::Clean);
+    state.branch = Some(branch_name.to_string());
 
-    // Get the local and remote commit OIDs
     let local_oid = branch
         .get()
         .target()
         .context("Failed to get local branch target")?;
 
-    let remote_oid = upstream
-        .get()
-        .target()
-        .context("Failed to get remote branch target")?;
+    if let Some(against) = &comparison.against {
+        let against_oid = repo
+            .revparse_single(against)
+            .and_then(|object| object.peel_to_commit())
+            .ok()
+            .map(|commit| commit.id());
+        if let Some(against_oid) = against_oid {
+            let (ahead, behind) = ahead_behind_of(repo, local_oid, against_oid)?;
+            state.ahead = Some(ahead);
+            state.behind = Some(behind);
+        }
+        if state.ahead != Some(0) {
+            state.status = RepoStatus::HasUnpushed;
+        }
+        return Ok(state);
+    }
 
-    // Check if the branches point to different commits
-    if local_oid == remote_oid {
-        return Ok(RepoStatus::Clean);
+    // Get the upstream branch
+    let upstream = branch
+        .upstream()
+        .ok()
+        .or_else(|| fallback_upstream(repo, branch_name, comparison));
+    if let Some(upstream) = upstream {
+        let remote_oid = upstream
+            .get()
+            .target()
+            .context("Failed to get remote branch target")?;
+        let (ahead, behind) = ahead_behind_of(repo, local_oid, remote_oid)?;
+        state.ahead = Some(ahead);
+        state.behind = Some(behind);
     }
 
-    // Check if local is ahead of remote
-    let (ahead, _behind) = repo
-        .graph_ahead_behind(local_oid, remote_oid)
-        .context("Failed to calculate ahead/behind")?;
+    // In triangular workflows, pushes go to another remote than the upstream's, e.g. a
+    // fork, so that is where the commits need to be
+    if let Some(push_remote) = push_remote(repo, branch_name) {
+        if let Ok(pushed) =
+            repo.find_branch(&format!("{push_remote}/{branch_name}"), BranchType::Remote)
+        {
+            if let Some(pushed_oid) = pushed.get().target() {
+                state.push_ahead = Some(ahead_behind_of(repo, local_oid, pushed_oid)?.0);
+            }
+        }
+        state.push_remote = Some(push_remote);
+        if state.push_ahead != Some(0) {
+            state.status = RepoStatus::HasUnpushed;
+        }
+    } else if state.ahead != Some(0) {
+        // No upstream branch configured, consider it as having unpushed changes
+        state.status = RepoStatus::HasUnpushed;
+    }
+    Ok(state)
+}
 
-    if ahead > 0 {
-        Ok(RepoStatus::HasUnpushed)
-    } else {
-        Ok(RepoStatus::Clean)
+/// Commits reachable from `local` but not from `remote`, and the other way round.
+fn ahead_behind_of(repo: &Repository, local: Oid, remote: Oid) -> Result<(usize, usize)> {
+    if local == remote {
+        return Ok((0, 0));
     }
+    repo.graph_ahead_behind(local, remote)
+        .context("Failed to calculate ahead/behind")
+}
+
+/// The remote `branch` is pushed to if it differs from its upstream's, from
+/// `branch.<name>.pushRemote` or `remote.pushDefault`.
+fn push_remote(repo: &Repository, branch: &str) -> Option<String> {
+    let config = repo.config().ok()?.snapshot().ok()?;
+    config
+        .get_string(&format!("branch.{branch}.pushRemote"))
+        .or_else(|_| config.get_string("remote.pushDefault"))
+        .ok()
 }
 
 #[cfg(test)]
@@ -321,4 +632,14 @@ mod tests {
         let status = check_repo_status(&repo_path).unwrap();
         assert_eq!(status, RepoStatus::Clean);
     }
+
+    #[test]
+    fn test_maintenance_running() {
+        let temp_dir = setup_test_repo("maintained");
+        let repo_path = temp_dir.path().join("maintained");
+        assert!(!maintenance_running(&repo_path));
+
+        std::fs::write(repo_path.join(".git").join("gc.pid"), "1234 localhost").unwrap();
+        assert!(maintenance_running(&repo_path));
+    }
 }
@@ -0,0 +1,159 @@
+//! Locale-aware formatting of numbers and dates for human-readable output.
+//!
+//! The locale is taken from the POSIX environment variables (`LC_ALL`, then
+//! `LC_NUMERIC` or `LC_TIME`, then `LANG`). Only the common conventions are covered:
+//! unknown locales and `C`/`POSIX` fall back to plain numbers and ISO 8601 dates.
+//! Machine-readable formats always use [`HumanFormat::iso`].
+
+use chrono::{DateTime, Local, Utc};
+use std::env;
+
+/// Number and date conventions for output meant to be read by people.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HumanFormat {
+    /// Separator between groups of thousands, `None` for plain numbers
+    thousands: Option<char>,
+    /// strftime format for dates
+    date_format: &'static str,
+    /// Show dates in UTC instead of the local time zone
+    utc: bool,
+}
+
+impl HumanFormat {
+    /// Plain numbers and ISO 8601 dates in UTC, for reproducible output.
+    #[must_use]
+    pub fn iso() -> Self {
+        Self {
+            thousands: None,
+            date_format: "%Y-%m-%d",
+            utc: true,
+        }
+    }
+
+    /// Conventions of the locale from the environment, with dates in the local time zone.
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self {
+            thousands: thousands_separator(&locale_of("LC_NUMERIC")),
+            date_format: date_format(&locale_of("LC_TIME")),
+            utc: false,
+        }
+    }
+
+    /// Shows dates in UTC instead of the local time zone.
+    #[must_use]
+    pub fn utc(mut self) -> Self {
+        self.utc = true;
+        self
+    }
+
+    /// Formats dates as ISO 8601 (`YYYY-MM-DD`) regardless of the locale.
+    #[must_use]
+    pub fn iso_dates(mut self) -> Self {
+        self.date_format = "%Y-%m-%d";
+        self
+    }
+
+    /// Formats a count, e.g. `12,345` in English locales.
+    #[must_use]
+    pub fn number(&self, n: usize) -> String {
+        let digits = n.to_string();
+        let Some(separator) = self.thousands else {
+            return digits;
+        };
+        let mut out = String::new();
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                out.push(separator);
+            }
+            out.push(digit);
+        }
+        out
+    }
+
+    /// Formats a date given in seconds since the Unix epoch, empty if out of range.
+    #[must_use]
+    pub fn date(&self, timestamp: i64) -> String {
+        let Some(date) = DateTime::<Utc>::from_timestamp(timestamp, 0) else {
+            return String::new();
+        };
+        if self.utc {
+            date.format(self.date_format).to_string()
+        } else {
+            date.with_timezone(&Local)
+                .format(self.date_format)
+                .to_string()
+        }
+    }
+}
+
+/// The locale for a category, e.g. `de_DE.UTF-8`, following POSIX precedence.
+fn locale_of(category: &str) -> String {
+    ["LC_ALL", category, "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default()
+}
+
+/// Splits `de_DE.UTF-8@euro` into language and territory, `("de", "DE")`.
+fn language_and_territory(locale: &str) -> (&str, &str) {
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    locale.split_once(['_', '-']).unwrap_or((locale, ""))
+}
+
+fn thousands_separator(locale: &str) -> Option<char> {
+    match language_and_territory(locale) {
+        ("de" | "it", "CH") => Some('\''),
+        ("en" | "ja" | "ko" | "zh" | "he" | "th", _) => Some(','),
+        ("de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr" | "el", _) => Some('.'),
+        ("fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "no" | "uk" | "hu", _) => {
+            Some('\u{a0}')
+        }
+        _ => None,
+    }
+}
+
+fn date_format(locale: &str) -> &'static str {
+    match language_and_territory(locale) {
+        ("en", "US") => "%m/%d/%Y",
+        ("en", "GB" | "AU" | "NZ" | "IE" | "IN") | ("fr" | "es" | "it" | "pt" | "el", _) => {
+            "%d/%m/%Y"
+        }
+        ("de" | "ru" | "pl" | "cs" | "sk" | "fi" | "nb" | "no" | "uk" | "tr", _) => "%d.%m.%Y",
+        ("nl" | "da", _) => "%d-%m-%Y",
+        ("ja" | "zh", _) => "%Y/%m/%d",
+        _ => "%Y-%m-%d",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number() {
+        let format = HumanFormat {
+            thousands: thousands_separator("en_US.UTF-8"),
+            ..HumanFormat::iso()
+        };
+        assert_eq!(format.number(0), "0");
+        assert_eq!(format.number(999), "999");
+        assert_eq!(format.number(1_234_567), "1,234,567");
+        assert_eq!(HumanFormat::iso().number(1_234_567), "1234567");
+        assert_eq!(thousands_separator("de_DE.UTF-8"), Some('.'));
+        assert_eq!(thousands_separator("C"), None);
+    }
+
+    #[test]
+    fn test_date() {
+        let format = HumanFormat {
+            date_format: date_format("de_DE.UTF-8"),
+            ..HumanFormat::iso()
+        };
+        assert_eq!(format.date(86_400 * 31), "01.02.1970");
+        assert_eq!(format.clone().iso_dates().date(0), "1970-01-01");
+        assert_eq!(date_format("en_US"), "%m/%d/%Y");
+        assert_eq!(date_format("POSIX"), "%Y-%m-%d");
+    }
+}
@@ -0,0 +1,138 @@
+//! Finding repositories through the system file index instead of walking.
+//!
+//! `locate` (mlocate/plocate) and macOS Spotlight (`mdfind`) list every `.git` entry on
+//! disk almost instantly. The index can be stale, so candidates are checked on disk,
+//! and repositories created since the index was last updated are missed. The same
+//! filters as for walking apply: hidden directories, exclude patterns, boundaries and
+//! `--nested`.
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use ignore::Match;
+use nasty_boii::paths;
+use nasty_boii::walk::{is_repo, Nested, WalkOptions};
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// How repositories below the search path are found.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Discovery {
+    /// Walk the directory tree
+    #[default]
+    Walk,
+    /// Query the locate database (mlocate or plocate), walk if it is unavailable
+    Locate,
+    /// Query Spotlight on macOS, walk if it is unavailable
+    Mdfind,
+}
+
+/// Repositories below `root` according to the file index of `discovery`.
+///
+/// Paths are returned below `root` as given, like the walker does.
+///
+/// # Errors
+/// Returns an error if the index cannot be queried, e.g. because it is not installed.
+pub fn find_repos(
+    discovery: Discovery,
+    root: &Path,
+    options: &WalkOptions,
+) -> Result<Vec<PathBuf>> {
+    let canonical_root =
+        paths::canonicalize(root).context(format!("Failed to resolve path: {}", root.display()))?;
+    let entries = match discovery {
+        Discovery::Walk => bail!("Walking does not use a file index"),
+        Discovery::Locate => query(Command::new("locate").args(["-0", "-b", r"\.git"]))?,
+        Discovery::Mdfind => query(
+            Command::new("mdfind")
+                .arg("-0")
+                .arg("-onlyin")
+                .arg(&canonical_root)
+                .arg("kMDItemFSName == '.git'"),
+        )?,
+    };
+
+    let candidates: BTreeSet<PathBuf> = entries
+        .iter()
+        .filter(|entry| entry.file_name().is_some_and(|name| name == ".git"))
+        .filter_map(|entry| entry.parent()?.strip_prefix(&canonical_root).ok())
+        .filter(|relative| !is_hidden(relative, options.hidden))
+        .map(Path::to_path_buf)
+        .collect();
+
+    Ok(candidates
+        .iter()
+        .filter(|relative| {
+            let nested = relative
+                .ancestors()
+                .skip(1)
+                .any(|ancestor| candidates.contains(ancestor));
+            match options.nested {
+                Nested::Include => true,
+                Nested::Skip => !nested,
+                Nested::Only => nested,
+            }
+        })
+        .filter(|relative| {
+            let canonical = canonical_root.join(relative);
+            !options
+                .boundaries
+                .iter()
+                .any(|boundary| canonical.starts_with(boundary))
+        })
+        .filter(|relative| {
+            options
+                .include_only
+                .is_none_or(|include_only| include_only.includes(&root.join(relative)))
+        })
+        .map(|relative| root.join(relative))
+        // The index may be stale
+        .filter(|path| is_repo(path))
+        .filter(|path| {
+            let Some(gi) = options.gitignore else {
+                return true;
+            };
+            match gi.matched_path_or_any_parents(path, true) {
+                Match::Ignore(glob) => {
+                    if let Some(excluded) = options.excluded {
+                        excluded.push(path, glob);
+                    }
+                    false
+                }
+                Match::None | Match::Whitelist(_) => true,
+            }
+        })
+        .collect())
+}
+
+/// Runs an index query printing NUL-separated paths.
+fn query(command: &mut Command) -> Result<Vec<PathBuf>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .context(format!("Failed to run {program}"))?;
+    // locate exits with 1 and no message if nothing matches
+    if !output.status.success() && !output.stderr.is_empty() {
+        bail!(
+            "{program} failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output
+        .stdout
+        .split(|&byte| byte == 0)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| PathBuf::from(String::from_utf8_lossy(entry).into_owned()))
+        .collect())
+}
+
+/// Whether a relative path goes through a directory that walking skips: a hidden one,
+/// or only `.git` with `--hidden`.
+fn is_hidden(relative: &Path, walk_hidden: bool) -> bool {
+    relative.components().any(|component| match component {
+        Component::Normal(name) if walk_hidden => name == ".git",
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
@@ -0,0 +1,318 @@
+//! Diagnostic logging, separate from the results printed by the output formats.
+
+use anyhow::{Context as _, Result};
+use chrono::{SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Record};
+use tracing::{Event, Id, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Scope};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line, with the fields of all enclosing spans
+    Json,
+}
+
+/// Where log messages are written.
+pub enum LogTarget {
+    Stdout,
+    /// For modes where stdout carries a protocol
+    Stderr,
+    /// Keeps stdout free for the results
+    File(RotatingFile),
+}
+
+/// Installs the global subscriber writing logs in `format` to `target`.
+pub fn init(filter: EnvFilter, format: LogFormat, target: LogTarget) {
+    let layer = match target {
+        LogTarget::Stdout => layer(format, io::stdout, true),
+        LogTarget::Stderr => layer(format, io::stderr, true),
+        LogTarget::File(file) => layer(format, file, false),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(layer)
+        .init();
+}
+
+type BoxedLayer<S> = Box<dyn Layer<S> + Send + Sync>;
+
+fn layer<S, W>(format: LogFormat, make_writer: W, ansi: bool) -> BoxedLayer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_target(false)
+            .with_ansi(ansi)
+            .with_writer(make_writer)
+            .boxed(),
+        LogFormat::Json => JsonLayer::new(make_writer).boxed(),
+    }
+}
+
+/// Log file that is moved to `<path>.1` once it grows past a maximum size, replacing
+/// the previous one, so scheduled scans don't fill the disk.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    state: Mutex<FileState>,
+}
+
+struct FileState {
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Opens `path` for appending.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be opened.
+    pub fn open(path: &Path, max_size: u64) -> Result<Self> {
+        let file = append(path)?;
+        let size = file.metadata().map_or(0, |metadata| metadata.len());
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            state: Mutex::new(FileState { file, size }),
+        })
+    }
+
+    /// Path of the previous log file.
+    fn rotated_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".1");
+        PathBuf::from(path)
+    }
+
+    fn rotate(&self, state: &mut FileState) -> io::Result<()> {
+        fs::rename(&self.path, self.rotated_path())?;
+        state.file = append(&self.path).map_err(io::Error::other)?;
+        state.size = 0;
+        Ok(())
+    }
+}
+
+fn append(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open log file {}", path.display()))
+}
+
+impl<'a> MakeWriter<'a> for RotatingFile {
+    type Writer = RotatingWriter<'a>;
+
+    /// Rotates between events, so a message is never split across files.
+    fn make_writer(&'a self) -> Self::Writer {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.size >= self.max_size {
+            // Logging must never fail the scan, keep writing to the full file instead
+            let _ = self.rotate(&mut state);
+        }
+        RotatingWriter { state }
+    }
+}
+
+/// Writes one event to a [`RotatingFile`], holding its lock until the event is written.
+pub struct RotatingWriter<'a> {
+    state: MutexGuard<'a, FileState>,
+}
+
+impl Write for RotatingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.state.file.write(buf)?;
+        self.state.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.file.flush()
+    }
+}
+
+/// Parses sizes like `512K`, `10M` or `1G`, in bytes without a suffix.
+///
+/// # Errors
+/// Returns an error message if `size` is not a number with an optional suffix.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let (number, unit) = match size.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => size.split_at(i),
+        None => (size, ""),
+    };
+    let multiplier: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return Err(format!("invalid size unit {unit:?}, expected K, M or G")),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("invalid size {size:?}, expected e.g. 10M"))
+}
+
+/// Writes events as JSON lines like
+/// `{"timestamp":"…","level":"WARN","message":"…","fields":{…},"spans":[{"name":"repo",…}]}`.
+pub struct JsonLayer<W> {
+    make_writer: W,
+}
+
+impl<W> JsonLayer<W> {
+    pub fn new(make_writer: W) -> Self {
+        Self { make_writer }
+    }
+}
+
+/// Fields of a span, kept in its extensions so events can include them.
+struct SpanFields(Map<String, Value>);
+
+impl<S, W> Layer<S> for JsonLayer<W>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + 'static,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = JsonVisitor::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(SpanFields(fields.0));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+            let mut visitor = JsonVisitor(std::mem::take(fields));
+            values.record(&mut visitor);
+            *fields = visitor.0;
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = JsonVisitor::default();
+        event.record(&mut fields);
+        let mut fields = fields.0;
+        let message = fields.remove("message").unwrap_or(Value::Null);
+
+        let spans: Vec<Value> = ctx
+            .event_scope(event)
+            .into_iter()
+            .flat_map(Scope::from_root)
+            .map(|span| {
+                let mut object = span
+                    .extensions()
+                    .get::<SpanFields>()
+                    .map(|SpanFields(fields)| fields.clone())
+                    .unwrap_or_default();
+                object.insert("name".to_string(), span.name().into());
+                Value::Object(object)
+            })
+            .collect();
+
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".to_string(),
+            Utc::now()
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+                .into(),
+        );
+        line.insert(
+            "level".to_string(),
+            event.metadata().level().as_str().into(),
+        );
+        line.insert("message".to_string(), message);
+        line.insert("fields".to_string(), Value::Object(fields));
+        line.insert("spans".to_string(), Value::Array(spans));
+
+        let mut writer = self.make_writer.make_writer();
+        // Logging must never fail the scan
+        let _ = writeln!(writer, "{}", Value::Object(line));
+    }
+}
+
+/// Collects field values as JSON, keeping numbers and booleans typed.
+#[derive(Default)]
+struct JsonVisitor(Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}").into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("100"), Ok(100));
+        assert_eq!(parse_size("512K"), Ok(512 * 1024));
+        assert_eq!(parse_size("10m"), Ok(10 * 1024 * 1024));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn test_rotating_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("nasty-boii.log");
+        let file = RotatingFile::open(&path, 10).unwrap();
+
+        file.make_writer().write_all(b"first event\n").unwrap();
+        file.make_writer().write_all(b"second\n").unwrap();
+        file.make_writer().write_all(b"third\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\nthird\n");
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("nasty-boii.log.1")).unwrap(),
+            "first event\n"
+        );
+    }
+}
@@ -1,17 +1,70 @@
-use anyhow::{Context, Result};
-use clap::Parser;
-use ignore::gitignore::{Gitignore, GitignoreBuilder};
-use nasty_boii::{check_repo_status, RepoStatus};
+use anyhow::{bail, Context, Result};
+use chrono::{NaiveDate, Utc};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use ignore::gitignore::Gitignore;
+use nasty_boii::ack::{AckList, Acks};
+use nasty_boii::audit::AuditReport;
+use nasty_boii::audit_log::AuditLog;
+#[cfg(not(feature = "read-only"))]
+use nasty_boii::audit_log::{BranchState, Entry};
+use nasty_boii::check::{CheckResults, ScriptCheck};
+use nasty_boii::clones;
+use nasty_boii::db::Db;
+use nasty_boii::digest::{Digest, Digests};
+use nasty_boii::discovery;
+use nasty_boii::events::{Event, EventBus};
+use nasty_boii::fleet::{self, Fleet, Remote};
+use nasty_boii::history::{History, ScanRecord};
+use nasty_boii::hooks;
+use nasty_boii::locale::HumanFormat;
+use nasty_boii::offline::OfflineRoot;
+use nasty_boii::policy::Policy;
+use nasty_boii::registry::Registry;
+use nasty_boii::resume::{Checkpoint, Checkpoints, Progress, SubtreeTracker};
+use nasty_boii::sample::Sampler;
+use nasty_boii::template::Template;
+use nasty_boii::trend;
+use nasty_boii::walk::{
+    canonicalize_boundaries, find_repos, load_gitignore, Excluded, IncludeOnly, Nested,
+    Unscannable, WalkCounts, WalkOptions,
+};
+use nasty_boii::{
+    explain, health, maintenance_running, output, paths, report, CheckSet, Comparison, PushDefault,
+    RepoReport, RepoStatus,
+};
 use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use tracing::{debug, info, warn};
-use tracing_subscriber::{fmt, EnvFilter};
-use walkdir::WalkDir;
+use std::process::{ExitCode, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, field, info, info_span, warn};
+use tracing_subscriber::EnvFilter;
+
+use locate::Discovery;
+use logging::{LogFormat, LogTarget, RotatingFile};
+
+mod locate;
+mod logging;
+mod platform;
+mod rpc;
+mod serve;
 
 #[derive(Parser, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 #[command(name = "nasty-boii")]
 #[command(about = "Finds git repos that have changes that are not yet pushed", long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Directory to search (defaults to current directory)
     #[arg(default_value = ".")]
     path: PathBuf,
@@ -24,6 +77,18 @@ struct Args {
     #[arg(short = 'l', long, default_value = "warn")]
     log_level: String,
 
+    /// Format of the log messages, separate from --format for the results
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Write log messages to this file instead of stdout
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// Size at which the log file is moved to <PATH>.1, e.g. 512K, 10M or 1G
+    #[arg(long, value_name = "SIZE", default_value = "10M", value_parser = logging::parse_size, requires = "log_file")]
+    log_max_size: u64,
+
     /// Enable verbose output (equivalent to --log-level info)
     #[arg(short, long)]
     verbose: bool,
@@ -32,32 +97,831 @@ struct Args {
     #[arg(long)]
     missing_head: bool,
 
-    /// Path to file containing exclude patterns (gitignore-style, one per line)
+    /// Path to file containing exclude patterns (gitignore-style, one per line, repeatable)
+    ///
+    /// Later files take precedence over earlier ones and over `--default-excludes`,
+    /// e.g. `!node_modules/` in a file brings `node_modules` back.
+    #[arg(long)]
+    exclude_from: Vec<PathBuf>,
+
+    /// Exclude directories that hold dependencies and caches rather than your own
+    /// repositories: `node_modules`, `.cargo`, `.cache`, `go/pkg` and Python virtualenvs
+    #[arg(long)]
+    default_excludes: bool,
+
+    /// Exclude what your global gitignore ignores (`core.excludesFile`, by default
+    /// `~/.config/git/ignore`), before `--default-excludes` and `--exclude-from`
+    #[arg(long)]
+    global_gitignore: bool,
+
+    /// Walk into hidden directories, e.g. to find repos in `~/.dotfiles-repos`
+    ///
+    /// `.git` directories are never walked into.
+    #[arg(long)]
+    hidden: bool,
+
+    /// Only report repositories matching this gitignore-style pattern, relative to the
+    /// search path, e.g. 'src/**' (repeatable, any matches)
+    ///
+    /// The walker doesn't descend into directories that cannot hold a match. Excludes
+    /// still apply on top.
+    #[arg(long, value_name = "GLOB", conflicts_with_all = ["registered", "paths_from"])]
+    include_only: Vec<String>,
+
+    /// Also check the repository with this git directory, with the directory after the
+    /// colon as its working directory (repeatable)
+    ///
+    /// For bare repositories whose files live elsewhere, like dotfiles managed with
+    /// `git --git-dir=$HOME/.cfg --work-tree=$HOME`: `--extra-gitdir $HOME/.cfg:$HOME`.
+    /// Without a working directory, `core.worktree` of the repository is used, if set.
+    /// On Windows, drive letters are fine: `--extra-gitdir C:\cfg:C:\home`.
+    #[arg(long, value_name = "PATH[:WORK_TREE]", value_parser = parse_extra_gitdir)]
+    extra_gitdir: Vec<ExtraGitDir>,
+
+    /// Check the repository paths listed in FILE (one per line, `-` for stdin) instead of walking
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["exclude_from", "default_excludes"])]
+    paths_from: Option<PathBuf>,
+
+    /// Check the repositories in the registry instead of walking
+    #[arg(long, conflicts_with_all = ["exclude_from", "default_excludes", "paths_from"])]
+    registered: bool,
+
+    /// Only check registered repositories with this label (repeatable, any matches)
+    #[arg(long, value_name = "LABEL")]
+    label: Vec<String>,
+
+    /// Never descend into this directory, even if it is below the search path (repeatable)
+    #[arg(long, value_name = "PATH")]
+    boundary: Vec<PathBuf>,
+
+    /// How to handle repositories nested inside other repositories, e.g. vendored clones
+    #[arg(long, value_enum, default_value_t = Nested::Include)]
+    nested: Nested,
+
+    /// If the search path is a repository or inside one, check only that repository
+    /// instead of also walking below the search path
+    #[arg(long, conflicts_with_all = ["registered", "paths_from"])]
+    stop_at_repo: bool,
+
+    /// Walk in a thread of its own, at most N found repos ahead of the checks
+    ///
+    /// By default, checking threads walk on whenever they need the next repo. A queue
+    /// keeps walking while all threads are busy checking, but no further than N repos,
+    /// so slow checks don't leave thousands of found repos waiting in memory.
+    #[arg(long, value_name = "N")]
+    queue_size: Option<usize>,
+
+    /// Order in which repos are checked and, in plain output, printed
+    ///
+    /// `mtime` gets recently used repos, the most likely to be nasty, on screen first.
+    /// Except for `none`, checking starts once all repos are found.
+    #[arg(long, value_enum, default_value_t = Priority::None)]
+    priority: Priority,
+
+    /// Order of the reported repos, oldest first for times, e.g. to spot abandoned work
+    ///
+    /// Repos without a commit or push come first. Plain output is printed once the scan
+    /// is done, unless sorted by path.
+    #[arg(long, value_enum, default_value_t = SortBy::Path)]
+    sort: SortBy,
+
+    /// How to find repos below the search path: walk it, or query the locate database
+    /// or Spotlight for `.git` entries, which is much faster for whole disks but misses
+    /// repos created since the index was last updated
+    #[arg(long, value_enum, default_value_t = Discovery::Walk)]
+    discovery: Discovery,
+
+    /// Checks to run, in order (default: conflicts,ahead,lfs,transfers)
+    ///
+    /// Available checks: conflicts, ahead, lfs, transfers, notes, refs, dirty, stashes,
+    /// tags, default-branch, shallow, on-host.
+    /// The status of a repo is that of the first check that finds something.
+    #[arg(long, value_delimiter = ',', value_name = "CHECK,...")]
+    checks: Vec<String>,
+
+    /// Run SCRIPT for each repo as an additional check (repeatable)
+    ///
+    /// The script runs in the repo with its path as argument and a JSON object with
+    /// path, branch and ahead on stdin. A non-zero exit status flags the repo, and the
+    /// script's output is shown as the finding, e.g. with `--template '{path}: {findings}'`.
+    #[arg(long, value_name = "SCRIPT")]
+    hook_check: Vec<PathBuf>,
+
+    /// Also list repos whose git notes (refs/notes/*) are not on any remote
+    ///
+    /// Notes are compared against notes fetched into refs/notes/remotes/<remote>/* or
+    /// refs/remotes/<remote>/notes/*, e.g. with
+    /// `git config --add remote.origin.fetch '+refs/notes/*:refs/notes/remotes/origin/*'`.
+    #[arg(long)]
+    include_notes: bool,
+
+    /// Also list repos with any local ref outside refs/heads that no remote-tracking ref contains
+    ///
+    /// Expert mode for tool-generated state such as refs/replace/* or git-annex refs.
+    /// Tags are included, which makes this slower on repos with many tags.
+    #[arg(long)]
+    all_refs: bool,
+
+    /// Compare branches without upstream against the same branch on origin, if it exists
+    ///
+    /// With `current`, branches pushed without `-u` (e.g. with `push.default=current`)
+    /// are not reported as lacking an upstream.
+    #[arg(long, value_name = "MODE", default_value = "upstream")]
+    assume_push_default: AssumePushDefault,
+
+    /// Compare the checked-out branch against REV, e.g. origin/main, instead of its upstream
+    ///
+    /// A repo is listed if its branch has commits that REV doesn't contain, e.g. to find
+    /// feature branches that were never merged into mainline. Repos without REV are
+    /// listed too, since nothing shows the work is there.
+    #[arg(long, value_name = "REV")]
+    against: Option<String>,
+
+    /// Skip repos that can't be written to, e.g. on read-only mounts of old backups
+    ///
+    /// Nothing can be committed or pushed from them, so they only clutter the results.
+    #[arg(long)]
+    skip_readonly: bool,
+
+    /// Also report repos that could use git gc, e.g. with many loose objects or packs
+    ///
+    /// These advisories are printed to stderr after the results and don't make a repo
+    /// nasty.
+    #[arg(long)]
+    health: bool,
+
+    /// Also list shallow and partial clones, which cannot serve as a backup of the history
+    #[arg(long)]
+    flag_shallow: bool,
+
+    /// Also ask GitHub and GitLab whether the upstream tip exists on the server
+    ///
+    /// Catches commits lost to force-pushes that the remote-tracking refs still show.
+    /// Private repos need `GITHUB_TOKEN` or `GITLAB_TOKEN`.
+    #[arg(long)]
+    verify_on_host: bool,
+
+    /// How often to come back to repos where git gc or maintenance is running
+    ///
+    /// Such repos are skipped and re-checked at the end of the scan. Once the retries are
+    /// used up they are checked anyway.
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    max_retries: usize,
+
+    /// Lower CPU and IO priority so background scans don't slow down the machine
+    #[arg(long)]
+    nice: bool,
+
+    /// Use fewer threads and walk more slowly when running on battery
+    #[arg(long)]
+    throttle_on_battery: bool,
+
+    /// Print each repo using a template, e.g. '{path}\t{branch}\t{ahead}'
+    #[arg(
+        long,
+        conflicts_with_all = ["format", "count"],
+        long_help = "Print each repo using a template, e.g. '{path}\\t{branch}\\t{ahead}'\n\n\
+            Placeholders: {path}, {status}, {branch}, {ahead}, {dirty}, {conflicted}, {operation}, {shallow}, {partial}, {sparse}, {last_commit}, {last_push}, {owner}, {lfs}, {notes}, {refs}, {findings}, {remote}, {remote_url}, {identity}, {push_remote}, {push_ahead}, {local_only_branches}"
+    )]
+    template: Option<String>,
+
+    /// Append commits ahead and behind the upstream to each path, e.g. `+2 -1`
+    #[arg(long, conflicts_with_all = ["format", "template", "count", "exec", "group_by"])]
+    show_counts: bool,
+
+    /// List the subjects of up to N unpushed commits beneath each path, or in JSON as
+    /// `unpushed_commits`, e.g. `--show-commits=10` [default: 5]
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "5",
+        conflicts_with_all = ["template", "count", "exec", "group_by", "print0"]
+    )]
+    show_commits: Option<usize>,
+
+    /// End paths with NUL instead of a newline, for `xargs -0` and paths with newlines
+    #[arg(long, conflicts_with_all = ["format", "template", "count", "exec", "group_by", "show_counts"])]
+    print0: bool,
+
+    /// Inspect and print nasty repos 256 at a time instead of all at once, for scans
+    /// with many thousands of them
+    ///
+    /// Only bounds the memory for the details of the repos, such as branches, remotes
+    /// and findings. The paths of all nasty repos, and their sort keys with `--sort`,
+    /// are still kept and sorted in memory; nothing is spilled to disk. Output stays
+    /// the same. Works with plain, JSON and CSV output, templates and `--group-by`.
+    #[arg(long)]
+    low_memory: bool,
+
+    /// Only print the number of repos that would be listed
+    #[arg(long, conflicts_with = "format")]
+    count: bool,
+
+    /// Print the number of repos per remote host, organization or owning user instead of
+    /// the repos
+    #[arg(long, value_name = "KEY", conflicts_with_all = ["format", "template", "count", "exec"])]
+    group_by: Option<GroupBy>,
+
+    /// Print each project once with the paths of all its nasty clones
+    ///
+    /// Clones of the same project have the same identity: the URL of `origin` without
+    /// scheme, user and `.git` suffix, e.g. github.com/acme/app.
+    #[arg(long, value_name = "KEY", conflicts_with_all = ["format", "template", "count", "exec", "group_by"])]
+    dedupe_by: Option<DedupeBy>,
+
+    /// With --count, print the number of repos per status, then what the walker visited
+    /// and skipped
+    #[arg(long, requires = "count")]
+    all: bool,
+
+    /// Print nothing and exit with 1 as soon as a nasty repo is found, 0 if there is none
+    #[arg(long, conflicts_with_all = ["format", "template", "count", "exec", "open_with"])]
+    any: bool,
+
+    /// Run COMMAND for each repo instead of listing it, e.g. 'git -C {} push'
+    ///
+    /// `{}` is replaced by the quoted repo path, or the path is appended if there is no
+    /// `{}`. The command runs through the shell. A summary is printed at the end and the
+    /// exit status is 1 if any command failed. Commands can change repos, so this needs
+    /// --allow-writes.
+    #[arg(long, value_name = "COMMAND", conflicts_with_all = ["template", "count", "format"])]
+    exec: Option<String>,
+
+    /// Open each repo with COMMAND after listing it, e.g. 'code' or 'code {}'
+    ///
+    /// Like --exec, `{}` is replaced by the quoted repo path or the path is appended.
+    /// The opener is started in the background without waiting for it, so use a GUI
+    /// editor or a terminal emulator rather than a terminal editor.
+    #[arg(long, value_name = "COMMAND", conflicts_with = "exec")]
+    open_with: Option<String>,
+
+    /// Make --exec commands fail instead of asking for credentials
+    ///
+    /// For scheduled runs, e.g. 'git -C {} push' from cron: git and ssh cannot prompt
+    /// on the terminal or through an askpass program, so a repo that needs a password
+    /// counts as failed instead of blocking the run. Without it, commands can ask
+    /// through `GIT_ASKPASS` or `SSH_ASKPASS`, which ssh prefers over the terminal.
+    #[arg(long, requires = "exec")]
+    non_interactive: bool,
+
+    /// Make sure nothing modifies the repos, which is the default
+    ///
+    /// nasty-boii only reads repos. --exec is the only way a scan can change them and
+    /// needs --allow-writes. This makes read-only mode explicit, e.g. in wrappers that
+    /// must never write. Builds with the `read-only` feature leave --exec out entirely.
+    #[arg(long, conflicts_with_all = ["exec", "allow_writes"])]
+    read_only: bool,
+
+    /// Turn off read-only mode, so --exec commands can run
+    #[arg(long, requires = "exec")]
+    allow_writes: bool,
+
+    /// Number of --exec commands to run at the same time
+    #[arg(long, value_name = "N", default_value_t = 4, requires = "exec")]
+    exec_jobs: usize,
+
+    /// Also list repos that were acknowledged with `nasty-boii ack`
     #[arg(long)]
-    exclude_from: Option<PathBuf>,
+    show_acked: bool,
+
+    /// Fail if any directory below the search path cannot be read
+    ///
+    /// Unreadable directories, e.g. without permission on mounted container or VM disks,
+    /// are always listed on stderr after the scan. With this flag the scan also fails.
+    #[arg(long)]
+    strict_walk: bool,
+
+    /// List repositories pruned by an exclude pattern on stderr, with the pattern and its file
+    #[arg(long)]
+    why_excluded: bool,
+
+    /// Answer JSON-RPC requests on stdin/stdout (scan, status, subscribe) for editors
+    #[arg(long)]
+    stdio_rpc: bool,
+
+    /// Print how the status of the repository at PATH is decided, step by step, instead
+    /// of scanning
+    #[arg(long, value_name = "PATH")]
+    explain: Option<PathBuf>,
+
+    /// Print on stderr how long walking directories, checking repos and output took,
+    /// and what the walker skipped. With --format json, as a JSON object
+    #[arg(long)]
+    stats: bool,
+
+    /// Check a random PERCENT of the repos and estimate the total, e.g. 10%
+    ///
+    /// For a quick look at machines with thousands of clones. A summary on stderr
+    /// extrapolates the nasty repos in the sample to all discovered repos, with a 95%
+    /// confidence interval. Sampled scans are not recorded in the history.
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, conflicts_with_all = ["any", "exec", "open_with"])]
+    sample: Option<f64>,
+
+    /// Don't record this scan in the history used by `nasty-boii diff`
+    #[arg(long)]
+    no_history: bool,
+
+    /// After the scan, write the number of reported repos and their paths to this file
+    ///
+    /// For status bars such as polybar, xbar or swaybar, which can read the file instead
+    /// of running a scan. The file is replaced at once, so readers never see half of it.
+    /// Not written if the scan is interrupted.
+    #[arg(long, value_name = "PATH", conflicts_with = "any")]
+    status_file: Option<PathBuf>,
+
+    /// Format of --status-file
+    #[arg(long, value_enum, default_value_t = StatusFormat::Plain, requires = "status_file")]
+    status_format: StatusFormat,
+
+    /// Store the progress of the scan, and continue an interrupted scan of the same
+    /// search path with --resume where it stopped
+    ///
+    /// Only scans that walk the search path can be resumed. Directories directly below
+    /// the search path whose repos were all checked are not walked again, and repos
+    /// already checked are not checked again.
+    #[arg(long, conflicts_with_all = ["no_history", "sample", "registered", "paths_from", "any", "missing_head"])]
+    resume: bool,
+
+    /// Show dates in UTC instead of the local time zone
+    #[arg(long)]
+    utc: bool,
+
+    /// Show dates as YYYY-MM-DD instead of following the locale
+    #[arg(long)]
+    iso_dates: bool,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Format::Plain)]
+    format: Format,
+
+    /// With --format json, print one compact object per line with sorted keys and `/`
+    /// as path separator on every platform, so reports can be diffed or checksummed
+    /// across runs and machines
+    #[arg(long)]
+    canonical: bool,
 }
 
-/// Load gitignore patterns from the exclude file if provided.
-fn load_gitignore(exclude_file: Option<&PathBuf>, base_path: &Path) -> Result<Option<Gitignore>> {
-    if let Some(exclude_file) = exclude_file {
-        let mut builder = GitignoreBuilder::new(base_path);
-        if let Some(err) = builder.add(exclude_file) {
-            return Err(err).context(format!(
-                "Failed to read exclude file: {}",
-                exclude_file.display()
-            ));
-        }
-        Ok(Some(
-            builder
-                .build()
-                .context("Failed to build gitignore matcher")?,
-        ))
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Add a repository to the registry
+    Register {
+        /// Path of the repository
+        path: PathBuf,
+    },
+    /// Remove a repository from the registry
+    Unregister {
+        /// Path of the repository
+        path: PathBuf,
+    },
+    /// List registered repositories, with their labels
+    List {
+        /// Only list repositories with this label (repeatable, any matches)
+        #[arg(long, value_name = "LABEL")]
+        label: Vec<String>,
+    },
+    /// Attach labels to a registered repository, e.g. to scan work projects with `--label work`
+    Label {
+        /// Path of the repository
+        path: PathBuf,
+
+        /// Labels to attach
+        #[arg(required = true)]
+        labels: Vec<String>,
+
+        /// Detach the labels instead
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Run every detector and print a pass/fail verdict, e.g. before wiping a machine
+    ///
+    /// Exits with status 1 if any repository is at risk.
+    Audit {
+        /// Directory to search
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Path to file containing exclude patterns (gitignore-style, one per line, repeatable)
+        #[arg(long)]
+        exclude_from: Vec<PathBuf>,
+
+        /// Exclude `node_modules`, `.cargo`, `.cache`, `go/pkg` and Python virtualenvs
+        #[arg(long)]
+        default_excludes: bool,
+
+        /// Only report findings that violate the rules in this TOML policy file
+        #[arg(long, value_name = "FILE")]
+        policy: Option<PathBuf>,
+    },
+    /// Find clones of the same project where one has commits the others lack, e.g.
+    /// before consolidating duplicate clones
+    ///
+    /// Clones of a project share the URL of `origin`, compared without scheme, user and
+    /// `.git` suffix. Exits with status 1 if clones diverged.
+    Clones {
+        /// Directory to search
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Path to file containing exclude patterns (gitignore-style, one per line, repeatable)
+        #[arg(long)]
+        exclude_from: Vec<PathBuf>,
+
+        /// Exclude `node_modules`, `.cargo`, `.cache`, `go/pkg` and Python virtualenvs
+        #[arg(long)]
+        default_excludes: bool,
+    },
+    /// Audit repositories copied from another machine's disk, without network access
+    ///
+    /// Repository paths are read as they were on the original machine and resolved below
+    /// the directory the disk is mounted or copied to, as are absolute paths git recorded
+    /// inside the repositories. Exits with status 1 if any repository is at risk.
+    OfflineAudit {
+        /// File listing the repository paths on the original machine (`-` for stdin)
+        #[arg(long, value_name = "FILE")]
+        from: PathBuf,
+
+        /// Directory the original machine's disk is mounted or copied to
+        #[arg(long, value_name = "DIR")]
+        objects_dir: PathBuf,
+    },
+    /// Scan several machines over SSH and combine the results, e.g. before reimaging
+    ///
+    /// Each host runs `nasty-boii --format json`. Exits with status 1 if any host has
+    /// nasty repos or could not be scanned.
+    Fleet {
+        /// File listing the hosts, one `host` or `user@host` per line (`-` for stdin)
+        #[arg(long, value_name = "FILE")]
+        hosts: PathBuf,
+
+        /// Directory to scan on each host
+        #[arg(long, default_value = "~")]
+        root: String,
+
+        /// Command running nasty-boii on the hosts
+        #[arg(long, default_value = "nasty-boii")]
+        remote_command: String,
+
+        /// Copy this binary, e.g. a static build, to each host for the scan instead
+        #[arg(long, value_name = "BINARY", conflicts_with = "remote_command")]
+        upload: Option<PathBuf>,
+
+        /// SSH command and options
+        #[arg(long, default_value = "ssh -o BatchMode=yes")]
+        ssh: String,
+
+        /// Maximum number of hosts scanned at a time
+        #[arg(long, default_value_t = 8)]
+        jobs: usize,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = FleetFormat::Plain)]
+        format: FleetFormat,
+    },
+    /// Acknowledge a nasty repository so scans don't list it until it changes
+    ///
+    /// The repository is listed again once HEAD moves or the acknowledgement expires.
+    Ack {
+        /// Path of the repository
+        path: PathBuf,
+
+        /// Expire the acknowledgement at the start of DATE (YYYY-MM-DD, UTC)
+        #[arg(long, value_name = "DATE", value_parser = parse_date)]
+        until: Option<i64>,
+
+        /// Why the repository is nasty, for future reference
+        #[arg(long, value_name = "TEXT")]
+        reason: Option<String>,
+    },
+    /// Remove the acknowledgement of a repository
+    Unack {
+        /// Path of the repository
+        path: PathBuf,
+    },
+    /// Show repositories that became nasty or were cleaned up since the previous scan
+    Diff {
+        /// Compare scans of this directory (defaults to the most recently scanned one)
+        path: Option<PathBuf>,
+    },
+    /// Summarize new, fixed and still nasty repos from the scan history, e.g. for mail
+    Digest {
+        /// Summarize scans of this directory (defaults to the most recently scanned one)
+        path: Option<PathBuf>,
+
+        /// Compare against the last scan covered by the previous digest, or the last scan
+        /// before DATE (YYYY-MM-DD, UTC)
+        #[arg(long, value_name = "last-run|DATE", default_value = "last-run", value_parser = parse_since)]
+        since: Since,
+    },
+    /// Render the stored scan history as a report
+    Report {
+        /// Report scans of this directory (defaults to the most recently scanned one)
+        path: Option<PathBuf>,
+
+        /// Number of nasty repos per day, from the last scan of each day
+        #[arg(long, required = true)]
+        history: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+    /// Serve scan results as JSON over HTTP, e.g. for dashboards
+    ///
+    /// `GET /scan?root=PATH` scans PATH, `GET /last-report?root=PATH` returns the last
+    /// recorded scan without scanning (root is optional there).
+    Serve {
+        /// Address to listen on; use 0.0.0.0 to allow other machines
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        addr: String,
+    },
+    /// Print the JSON Schema of `--format json` output
+    Schema,
+    /// Print a man page in roff format, e.g. for packaging
+    ///
+    /// `nasty-boii man > nasty-boii.1` and install it to a man1 directory.
+    Man,
+    /// Print a compact segment like "⚠ 3" for shell prompts, based on the last scan
+    ///
+    /// Prints nothing if the last scan found no nasty repos. Does not scan.
+    Prompt {
+        /// Use the last scan of this directory (defaults to the most recent scan)
+        path: Option<PathBuf>,
+
+        /// Symbol printed before the count
+        #[arg(long, default_value = "⚠")]
+        symbol: String,
+    },
+    /// Check a single repository without walking, e.g. from git hooks, prompts and scripts
+    ///
+    /// Prints the status of the repository and what the checks found. Exits with status
+    /// 0 if it is clean, 1 if it is nasty and 2 if it cannot be checked.
+    Check {
+        /// Path of the repository
+        path: PathBuf,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "plain")]
+        format: CheckFormat,
+    },
+    /// Print a shell hook that warns when leaving a nasty repository
+    ///
+    /// Add `eval "$(nasty-boii shell-hook zsh)"` to ~/.zshrc or
+    /// `nasty-boii shell-hook fish | source` to ~/.config/fish/config.fish.
+    ShellHook {
+        /// Shell to print the hook for
+        shell: HookShell,
+    },
+    /// Access the database holding the registry and scan history
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Install a hook that runs nasty-boii automatically
+    InstallHook {
+        #[command(subcommand)]
+        hook: Hook,
+    },
+    /// Install a git hook that registers repositories as you commit to them, so that
+    /// --registered covers them without a full scan
+    ///
+    /// Refuses to replace hooks of other tools.
+    InstallGitHook {
+        /// Repository to install the hook into
+        #[arg(default_value = ".", conflicts_with = "global")]
+        path: PathBuf,
+
+        /// Install a post-commit hook
+        #[arg(long, required = true)]
+        post_commit: bool,
+
+        /// Install the hook for all repositories, in the directory of core.hooksPath.
+        /// Sets core.hooksPath in the global git config if it is unset, which makes git
+        /// ignore the hooks in .git/hooks, so it also installs a hook for every other
+        /// hook name that runs the repository's own hook.
+        #[arg(long)]
+        global: bool,
+    },
+    /// Review the commands run with --exec and what they pushed or fetched
+    AuditLog {
+        #[command(subcommand)]
+        command: AuditLogCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AuditLogCommand {
+    /// Print all logged commands, oldest first
+    Show,
+}
+
+#[derive(Subcommand, Debug)]
+enum DbCommand {
+    /// Run an SQL statement and print the result as tab-separated values
+    Query {
+        /// SQL to run, e.g. "SELECT * FROM scans"
+        sql: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum Hook {
+    /// Ask for confirmation before poweroff/reboot/shutdown if registered repos are nasty
+    Shutdown {
+        /// Shell profile to source the hook from (defaults to ~/.zshrc or ~/.bashrc based on $SHELL)
+        #[arg(long)]
+        profile: Option<PathBuf>,
+
+        /// Print the hook script instead of installing it
+        #[arg(long)]
+        print: bool,
+    },
+}
+
+/// Output formats of `check`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum CheckFormat {
+    /// Status and findings
+    Plain,
+    /// JSON object with all details, like an element of `--format json`
+    Json,
+}
+
+/// Shells `shell-hook` supports.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum HookShell {
+    Zsh,
+    Fish,
+}
+
+/// Order in which repositories are checked.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+enum Priority {
+    /// Most recently worked on first, by modification time of the index or directory
+    Mtime,
+    /// By path
+    Path,
+    /// As they are found, so checks start right away
+    #[default]
+    None,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SortBy {
+    Path,
+    /// Time of the last commit on HEAD
+    LastCommit,
+    /// Time the checked-out branch was last pushed, or else the commit time of its
+    /// upstream
+    LastPush,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Format {
+    /// One repository path per line, printed as repos are found
+    Plain,
+    /// Table with branch, ahead count, dirty flag and last commit
+    Markdown,
+    /// SARIF 2.1.0 log for CI annotations
+    Sarif,
+    /// JSON array with all details, including the upstream remote and its URL
+    Json,
+    /// CSV with all details, including the upstream remote and its URL
+    Csv,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum AssumePushDefault {
+    /// Only the configured upstream counts
+    Upstream,
+    /// Fall back to origin/<branch>, like `push.default=current`
+    Current,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum GroupBy {
+    /// Host of the upstream remote, e.g. github.com
+    RemoteHost,
+    /// Host and owner of the upstream remote, e.g. github.com/acme
+    Org,
+    /// User owning the working directory, e.g. to know whom to ask on a shared server
+    Owner,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum DedupeBy {
+    /// Normalized URL of origin, e.g. github.com/acme/app
+    Identity,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum StatusFormat {
+    /// The number of repos on the first line, then one path per line
+    Plain,
+    /// JSON object with the time of the scan, the number of repos and their paths
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum FleetFormat {
+    /// One `host:path` line per nasty repo
+    Plain,
+    /// JSON array like `--format json`, with a `host` field added
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ReportFormat {
+    /// One line per day with a bar
+    Text,
+    /// Standalone HTML page with a chart, e.g. for archiving
+    Html,
+}
+
+/// Pause after each directory when throttling on battery.
+const BATTERY_WALK_DELAY: Duration = Duration::from_millis(1);
+
+/// Pause before re-checking repositories where git maintenance was running.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Exit code after Ctrl-C, following the shell convention of 128 + SIGINT.
+const INTERRUPTED_EXIT_CODE: u8 = 130;
+
+/// Exit code of `check` if the repository cannot be checked, as 1 means nasty.
+const CHECK_ERROR_EXIT_CODE: u8 = 2;
+
+/// Set on the first Ctrl-C to stop the walker and skip remaining checks.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Parse a `YYYY-MM-DD` date into seconds since the Unix epoch at its start (UTC).
+fn parse_date(date: &str) -> Result<i64, String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc().timestamp())
+        .map_err(|e| format!("expected YYYY-MM-DD: {e}"))
+}
+
+/// Parse a percentage like `10%` or `2.5`, above 0 and at most 100.
+fn parse_percent(percent: &str) -> Result<f64, String> {
+    match percent.strip_suffix('%').unwrap_or(percent).parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent),
+        _ => Err("expected a percentage above 0 and at most 100, e.g. 10%".to_string()),
+    }
+}
+
+/// A git directory outside of the search path, given with `--extra-gitdir`.
+#[derive(Clone, Debug)]
+struct ExtraGitDir {
+    git_dir: PathBuf,
+    work_tree: Option<PathBuf>,
+}
+
+/// Parse `PATH[:WORK_TREE]`. The last colon separates the paths, unless it follows a
+/// single letter at the start of either path, i.e. a Windows drive like `C:\cfg:C:\home`.
+fn parse_extra_gitdir(value: &str) -> Result<ExtraGitDir, String> {
+    let bytes = value.as_bytes();
+    let is_drive = |colon: usize| {
+        colon > 0
+            && bytes[colon - 1].is_ascii_alphabetic()
+            && (colon == 1 || bytes[colon - 2] == b':')
+    };
+    let separator = value
+        .rmatch_indices(':')
+        .map(|(colon, _)| colon)
+        .find(|&colon| !is_drive(colon));
+    let (git_dir, work_tree) = match separator {
+        Some(colon) => (&value[..colon], Some(&value[colon + 1..])),
+        None => (value, None),
+    };
+    if git_dir.is_empty() || work_tree.is_some_and(str::is_empty) {
+        return Err("expected PATH or PATH:WORK_TREE".to_string());
+    }
+    Ok(ExtraGitDir {
+        git_dir: PathBuf::from(git_dir),
+        work_tree: work_tree.map(PathBuf::from),
+    })
+}
+
+/// What a digest compares the latest scan against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Since {
+    /// The last scan covered by the previous digest, or the scan before the latest one
+    LastRun,
+    /// The last scan before this time, in seconds since the Unix epoch
+    Time(i64),
+}
+
+fn parse_since(since: &str) -> Result<Since, String> {
+    if since == "last-run" {
+        Ok(Since::LastRun)
     } else {
-        Ok(None)
+        parse_date(since)
+            .map(Since::Time)
+            .map_err(|_| "expected last-run or YYYY-MM-DD".to_string())
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let args = Args::parse();
 
     // Set up tracing
@@ -74,101 +938,2120 @@ fn main() -> Result<()> {
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(log_level));
 
-    fmt().with_env_filter(env_filter).with_target(false).init();
+    let log_target = match &args.log_file {
+        Some(path) => LogTarget::File(RotatingFile::open(path, args.log_max_size)?),
+        // Stdout carries the protocol
+        None if args.stdio_rpc => LogTarget::Stderr,
+        None => LogTarget::Stdout,
+    };
+    logging::init(env_filter, args.log_format, log_target);
+
+    if args.low_memory && matches!(args.format, Format::Markdown | Format::Sarif) {
+        bail!("--low-memory only works with plain, JSON and CSV output");
+    }
+    if args.canonical && args.format != Format::Json {
+        bail!("--canonical only works with JSON output");
+    }
+
+    if args.stdio_rpc {
+        rpc::run()?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(repo_path) = &args.explain {
+        for line in explain::explain(repo_path, &check_set(&args)?)? {
+            println!("{line}");
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Subcommands compare branches like a scan, whatever checks are selected
+    let comparison = compared(CheckSet::default(), &args);
+    if let Some(command) = args.command {
+        return run_command(command, comparison.comparison());
+    }
+    scan(&args)
+}
 
+/// Scan for nasty repositories and print them in the requested format.
+fn scan(args: &Args) -> Result<ExitCode> {
     info!(
         search_path = %args.path.display(),
         threads = ?args.threads,
         "Starting repository scan"
     );
-
-    // Set up thread pool
-    if let Some(threads) = args.threads {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(threads)
-            .build_global()
-            .context("Failed to set thread pool size")?;
-        debug!(thread_count = threads, "Configured thread pool");
+    install_interrupt_handler();
+    if cfg!(feature = "read-only") && args.exec.is_some() {
+        bail!("--exec is not available in read-only builds");
+    }
+    if args.exec.is_some() && !args.allow_writes {
+        bail!(
+            "--exec can change repos, which read-only mode prevents. Add --allow-writes to run it"
+        );
     }
 
+    let (pool, walk_delay) = configure_process(args)?;
+    pool.install(|| scan_on_pool(args, walk_delay))
+}
+
+/// The part of [`scan`] that runs on its thread pool, from walking to printing.
+fn scan_on_pool(args: &Args, walk_delay: Option<Duration>) -> Result<ExitCode> {
     // Load exclude patterns if provided
-    let gitignore = load_gitignore(args.exclude_from.as_ref(), &args.path)?;
+    let gitignore = load_gitignore(
+        &args.exclude_from,
+        args.default_excludes,
+        args.global_gitignore,
+        &args.path,
+    )?;
+    let include_only = IncludeOnly::new(&args.include_only, &args.path)?;
+    let unscannable = Unscannable::default();
+    let excluded = Excluded::default();
+    let stats = ScanStats::new();
+    let counts = StatusCounts::default();
+    let walk_options = WalkOptions {
+        gitignore: gitignore.as_ref(),
+        include_only: include_only.as_ref(),
+        boundaries: canonicalize_boundaries(&args.boundary),
+        nested: args.nested,
+        hidden: args.hidden,
+        unscannable: Some(&unscannable),
+        excluded: args.why_excluded.then_some(&excluded),
+        delay: walk_delay,
+        counts: Some(&counts.walk),
+        interrupt: Some(&INTERRUPTED),
+        ..WalkOptions::default()
+    };
+
+    let checkpoint = ScanCheckpoint::start(args);
 
     // Find git repositories and check them in parallel
-    let missing_head_mode = args.missing_head;
-    WalkDir::new(&args.path)
-        .follow_links(false)
-        .into_iter()
-        .filter_entry(|e| {
-            // Always allow the root directory (depth 0)
-            if e.depth() == 0 {
-                return true;
-            }
-
-            // Check gitignore patterns if configured
-            if let Some(ref gi) = gitignore {
-                // Use matched_path_or_any_parents to check if this path or any parent is ignored
-                let is_dir = e.file_type().is_dir();
-                match gi.matched_path_or_any_parents(e.path(), is_dir) {
-                    ignore::Match::Ignore(_) => {
-                        debug!(
-                            path = %e.path().display(),
-                            "Excluding path based on pattern"
-                        );
-                        return false;
-                    }
-                    ignore::Match::None | ignore::Match::Whitelist(_) => {
-                        // Continue with other filters
-                    }
-                }
-            }
+    let checks = check_set(args)?;
+    let sampler = args.sample.map(Sampler::new);
+    let template = args.template.as_deref().map(Template::parse).transpose()?;
+    let events = scan_events(args, &checks, template.as_ref(), checkpoint.as_ref());
+    let repos = stats
+        .time_walk(scan_repos(
+            args,
+            walk_options,
+            sampler.as_ref(),
+            checkpoint.as_ref(),
+        )?)
+        .inspect(|repo_path| events.emit(&Event::RepoDiscovered { path: repo_path }));
+    let repos = prioritize(repos, args.priority);
+    let advisories = Advisories::default();
+    let checker = RepoChecker {
+        args,
+        checks: &checks,
+        counts: &counts,
+        stats: &stats,
+        advisories: &advisories,
+        checkpoint: checkpoint.as_ref(),
+        events: &events,
+        acks: load_acks(args.show_acked),
+        now: Utc::now().timestamp(),
+        slots: OpenRepoSlots::for_open_file_limit(),
+    };
+    let check = |repo_path: &PathBuf| checker.check(repo_path);
+    if args.any {
+        // Stop at the first nasty repo, the rest doesn't change the answer
+        let found = queued(repos, args.queue_size, |repos| {
+            repos.par_bridge().any(|repo_path| check(&repo_path))
+        });
+        return Ok(if found {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        });
+    }
+    let selected = queued(repos, args.queue_size, |repos| {
+        sort_repos(
+            check_repos(repos, args.max_retries, &check),
+            args.sort,
+            &checks,
+        )
+    });
+    events.emit(&Event::ScanFinished {
+        checked: stats.checked.load(Ordering::Relaxed),
+        nasty: selected.len(),
+        interrupted: interrupted(),
+    });
+
+    if interrupted() {
+        // Show what was found so far, but don't run commands on incomplete results
+        if args.exec.is_none() {
+            print_results(args, &selected, &checks, template.as_ref(), &counts)?;
+        }
+        report_unscannable(unscannable, false)?;
+        print_interrupted(stats.checked.load(Ordering::Relaxed), checkpoint.is_some());
+        return Ok(ExitCode::from(INTERRUPTED_EXIT_CODE));
+    }
+
+    let output_start = Instant::now();
+    let exit_code = print_results(args, &selected, &checks, template.as_ref(), &counts)?;
+    if args.stats {
+        stats.print(output_start.elapsed(), &counts.walk, args.format);
+    }
+    advisories.report();
+    report_excluded(excluded);
+    if let Some(sampler) = &sampler {
+        eprintln!("{}", sampler.estimate(selected.len()));
+    }
 
-            // Skip hidden directories except .git
-            let name = e.file_name().to_string_lossy();
-            if name == ".git" {
-                return true;
+    if let Some(opener) = &args.open_with {
+        open_with(opener, &selected);
+    }
+
+    record_scan(args, &selected, sampler.is_some())?;
+    report_unscannable(unscannable, args.strict_walk)?;
+    Ok(exit_code)
+}
+
+/// Print a nasty repository right away in plain output, so results show up while the
+/// scan is running. Other outputs are printed once the scan is done.
+fn print_as_found(args: &Args, checks: &CheckSet, template: Option<&Template>, repo_path: &Path) {
+    if args.format == Format::Plain
+        && template.is_none()
+        && !args.count
+        && args.group_by.is_none()
+        && args.dedupe_by.is_none()
+        && args.exec.is_none()
+        && args.sort == SortBy::Path
+    {
+        print_plain(args, checks, repo_path);
+    }
+}
+
+/// Print a nasty repository in plain output.
+fn print_plain(args: &Args, checks: &CheckSet, repo_path: &Path) {
+    let mut out = if args.show_counts {
+        format!("{}\n", with_counts(repo_path, checks)).into_bytes()
+    } else {
+        // Byte for byte, so paths that are not valid UTF-8 can be used as they are
+        let mut out = repo_path.as_os_str().as_encoded_bytes().to_vec();
+        out.push(if args.print0 { b'\0' } else { b'\n' });
+        out
+    };
+    if let Some(limit) = args.show_commits {
+        for subject in unpushed_commits(repo_path, limit, checks) {
+            out.extend_from_slice(format!("  {subject}\n").as_bytes());
+        }
+    }
+    // At once, so the lines of repos found in parallel don't interleave
+    let _ = io::stdout().lock().write_all(&out);
+}
+
+/// Subjects of up to `limit` unpushed commits, for `--show-commits`.
+fn unpushed_commits(repo_path: &Path, limit: usize, checks: &CheckSet) -> Vec<String> {
+    nasty_boii::unpushed_commits(repo_path, limit, checks).unwrap_or_else(|e| {
+        debug!(repo_path = %repo_path.display(), error = %e, "Failed to list commits");
+        Vec::new()
+    })
+}
+
+/// Stop the scan gracefully on the first Ctrl-C, exit right away on the second.
+fn install_interrupt_handler() {
+    let result = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::Relaxed) {
+            std::process::exit(INTERRUPTED_EXIT_CODE.into());
+        }
+    });
+    if let Err(e) = result {
+        warn!(error = %e, "Failed to install Ctrl-C handler");
+    }
+}
+
+fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Tell on stderr that the results are incomplete, and how to continue if possible.
+fn print_interrupted(checked: usize, resumable: bool) {
+    let hint = if resumable {
+        ". Run again with --resume to continue"
+    } else {
+        ""
+    };
+    eprintln!("Scan interrupted after checking {checked} repos, results are incomplete{hint}");
+}
+
+/// Print the selected repositories in the requested form, or run `--exec` for them.
+fn print_results(
+    args: &Args,
+    selected: &[PathBuf],
+    checks: &CheckSet,
+    template: Option<&Template>,
+    counts: &StatusCounts,
+) -> Result<ExitCode> {
+    if args.all {
+        counts.print();
+    } else if args.count {
+        println!("{}", selected.len());
+    } else if let Some(group_by) = args.group_by {
+        let mut keys = Vec::new();
+        inspect_batches(args, selected, checks, |reports| {
+            keys.extend(reports.iter().map(|report| match group_by {
+                GroupBy::RemoteHost => report.remote_host(),
+                GroupBy::Org => report.remote_org(),
+                GroupBy::Owner => report.owner.clone(),
+            }));
+        });
+        print_groups(keys, group_by);
+    } else if let Some(DedupeBy::Identity) = args.dedupe_by {
+        let mut clones: Vec<(Option<String>, PathBuf)> = Vec::new();
+        inspect_batches(args, selected, checks, |reports| {
+            clones.extend(
+                reports
+                    .iter()
+                    .map(|report| (report.identity.clone(), report.path.clone())),
+            );
+        });
+        print_clones(clones);
+    } else if let Some(template) = template {
+        inspect_batches(args, selected, checks, |reports| {
+            for report in reports {
+                println!("{}", template.render(report));
             }
+        });
+    } else if let Some(command) = &args.exec {
+        #[cfg(not(feature = "read-only"))]
+        return exec(command, selected, args.exec_jobs, args.non_interactive);
+        #[cfg(feature = "read-only")]
+        bail!("--exec {command} is not available in read-only builds");
+    } else {
+        print_reports(args, selected, checks);
+    }
+    Ok(ExitCode::SUCCESS)
+}
 
-            !name.starts_with('.')
-        })
-        .filter_map(std::result::Result::ok)
-        .filter(|e| e.file_type().is_dir() && e.file_name() == ".git")
-        .filter_map(|e| e.path().parent().map(std::path::Path::to_path_buf))
-        .par_bridge()
-        .for_each(|repo_path| {
-            info!(repo_path = %repo_path.display(), "Found repository");
+/// `repo_path` with the commits it is ahead and behind its upstream, for plain output.
+fn with_counts(repo_path: &Path, checks: &CheckSet) -> String {
+    match nasty_boii::ahead_behind(repo_path, checks) {
+        Ok(Some((ahead, behind))) => format!("{} +{ahead} -{behind}", paths::escape(repo_path)),
+        Ok(None) => format!("{} (no upstream)", paths::escape(repo_path)),
+        Err(e) => {
+            debug!(repo_path = %repo_path.display(), error = %e, "Failed to count commits");
+            paths::escape(repo_path).into_owned()
+        }
+    }
+}
 
-            match check_repo_status(&repo_path) {
-                Ok(RepoStatus::HasUnpushed) => {
-                    if !missing_head_mode {
-                        println!("{}", repo_path.display());
-                    }
-                }
-                Ok(RepoStatus::MissingHead) => {
-                    if missing_head_mode {
-                        println!("{}", repo_path.display());
-                    } else {
-                        warn!(
-                            repo_path = %repo_path.display(),
-                            "Repository has no HEAD"
-                        );
-                    }
-                }
-                Ok(RepoStatus::Clean) => {
-                    debug!(
-                        repo_path = %repo_path.display(),
-                        "Repository is clean"
-                    );
-                }
-                Err(e) => {
-                    warn!(
-                        repo_path = %repo_path.display(),
-                        error = %e,
-                        "Failed to check repository"
-                    );
-                }
+/// Print how many repos are under each remote host or organization, or owned by each
+/// user, most first.
+fn print_groups(keys: Vec<Option<String>>, group_by: GroupBy) {
+    let mut groups: BTreeMap<Option<String>, usize> = BTreeMap::new();
+    for key in keys {
+        *groups.entry(key).or_default() += 1;
+    }
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    // Most first, repos without a key last among equals
+    groups.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.is_none().cmp(&b.0.is_none())));
+    for (key, count) in groups {
+        let repos = if count == 1 { "repo" } else { "repos" };
+        match (key, group_by) {
+            (Some(key), GroupBy::Owner) => println!("{count} nasty {repos} owned by {key}"),
+            (Some(key), _) => println!("{count} nasty {repos} under {key}"),
+            (None, GroupBy::Owner) => println!("{count} nasty {repos} without a known owner"),
+            (None, _) => println!("{count} nasty {repos} without a hosted remote"),
+        }
+    }
+}
+
+/// Checks the repositories of a scan and emits the results.
+struct RepoChecker<'a> {
+    args: &'a Args,
+    checks: &'a CheckSet,
+    counts: &'a StatusCounts,
+    stats: &'a ScanStats,
+    advisories: &'a Advisories,
+    checkpoint: Option<&'a ScanCheckpoint>,
+    events: &'a EventBus<'a>,
+    acks: AckList,
+    now: i64,
+    slots: OpenRepoSlots,
+}
+
+impl RepoChecker<'_> {
+    /// Whether the repository is reported as nasty. Once the scan is interrupted,
+    /// repositories are not checked anymore.
+    fn check(&self, repo_path: &PathBuf) -> bool {
+        if interrupted() {
+            return false;
+        }
+        let nasty = match self.checkpoint {
+            Some(checkpoint) => checkpoint.check(repo_path, |repo_path| self.check_repo(repo_path)),
+            None => self.check_repo(repo_path),
+        };
+        self.events.emit(&Event::RepoChecked {
+            path: repo_path,
+            nasty,
+        });
+        nasty
+    }
+
+    fn check_repo(&self, repo_path: &Path) -> bool {
+        if skip_read_only(self.args.skip_readonly, repo_path) {
+            return false;
+        }
+        let _slot = self.slots.acquire();
+        if self.args.health {
+            self.advisories.inspect(repo_path);
+        }
+        let missing_head_mode = self.args.missing_head;
+        let (status, selected) = self
+            .stats
+            .time_check(|| is_selected(repo_path, missing_head_mode, self.checks));
+        let acked = selected && self.acks.covers(repo_path, self.now);
+        if acked {
+            debug!(repo_path = %repo_path.display(), "Skipping acknowledged repository");
+        }
+        self.counts.add(status, acked);
+        selected && !acked
+    }
+}
+
+/// Subscribers to the events of a scan: plain output and the checkpoint for `--resume`.
+fn scan_events<'a>(
+    args: &'a Args,
+    checks: &'a CheckSet,
+    template: Option<&'a Template>,
+    checkpoint: Option<&'a ScanCheckpoint>,
+) -> EventBus<'a> {
+    let mut events = EventBus::default();
+    // --any prints nothing
+    if !args.any {
+        events.subscribe(move |event: &Event<'_>| {
+            if let Event::RepoChecked { path, nasty: true } = *event {
+                print_as_found(args, checks, template, path);
             }
         });
+    }
+    if let Some(checkpoint) = checkpoint {
+        events.subscribe(|event: &Event<'_>| checkpoint.handle(event));
+    }
+    events
+}
 
-    Ok(())
+/// Print each identity with the paths of its clones, repos without a remote last.
+fn print_clones(clones: Vec<(Option<String>, PathBuf)>) {
+    let mut projects: BTreeMap<(bool, Option<String>), Vec<PathBuf>> = BTreeMap::new();
+    for (identity, path) in clones {
+        projects
+            .entry((identity.is_none(), identity))
+            .or_default()
+            .push(path);
+    }
+    for ((_, identity), mut paths) in projects {
+        paths.sort();
+        match identity {
+            Some(identity) => println!("{identity}"),
+            None => println!("Without a remote"),
+        }
+        for path in paths {
+            println!("  {}", paths::escape(&path));
+        }
+    }
+}
+
+/// Repositories to check, limited to the sample and preceded by those completed before
+/// `--resume`.
+fn scan_repos<'a>(
+    args: &'a Args,
+    mut walk_options: WalkOptions<'a>,
+    sampler: Option<&'a Sampler>,
+    checkpoint: Option<&'a ScanCheckpoint>,
+) -> Result<Box<dyn Iterator<Item = PathBuf> + Send + 'a>> {
+    walk_options.completed = checkpoint.map(|checkpoint| &checkpoint.resumed.subtrees);
+    let repos = repo_source(args, walk_options)?
+        .filter(move |repo_path| sampler.is_none_or(|sampler| sampler.keep(repo_path)));
+    Ok(match checkpoint {
+        Some(checkpoint) => Box::new(checkpoint.resume(repos)),
+        None => Box::new(repos),
+    })
+}
+
+/// `repos` in the order of `priority`.
+fn prioritize<'a>(
+    repos: impl Iterator<Item = PathBuf> + Send + 'a,
+    priority: Priority,
+) -> Box<dyn Iterator<Item = PathBuf> + Send + 'a> {
+    if priority == Priority::None {
+        return Box::new(repos);
+    }
+    let mut repos: Vec<PathBuf> = repos.collect();
+    match priority {
+        // Ties, e.g. repos without readable times, stay in the order they were found
+        Priority::Mtime => {
+            repos.sort_by_cached_key(|repo_path| Reverse(discovery::last_activity(repo_path)));
+        }
+        Priority::Path => repos.sort(),
+        Priority::None => {}
+    }
+    Box::new(repos.into_iter())
+}
+
+/// Repositories to check: registered, listed in a file or found below the search path.
+fn repo_source<'a>(
+    args: &'a Args,
+    walk_options: WalkOptions<'a>,
+) -> Result<Box<dyn Iterator<Item = PathBuf> + Send + 'a>> {
+    let repos: Box<dyn Iterator<Item = PathBuf> + Send + 'a> = if args.registered {
+        let db = Db::open_default()?;
+        Box::new(Registry::new(&db).repos()?.into_iter())
+    } else if let Some(source) = &args.paths_from {
+        Box::new(read_paths(source)?.into_iter())
+    } else {
+        search_path_repos(args, walk_options)
+    };
+    let extra = args.extra_gitdir.iter().map(|extra| extra.git_dir.clone());
+    let repos = Box::new(repos.chain(extra));
+    if args.label.is_empty() {
+        return Ok(repos);
+    }
+    let labelled = Registry::new(&Db::open_default()?).labelled(&args.label)?;
+    Ok(Box::new(repos.filter(move |repo_path| {
+        paths::canonicalize(repo_path).is_ok_and(|path| labelled.contains(&path))
+    })))
+}
+
+/// Repositories found below the search path, after the repository it is in, if any.
+fn search_path_repos<'a>(
+    args: &'a Args,
+    mut walk_options: WalkOptions<'a>,
+) -> Box<dyn Iterator<Item = PathBuf> + Send + 'a> {
+    // The enclosing repository is above the search path, so no pattern can match it
+    let enclosing = discovery::enclosing(&args.path).filter(|_| args.include_only.is_empty());
+    if args.stop_at_repo {
+        if discovery::detect(&args.path).is_some() {
+            return Box::new(std::iter::once(args.path.clone()));
+        }
+        if let Some(enclosing) = enclosing {
+            return Box::new(std::iter::once(enclosing));
+        }
+    }
+    if let Some(enclosing) = &enclosing {
+        debug!(repo_path = %enclosing.display(), "Search path is inside a repository");
+        match args.nested {
+            // Everything below is nested inside it
+            Nested::Skip => return Box::new(std::iter::once(enclosing.clone())),
+            Nested::Include | Nested::Only => walk_options.enclosed = true,
+        }
+    }
+    let walked: Box<dyn Iterator<Item = PathBuf> + Send + 'a> = if args.discovery == Discovery::Walk
+    {
+        Box::new(find_repos(&args.path, walk_options))
+    } else {
+        match locate::find_repos(args.discovery, &args.path, &walk_options) {
+            Ok(repos) => Box::new(repos.into_iter()),
+            Err(e) => {
+                warn!(
+                    error = format!("{e:#}"),
+                    "File index unavailable, walking instead"
+                );
+                Box::new(find_repos(&args.path, walk_options))
+            }
+        }
+    };
+    let enclosing = enclosing.filter(|_| args.nested != Nested::Only);
+    Box::new(enclosing.into_iter().chain(walked))
+}
+
+/// Run `command` for each repository, at most `jobs` at a time, and print a summary.
+///
+/// Output is captured and printed per repository so parallel commands don't interleave.
+#[cfg(not(feature = "read-only"))]
+fn exec(
+    command: &str,
+    selected: &[PathBuf],
+    jobs: usize,
+    non_interactive: bool,
+) -> Result<ExitCode> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.max(1))
+        .build()
+        .context("Failed to set up --exec thread pool")?;
+    let audit_log = AuditLog::open_default()?;
+    let print_lock = Mutex::new(());
+    let failed: Vec<&PathBuf> = pool.install(|| {
+        selected
+            .par_iter()
+            .filter(|repo_path| {
+                let command_line = command_line(command, repo_path);
+                debug!(command = %command_line, "Running command");
+                let mut shell_command = platform::shell_command(&command_line);
+                if non_interactive {
+                    platform::deny_prompts(&mut shell_command);
+                } else {
+                    platform::forward_askpass(&mut shell_command);
+                }
+                let before = BranchState::of(repo_path);
+                let result = shell_command.output();
+                let exit_code = result.as_ref().ok().and_then(|output| output.status.code());
+                let entry = Entry::new(repo_path, command_line, exit_code, before);
+                if let Err(e) = audit_log.append(&entry) {
+                    warn!(error = %e, "Failed to write audit log");
+                }
+
+                let _guard = print_lock.lock().unwrap_or_else(PoisonError::into_inner);
+                match result {
+                    Ok(output) => {
+                        let _ = io::stdout().write_all(&output.stdout);
+                        let _ = io::stderr().write_all(&output.stderr);
+                        if !output.status.success() {
+                            warn!(repo_path = %repo_path.display(), status = %output.status, "Command failed");
+                        }
+                        !output.status.success()
+                    }
+                    Err(e) => {
+                        warn!(repo_path = %repo_path.display(), error = %e, "Failed to run command");
+                        true
+                    }
+                }
+            })
+            .collect()
+    });
+
+    eprintln!(
+        "{} succeeded, {} failed",
+        selected.len() - failed.len(),
+        failed.len()
+    );
+    for repo_path in &failed {
+        eprintln!("failed: {}", repo_path.display());
+    }
+    Ok(if failed.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Start `opener` for each repository without waiting for it to exit.
+fn open_with(opener: &str, selected: &[PathBuf]) {
+    for repo_path in selected {
+        let command_line = command_line(opener, repo_path);
+        debug!(command = %command_line, "Opening repository");
+        if let Err(e) = platform::shell_command(&command_line)
+            .stdin(Stdio::null())
+            .spawn()
+        {
+            warn!(repo_path = %repo_path.display(), error = %e, "Failed to open repository");
+        }
+    }
+}
+
+/// `command` with `{}` replaced by the quoted repository path, or the path appended.
+fn command_line(command: &str, repo_path: &Path) -> String {
+    let quoted = platform::shell_quote(repo_path);
+    if command.contains("{}") {
+        command.replace("{}", &quoted)
+    } else {
+        format!("{command} {quoted}")
+    }
+}
+
+/// Lower the process priority and set up the thread pool of the scan and the delay
+/// between walked directories, throttled while running on battery.
+///
+/// The pool is built for the scan rather than configured globally, so it doesn't
+/// depend on what else in the process uses rayon.
+fn configure_process(args: &Args) -> Result<(ThreadPool, Option<Duration>)> {
+    // Lower priority before the thread pool spawns its workers, which inherit it
+    if args.nice {
+        match platform::lower_priority() {
+            Ok(()) => debug!("Lowered process priority"),
+            Err(e) => warn!(error = %e, "Failed to lower process priority"),
+        }
+    }
+
+    // Throttle on battery by capping threads and slowing down the walker
+    let mut threads = args.threads;
+    let mut walk_delay = None;
+    if args.throttle_on_battery && platform::on_battery() == Some(true) {
+        let throttled = (num_cpus::get() / 4).max(1);
+        threads = Some(threads.map_or(throttled, |t| t.min(throttled)));
+        walk_delay = Some(BATTERY_WALK_DELAY);
+        info!(threads = throttled, "Running on battery, throttling scan");
+    }
+
+    // Set up thread pool, 0 threads is one per CPU
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.unwrap_or(0))
+        .build()
+        .context("Failed to set up thread pool")?;
+    debug!(
+        thread_count = pool.current_num_threads(),
+        "Configured thread pool"
+    );
+    Ok((pool, walk_delay))
+}
+
+/// Whether `repo_path` is skipped by `--skip-readonly`.
+fn skip_read_only(skip_readonly: bool, repo_path: &Path) -> bool {
+    let read_only = skip_readonly && platform::is_read_only(repo_path);
+    if read_only {
+        debug!(repo_path = %repo_path.display(), "Skipping read-only repository");
+    }
+    read_only
+}
+
+/// Acknowledged repositories, none with `--show-acked` or if the database cannot be read.
+fn load_acks(show_acked: bool) -> AckList {
+    if show_acked {
+        return AckList::default();
+    }
+    Db::open_default()
+        .and_then(|db| Acks::new(&db).load())
+        .unwrap_or_else(|e| {
+            warn!(error = %e, "Failed to load acknowledgements");
+            AckList::default()
+        })
+}
+
+/// The checks selected with `--checks`, plus those enabled by shorthand flags.
+fn check_set(args: &Args) -> Result<CheckSet> {
+    let mut checks = if args.checks.is_empty() {
+        CheckSet::default()
+    } else {
+        CheckSet::from_names(&args.checks)?
+    };
+    if args.include_notes {
+        checks.require("notes")?;
+    }
+    if args.all_refs {
+        checks.require("refs")?;
+    }
+    if args.flag_shallow {
+        checks.require("shallow")?;
+    }
+    if args.verify_on_host {
+        checks.require("on-host")?;
+    }
+    for script in &args.hook_check {
+        checks.add(Box::new(ScriptCheck::new(script)));
+    }
+    for extra in &args.extra_gitdir {
+        if let Some(work_tree) = &extra.work_tree {
+            checks = checks.work_tree(extra.git_dir.clone(), work_tree.clone());
+        }
+    }
+    Ok(compared(checks, args))
+}
+
+/// `checks`, comparing the checked-out branch as `--against` and
+/// `--assume-push-default` say.
+fn compared(checks: CheckSet, args: &Args) -> CheckSet {
+    checks
+        .against(args.against.clone())
+        .push_default(match args.assume_push_default {
+            AssumePushDefault::Upstream => PushDefault::Upstream,
+            AssumePushDefault::Current => PushDefault::Current,
+        })
+}
+
+/// Hand `repos` to `consume`, from a thread of its own with a queue of `queue_size`.
+fn queued<T>(
+    repos: impl Iterator<Item = PathBuf> + Send,
+    queue_size: Option<usize>,
+    consume: impl FnOnce(Box<dyn Iterator<Item = PathBuf> + Send + '_>) -> T,
+) -> T {
+    let Some(queue_size) = queue_size else {
+        return consume(Box::new(repos));
+    };
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::sync_channel(queue_size);
+        scope.spawn(move || {
+            for repo_path in repos {
+                // The checks stopped early, e.g. for --any
+                if sender.send(repo_path).is_err() {
+                    break;
+                }
+            }
+        });
+        consume(Box::new(receiver.into_iter()))
+    })
+}
+
+/// Check repositories in parallel and return the selected ones, sorted.
+///
+/// Repositories where git maintenance is running are checked at the end.
+fn check_repos(
+    repos: impl Iterator<Item = PathBuf> + Send,
+    max_retries: usize,
+    check: &(dyn Fn(&PathBuf) -> bool + Sync),
+) -> Vec<PathBuf> {
+    let deferred = Mutex::new(Vec::new());
+    let mut selected: Vec<PathBuf> = repos
+        .par_bridge()
+        .filter(|repo_path| {
+            if max_retries > 0 && maintenance_running(repo_path) {
+                debug!(repo_path = %repo_path.display(), "Maintenance running, checking later");
+                deferred
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(repo_path.clone());
+                return false;
+            }
+            check(repo_path)
+        })
+        .collect();
+    let deferred = deferred
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner);
+    selected.extend(retry_deferred(deferred, max_retries, check));
+    selected.sort();
+    selected
+}
+
+/// `selected`, sorted by path, in the order of `sort`.
+fn sort_repos(selected: Vec<PathBuf>, sort: SortBy, checks: &CheckSet) -> Vec<PathBuf> {
+    if sort == SortBy::Path {
+        return selected;
+    }
+    let mut keyed: Vec<_> = selected
+        .into_par_iter()
+        .map(|repo_path| {
+            let (last_commit, last_push) =
+                report::last_commit_and_push(&repo_path, checks.comparison()).unwrap_or_else(|e| {
+                    debug!(repo_path = %repo_path.display(), error = %e, "Failed to read times");
+                    (None, None)
+                });
+            let key = match sort {
+                SortBy::LastCommit => last_commit,
+                SortBy::LastPush | SortBy::Path => last_push,
+            };
+            (key, repo_path)
+        })
+        .collect();
+    // Stable, so repos with the same time stay sorted by path
+    keyed.sort_by_key(|(key, _)| *key);
+    keyed.into_iter().map(|(_, repo_path)| repo_path).collect()
+}
+
+/// Check repositories that were skipped because git maintenance was running, pausing
+/// before each round. In the last round they are checked even if it is still running.
+fn retry_deferred(
+    mut deferred: Vec<PathBuf>,
+    max_retries: usize,
+    check: &(dyn Fn(&PathBuf) -> bool + Sync),
+) -> Vec<PathBuf> {
+    let mut selected = Vec::new();
+    for attempt in 1..=max_retries {
+        if deferred.is_empty() {
+            break;
+        }
+        thread::sleep(RETRY_DELAY);
+        let last_attempt = attempt == max_retries;
+        let (locked, ready): (Vec<PathBuf>, Vec<PathBuf>) = deferred
+            .into_iter()
+            .partition(|repo_path| !last_attempt && maintenance_running(repo_path));
+        if last_attempt {
+            for repo_path in ready.iter().filter(|path| maintenance_running(path)) {
+                warn!(
+                    repo_path = %repo_path.display(),
+                    "Maintenance still running, checking anyway"
+                );
+            }
+        }
+        selected.extend(
+            ready
+                .into_par_iter()
+                .filter(|repo_path| check(repo_path))
+                .collect::<Vec<_>>(),
+        );
+        deferred = locked;
+    }
+    selected
+}
+
+/// Number and date conventions for human-readable formats, from the locale unless
+/// overridden.
+fn human_format(args: &Args) -> HumanFormat {
+    let mut format = HumanFormat::from_env();
+    if args.utc {
+        format = format.utc();
+    }
+    if args.iso_dates {
+        format = format.iso_dates();
+    }
+    format
+}
+
+/// Print the reports for formats that need details beyond the repository path.
+fn print_reports(args: &Args, selected: &[PathBuf], checks: &CheckSet) {
+    match args.format {
+        // Printed as found when sorted by path
+        Format::Plain if args.sort == SortBy::Path => {}
+        Format::Plain => {
+            for repo_path in selected {
+                print_plain(args, checks, repo_path);
+            }
+        }
+        Format::Markdown => {
+            let reports = inspect_repos(selected, checks);
+            if !reports.is_empty() {
+                print!("{}", output::markdown(&reports, &human_format(args)));
+            }
+        }
+        Format::Sarif => println!("{:#}", output::sarif(&inspect_repos(selected, checks))),
+        Format::Json if args.canonical => {
+            let mut empty = true;
+            inspect_batches(args, selected, checks, |reports| {
+                for line in output::canonical_json(reports) {
+                    print!("{}\n{line}", if empty { "[" } else { "," });
+                    empty = false;
+                }
+            });
+            println!("{}", if empty { "[]" } else { "\n]" });
+        }
+        Format::Json => {
+            // Print the array like `{:#}` would, one element at a time
+            let mut empty = true;
+            inspect_batches(args, selected, checks, |reports| {
+                for element in output::json(reports).as_array().into_iter().flatten() {
+                    let element = format!("{element:#}").replace('\n', "\n  ");
+                    print!("{}\n  {element}", if empty { "[" } else { "," });
+                    empty = false;
+                }
+            });
+            println!("{}", if empty { "[]" } else { "\n]" });
+        }
+        Format::Csv => {
+            let mut header = true;
+            inspect_batches(args, selected, checks, |reports| {
+                let csv = output::csv(reports);
+                // Every batch starts with the header line
+                print!(
+                    "{}",
+                    if header {
+                        &csv
+                    } else {
+                        csv.split_once('\n').map_or("", |(_, rows)| rows)
+                    }
+                );
+                header = false;
+            });
+        }
+    }
+}
+
+/// Key under which a scan is stored in the history, `None` for ad-hoc path lists.
+fn scan_root(args: &Args) -> Option<String> {
+    if args.registered {
+        Some("registered".to_string())
+    } else if args.paths_from.is_some() {
+        None
+    } else {
+        let root = paths::canonicalize(&args.path).ok()?;
+        Some(root.display().to_string())
+    }
+}
+
+/// Record a complete scan that reported `selected` in the history and the status file.
+fn record_scan(args: &Args, selected: &[PathBuf], sampled: bool) -> Result<()> {
+    if !args.no_history && !args.missing_head && !sampled {
+        if let Some(root) = scan_root(args) {
+            if let Err(e) = record_history(root, selected) {
+                warn!(error = %e, "Failed to record scan history");
+            }
+        }
+    }
+    if let Some(status_file) = &args.status_file {
+        write_status_file(status_file, args.status_format, selected)?;
+    }
+    Ok(())
+}
+
+fn record_history(root: String, selected: &[PathBuf]) -> Result<()> {
+    let db = Db::open_default()?;
+    History::new(&db).push(&ScanRecord {
+        time: Utc::now().timestamp(),
+        root,
+        repos: selected
+            .iter()
+            .map(|path| paths::canonicalize(path).unwrap_or_else(|_| path.clone()))
+            .collect(),
+    })
+}
+
+/// Replace `path` with the status after a scan that reported `selected`.
+///
+/// The status is written to a temporary file next to `path` and renamed, so readers see
+/// either the old or the new status.
+fn write_status_file(path: &Path, format: StatusFormat, selected: &[PathBuf]) -> Result<()> {
+    let content = match format {
+        StatusFormat::Plain => {
+            let mut content = format!("{}\n", selected.len());
+            for repo_path in selected {
+                content.push_str(&paths::escape(repo_path));
+                content.push('\n');
+            }
+            content
+        }
+        StatusFormat::Json => {
+            let status = serde_json::json!({
+                "time": Utc::now().timestamp(),
+                "nasty": selected.len(),
+                "repos": selected.iter().map(|path| path.display().to_string()).collect::<Vec<_>>(),
+            });
+            format!("{status}\n")
+        }
+    };
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    fs::write(&temp_path, content)
+        .and_then(|()| fs::rename(&temp_path, path))
+        .with_context(|| format!("Failed to write status file {}", path.display()))
+}
+
+/// Steps of a scan stored at once, so that recording progress costs one transaction
+/// per batch instead of one per repository.
+const CHECKPOINT_BATCH: usize = 256;
+
+/// Longest time pending steps wait to be stored, so a crash of a slow scan loses
+/// little progress.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Progress of a scan with `--resume` that walks the search path, stored so that a
+/// later `--resume` can continue it after an interruption.
+struct ScanCheckpoint {
+    writer: Mutex<CheckpointWriter>,
+    root: String,
+    search_path: PathBuf,
+    /// Progress of the interrupted scan this one continues
+    resumed: Checkpoint,
+    tracker: Mutex<SubtreeTracker>,
+}
+
+/// The database and the steps of the scan that are not stored yet.
+struct CheckpointWriter {
+    db: Db,
+    pending: Vec<Progress>,
+    stored_at: Instant,
+}
+
+impl ScanCheckpoint {
+    /// Continue the interrupted scan with `--resume`, or start one that can be
+    /// resumed. `None` if the scan is not tracked.
+    fn start(args: &Args) -> Option<Self> {
+        if !args.resume {
+            return None;
+        }
+        let walks =
+            args.discovery == Discovery::Walk && !args.registered && args.paths_from.is_none();
+        if !walks || args.no_history || args.missing_head || args.sample.is_some() || args.any {
+            warn!("--resume only works for scans that walk the search path");
+            return None;
+        }
+        let root = scan_root(args)?;
+        let result = Db::open_default().and_then(|db| Ok((Checkpoints::new(&db).load(&root)?, db)));
+        match result {
+            Ok((resumed, db)) => Some(Self {
+                writer: Mutex::new(CheckpointWriter {
+                    db,
+                    pending: Vec::new(),
+                    stored_at: Instant::now(),
+                }),
+                root,
+                search_path: args.path.clone(),
+                resumed,
+                tracker: Mutex::new(SubtreeTracker::default()),
+            }),
+            Err(e) => {
+                warn!(error = format!("{e:#}"), "Failed to open scan checkpoint");
+                None
+            }
+        }
+    }
+
+    /// Repositories in directories completed before, followed by the walked ones.
+    fn resume<'a>(
+        &'a self,
+        walked: impl Iterator<Item = PathBuf> + Send + 'a,
+    ) -> impl Iterator<Item = PathBuf> + Send + 'a {
+        self.resumed
+            .completed_repos()
+            .map(|path| self.search_path.join(path))
+            .chain(walked)
+    }
+
+    /// Store the progress of the scan as it goes.
+    fn handle(&self, event: &Event<'_>) {
+        match *event {
+            Event::RepoDiscovered { path } => self.discovered(path),
+            Event::RepoChecked { path, nasty } => self.checked(path, nasty),
+            Event::ScanFinished { interrupted, .. } => self.finish(interrupted),
+            _ => {}
+        }
+    }
+
+    /// Whether the repository was nasty when the interrupted scan checked it.
+    fn previous(&self, repo_path: &Path) -> Option<bool> {
+        let relative = repo_path.strip_prefix(&self.search_path).ok()?;
+        self.resumed.repos.get(relative).copied()
+    }
+
+    /// Whether the repository is nasty, from the interrupted scan or `check_repo`.
+    fn check(&self, repo_path: &PathBuf, check_repo: impl FnOnce(&PathBuf) -> bool) -> bool {
+        self.previous(repo_path)
+            .unwrap_or_else(|| check_repo(repo_path))
+    }
+
+    fn discovered(&self, repo_path: &Path) {
+        let Ok(relative) = repo_path.strip_prefix(&self.search_path) else {
+            return;
+        };
+        let left = self.tracker().discovered(relative);
+        if let Some(subtree) = left {
+            self.record([Progress::Subtree(subtree)]);
+        }
+    }
+
+    fn checked(&self, repo_path: &Path, nasty: bool) {
+        let Ok(relative) = repo_path.strip_prefix(&self.search_path) else {
+            return;
+        };
+        let repo = Progress::Repo {
+            path: relative.to_path_buf(),
+            nasty,
+        };
+        let completed = self.tracker().checked(relative);
+        self.record(std::iter::once(repo).chain(completed.map(Progress::Subtree)));
+    }
+
+    /// Store the steps not stored yet of an interrupted scan, or remove the
+    /// checkpoint of a scan that ran to the end.
+    fn finish(&self, interrupted: bool) {
+        let mut writer = self.writer();
+        if interrupted {
+            self.store(&mut writer);
+        } else {
+            writer.pending.clear();
+            if let Err(e) = Checkpoints::new(&writer.db).clear(&self.root) {
+                warn!(error = format!("{e:#}"), "Failed to remove scan checkpoint");
+            }
+        }
+    }
+
+    fn tracker(&self) -> MutexGuard<'_, SubtreeTracker> {
+        self.tracker.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn writer(&self) -> MutexGuard<'_, CheckpointWriter> {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds `steps` to the pending ones, storing them once there are enough or they
+    /// waited long enough.
+    fn record(&self, steps: impl IntoIterator<Item = Progress>) {
+        let mut writer = self.writer();
+        writer.pending.extend(steps);
+        if writer.pending.len() >= CHECKPOINT_BATCH
+            || writer.stored_at.elapsed() >= CHECKPOINT_INTERVAL
+        {
+            self.store(&mut writer);
+        }
+    }
+
+    /// A failed update only makes a later `--resume` redo more work.
+    fn store(&self, writer: &mut CheckpointWriter) {
+        let pending = std::mem::take(&mut writer.pending);
+        if let Err(e) = Checkpoints::new(&writer.db).add(&self.root, &pending) {
+            warn!(error = format!("{e:#}"), "Failed to update scan checkpoint");
+        }
+        writer.stored_at = Instant::now();
+    }
+}
+
+/// Print the number of nasty repos from the last scan, for embedding in shell prompts.
+fn prompt(root: Option<&Path>, symbol: &str) -> Result<()> {
+    let root = root
+        .map(|root| paths::canonicalize(root).map(|root| root.display().to_string()))
+        .transpose()
+        .context("Failed to resolve path")?;
+    let db = Db::open_default()?;
+    if let Some(record) = History::new(&db).latest(root.as_deref())? {
+        if !record.repos.is_empty() {
+            println!("{symbol} {}", record.repos.len());
+        }
+    }
+    Ok(())
+}
+
+/// Print the hook that warns when leaving a nasty repository in `shell`.
+fn print_shell_hook(shell: HookShell) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to determine executable path")?;
+    match shell {
+        HookShell::Zsh => print!("{}", hooks::zsh_leave_hook_script(&exe)),
+        HookShell::Fish => print!("{}", hooks::fish_leave_hook_script(&exe)),
+    }
+    Ok(())
+}
+
+/// Check the repository at `path` alone and print its status, exiting with 0 if it is
+/// clean, 1 if it is nasty and [`CHECK_ERROR_EXIT_CODE`] if it cannot be checked.
+fn check_one(path: &Path, format: CheckFormat) -> ExitCode {
+    let status = match format {
+        CheckFormat::Plain => CheckSet::default().check(path).map(|results| {
+            println!("{}: {}", path.display(), results.status);
+            for (name, findings) in &results.findings {
+                for finding in findings {
+                    println!("  {name}: {finding}");
+                }
+            }
+            results.status
+        }),
+        CheckFormat::Json => RepoReport::inspect(path).map(|report| {
+            let status = report.status;
+            let json = output::json(&[report])[0].take();
+            println!("{json:#}");
+            status
+        }),
+    };
+    match status {
+        Ok(RepoStatus::Clean) => ExitCode::SUCCESS,
+        Ok(_) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            ExitCode::from(CHECK_ERROR_EXIT_CODE)
+        }
+    }
+}
+
+/// Scan the hosts listed in `hosts_file`, at most `jobs` at a time, and print the
+/// combined results.
+fn scan_fleet(
+    fleet: &Fleet,
+    hosts_file: &Path,
+    jobs: usize,
+    format: FleetFormat,
+) -> Result<ExitCode> {
+    let content = if hosts_file == Path::new("-") {
+        io::read_to_string(io::stdin()).context("Failed to read hosts from stdin")?
+    } else {
+        fs::read_to_string(hosts_file)
+            .context(format!("Failed to read hosts: {}", hosts_file.display()))?
+    };
+    let hosts = fleet::parse_hosts(&content);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.max(1))
+        .build()
+        .context("Failed to set up fleet thread pool")?;
+    let results: Vec<(&String, Result<Vec<serde_json::Value>>)> = pool.install(|| {
+        hosts
+            .par_iter()
+            .map(|host| {
+                debug!(host, "Scanning host");
+                (host, fleet.scan(host))
+            })
+            .collect()
+    });
+
+    let mut reports = Vec::new();
+    let mut failed = Vec::new();
+    for (host, result) in results {
+        match result {
+            Ok(host_reports) => reports.extend(host_reports),
+            Err(e) => {
+                warn!(host, error = format!("{e:#}"), "Failed to scan host");
+                failed.push(host);
+            }
+        }
+    }
+    let nasty = reports.len();
+    match format {
+        FleetFormat::Plain => fleet::write_plain(&mut io::stdout().lock(), &reports)?,
+        FleetFormat::Json => println!("{}", serde_json::Value::Array(reports)),
+    }
+
+    eprintln!(
+        "{} hosts scanned, {} failed, {nasty} nasty repos",
+        hosts.len() - failed.len(),
+        failed.len(),
+    );
+    for host in &failed {
+        eprintln!("failed: {host}");
+    }
+    Ok(if failed.is_empty() && nasty == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Print the number of nasty repositories per day for scans of `root`.
+fn report_history(root: Option<&Path>, format: ReportFormat) -> Result<()> {
+    let db = Db::open_default()?;
+    let history = History::new(&db);
+    let root = history_root(&history, root)?;
+    let days = history.days(&root)?;
+    if days.is_empty() {
+        bail!("No scans of {root} yet");
+    }
+    match format {
+        ReportFormat::Text => print!("{}", trend::text(&days)),
+        ReportFormat::Html => print!("{}", trend::html(&root, &days)),
+    }
+    Ok(())
+}
+
+/// The history root for `root`, or the most recently scanned one.
+fn history_root(history: &History, root: Option<&Path>) -> Result<String> {
+    Ok(match root {
+        Some(root) => paths::canonicalize(root)
+            .context(format!("Failed to resolve path: {}", root.display()))?
+            .display()
+            .to_string(),
+        None => match history.latest(None)? {
+            Some(record) => record.root,
+            None => bail!("No scan history yet"),
+        },
+    })
+}
+
+/// Show which repositories became nasty or were cleaned up between the last two scans.
+fn diff(root: Option<&Path>) -> Result<ExitCode> {
+    let db = Db::open_default()?;
+    let history = History::new(&db);
+    let root = history_root(&history, root)?;
+
+    let (Some(latest), Some(previous)) = history.last_two(&root)? else {
+        bail!("Need at least two scans of {root} to compare");
+    };
+    let diff = latest.diff(&previous);
+    for path in &diff.became_nasty {
+        println!("+ {}", path.display());
+    }
+    for path in &diff.cleaned_up {
+        println!("- {}", path.display());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Print a digest of the scans of `root` since the previous digest or a date.
+fn digest(root: Option<&Path>, since: Since) -> Result<()> {
+    let db = Db::open_default()?;
+    let history = History::new(&db);
+    let digests = Digests::new(&db);
+    let root = history_root(&history, root)?;
+
+    let records = history.records_of(&root)?;
+    let baseline = match since {
+        Since::LastRun => digests.last_run(&root)?,
+        // Scans during the day of DATE are not before it
+        Since::Time(time) => Some(time - 1),
+    };
+    let Some(digest) = Digest::new(&records, baseline) else {
+        bail!("No scans of {root} yet");
+    };
+    print!("{digest}");
+    // Looking further back doesn't change what the next regular digest covers
+    if since == Since::LastRun {
+        digests.set_last_run(&root, digest.to)?;
+    }
+    Ok(())
+}
+
+/// Print the registered repositories with any of `labels`, or all without labels.
+fn list(labels: &[String]) -> Result<()> {
+    let db = Db::open_default()?;
+    let registry = Registry::new(&db);
+    let labelled = registry.labelled(labels)?;
+    for path in registry.repos()? {
+        if !labels.is_empty() && !labelled.contains(&path) {
+            continue;
+        }
+        let labels = registry.labels(&path)?;
+        if labels.is_empty() {
+            println!("{}", path.display());
+        } else {
+            println!("{}\t{}", path.display(), labels.join(","));
+        }
+    }
+    Ok(())
+}
+
+/// Attach `labels` to the registered repository at `path`, or detach them.
+fn label(path: &Path, labels: &[String], remove: bool) -> Result<()> {
+    let db = Db::open_default()?;
+    let registry = Registry::new(&db);
+    if remove {
+        let path = registry.remove_labels(path, labels)?;
+        info!(repo_path = %path.display(), ?labels, "Removed labels");
+    } else {
+        let path = registry.add_labels(path, labels)?;
+        info!(repo_path = %path.display(), ?labels, "Added labels");
+    }
+    Ok(())
+}
+
+/// Run a subcommand.
+fn run_command(command: Command, comparison: &Comparison) -> Result<ExitCode> {
+    match command {
+        Command::Register { path } => {
+            let db = Db::open_default()?;
+            let path = Registry::new(&db).add(&path)?;
+            info!(repo_path = %path.display(), "Registered repository");
+        }
+        Command::Unregister { path } => {
+            let db = Db::open_default()?;
+            let path = Registry::new(&db).remove(&path)?;
+            info!(repo_path = %path.display(), "Unregistered repository");
+        }
+        Command::List { label } => list(&label)?,
+        Command::Label {
+            path,
+            labels,
+            remove,
+        } => label(&path, &labels, remove)?,
+        Command::Db {
+            command: DbCommand::Query { sql },
+        } => query(&sql)?,
+        Command::Ack {
+            path,
+            until,
+            reason,
+        } => {
+            let db = Db::open_default()?;
+            let ack = Acks::new(&db).add(&path, until, reason.as_deref())?;
+            info!(repo_path = %ack.path.display(), head = %ack.head, "Acknowledged repository");
+        }
+        Command::Unack { path } => {
+            let db = Db::open_default()?;
+            let path = Acks::new(&db).remove(&path)?;
+            info!(repo_path = %path.display(), "Removed acknowledgement");
+        }
+        Command::Audit {
+            path,
+            exclude_from,
+            default_excludes,
+            policy,
+        } => {
+            let gitignore = load_gitignore(&exclude_from, default_excludes, false, &path)?;
+            return audit(&path, gitignore.as_ref(), policy.as_deref(), comparison);
+        }
+        Command::Clones {
+            path,
+            exclude_from,
+            default_excludes,
+        } => {
+            let gitignore = load_gitignore(&exclude_from, default_excludes, false, &path)?;
+            return clones(&path, gitignore.as_ref());
+        }
+        Command::OfflineAudit { from, objects_dir } => {
+            return offline_audit(&from, &objects_dir, comparison);
+        }
+        Command::Fleet {
+            hosts,
+            root,
+            remote_command,
+            upload,
+            ssh,
+            jobs,
+            format,
+        } => {
+            let fleet = Fleet {
+                ssh: ssh.split_whitespace().map(str::to_string).collect(),
+                remote: upload.map_or(Remote::Installed(remote_command), Remote::Upload),
+                root,
+            };
+            return scan_fleet(&fleet, &hosts, jobs, format);
+        }
+        Command::Diff { path } => return diff(path.as_deref()),
+        Command::Digest { path, since } => digest(path.as_deref(), since)?,
+        Command::Report { path, format, .. } => report_history(path.as_deref(), format)?,
+        Command::Serve { addr } => serve::serve(&addr)?,
+        Command::Schema => println!("{:#}", output::json_schema()),
+        Command::Man => clap_mangen::Man::new(Args::command())
+            .render(&mut io::stdout().lock())
+            .context("Failed to write man page")?,
+        Command::Prompt { path, symbol } => prompt(path.as_deref(), &symbol)?,
+        Command::Check { path, format } => return Ok(check_one(&path, format)),
+        Command::ShellHook { shell } => print_shell_hook(shell)?,
+        Command::InstallHook {
+            hook: Hook::Shutdown { profile, print },
+        } => install_shutdown_hook(profile, print)?,
+        Command::InstallGitHook { path, global, .. } => install_post_commit_hook(&path, global)?,
+        Command::AuditLog {
+            command: AuditLogCommand::Show,
+        } => {
+            for entry in AuditLog::open_default()?.entries()? {
+                println!("{entry}");
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Run `sql` against the database and print the rows tab-separated.
+fn query(sql: &str) -> Result<()> {
+    let result = Db::open_default()?.query(sql)?;
+    if !result.columns.is_empty() {
+        println!("{}", result.columns.join("\t"));
+    }
+    for row in result.rows {
+        println!("{}", row.join("\t"));
+    }
+    Ok(())
+}
+
+/// Audit all repositories below `path` and print a breakdown of everything at risk.
+fn audit(
+    path: &Path,
+    gitignore: Option<&Gitignore>,
+    policy: Option<&Path>,
+    comparison: &Comparison,
+) -> Result<ExitCode> {
+    let policy = policy.map(Policy::load).transpose()?;
+    let slots = OpenRepoSlots::for_open_file_limit();
+    let unscannable = Unscannable::default();
+    let walk_options = WalkOptions {
+        gitignore,
+        unscannable: Some(&unscannable),
+        interrupt: Some(&INTERRUPTED),
+        ..WalkOptions::default()
+    };
+    let mut reports: Vec<AuditReport> = find_repos(path, walk_options)
+        .par_bridge()
+        .filter_map(|repo_path| {
+            let _slot = slots.acquire();
+            audit_or_warn(&repo_path, AuditReport::run(&repo_path, comparison))
+        })
+        .collect();
+    report_unscannable(unscannable, false)?;
+    if let Some(policy) = policy {
+        let db = Db::open_default()?;
+        let registry = Registry::new(&db);
+        let now = Utc::now().timestamp();
+        for report in &mut reports {
+            let canonical =
+                paths::canonicalize(&report.path).unwrap_or_else(|_| report.path.clone());
+            policy.apply(report, &registry.labels(&canonical)?, now);
+        }
+    }
+    Ok(print_audit(reports))
+}
+
+/// Print the projects cloned in several places below `path` where the clones diverged.
+fn clones(path: &Path, gitignore: Option<&Gitignore>) -> Result<ExitCode> {
+    let unscannable = Unscannable::default();
+    let walk_options = WalkOptions {
+        gitignore,
+        unscannable: Some(&unscannable),
+        interrupt: Some(&INTERRUPTED),
+        ..WalkOptions::default()
+    };
+    let identities: Vec<(String, PathBuf)> = find_repos(path, walk_options)
+        .par_bridge()
+        .filter_map(|repo_path| match clones::identify(&repo_path) {
+            Ok(identity) => Some((identity?, repo_path)),
+            Err(e) => {
+                warn!(repo_path = %repo_path.display(), error = %e, "Failed to open repository");
+                None
+            }
+        })
+        .collect();
+    report_unscannable(unscannable, false)?;
+    let mut projects: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for (identity, repo_path) in identities {
+        projects.entry(identity).or_default().push(repo_path);
+    }
+
+    let mut cloned = 0;
+    let mut diverged = 0;
+    for (identity, paths) in projects.into_iter().filter(|(_, paths)| paths.len() > 1) {
+        cloned += 1;
+        let project = match clones::compare(identity, paths) {
+            Ok(project) => project,
+            Err(e) => {
+                warn!(error = format!("{e:#}"), "Failed to compare clones");
+                continue;
+            }
+        };
+        if !project.diverged() {
+            continue;
+        }
+        diverged += 1;
+        println!("{}", project.identity);
+        for clone in &project.clones {
+            let branches = if clone.unique_branches.is_empty() {
+                "nothing the others lack".to_string()
+            } else {
+                clone.unique_branches.join(", ")
+            };
+            println!("  {}: {branches}", paths::escape(&clone.path));
+        }
+    }
+
+    let human_format = HumanFormat::from_env();
+    println!(
+        "Found {} projects with several clones, {} diverged",
+        human_format.number(cloned),
+        human_format.number(diverged)
+    );
+    Ok(if diverged == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Audit the repositories listed in `source` below `root`, reporting their original paths.
+fn offline_audit(source: &Path, root: &Path, comparison: &Comparison) -> Result<ExitCode> {
+    let root = OfflineRoot::new(root);
+    let reports: Vec<AuditReport> = read_paths(source)?
+        .into_par_iter()
+        .filter_map(|repo_path| {
+            let report = root
+                .open(&repo_path)
+                .and_then(|repo| AuditReport::run_on(repo, &repo_path, comparison));
+            audit_or_warn(&repo_path, report)
+        })
+        .collect();
+    Ok(print_audit(reports))
+}
+
+fn audit_or_warn(repo_path: &Path, report: Result<AuditReport>) -> Option<AuditReport> {
+    match report {
+        Ok(report) => Some(report),
+        Err(e) => {
+            warn!(
+                repo_path = %repo_path.display(),
+                error = %e,
+                "Failed to audit repository"
+            );
+            None
+        }
+    }
+}
+
+/// Print the repositories at risk and a verdict.
+fn print_audit(mut reports: Vec<AuditReport>) -> ExitCode {
+    reports.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let failed: Vec<&AuditReport> = reports.iter().filter(|r| !r.passed()).collect();
+    for report in &failed {
+        println!("{}", report.path.display());
+        for finding in &report.findings {
+            println!("  {}: {}", finding.kind, finding.detail);
+        }
+    }
+
+    let human_format = HumanFormat::from_env();
+    println!(
+        "Audited {} repositories, {} at risk",
+        human_format.number(reports.len()),
+        human_format.number(failed.len())
+    );
+    if failed.is_empty() {
+        println!("Verdict: PASS");
+        ExitCode::SUCCESS
+    } else {
+        println!("Verdict: FAIL");
+        ExitCode::FAILURE
+    }
+}
+
+/// The interactive shell profile of the current user, based on `$SHELL`.
+fn default_profile() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Failed to determine home directory")?;
+    let shell = std::env::var("SHELL").unwrap_or_default();
+    Ok(home.join(if shell.ends_with("zsh") {
+        ".zshrc"
+    } else {
+        ".bashrc"
+    }))
+}
+
+fn install_shutdown_hook(profile: Option<PathBuf>, print: bool) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to determine executable path")?;
+    let script = hooks::shutdown_hook_script(&exe);
+    if print {
+        print!("{script}");
+        return Ok(());
+    }
+
+    let profile = match profile {
+        Some(profile) => profile,
+        None => default_profile()?,
+    };
+    let script_path = nasty_boii::data_dir()?.join("hooks").join("shutdown.sh");
+    if hooks::install_profile_hook(&profile, &script_path, &script)? {
+        println!(
+            "Installed shutdown hook in {}, open a new shell to activate it",
+            profile.display()
+        );
+    } else {
+        println!(
+            "Updated shutdown hook, already sourced by {}",
+            profile.display()
+        );
+    }
+    Ok(())
+}
+
+/// Install the post-commit hook into the repository at `path`, or for all repositories
+/// with `global`.
+fn install_post_commit_hook(path: &Path, global: bool) -> Result<()> {
+    let hooks_dir = if global {
+        let home = dirs::home_dir().context("Failed to determine home directory")?;
+        hooks::global_hooks_dir(&home, &nasty_boii::data_dir()?.join("git-hooks"))?
+    } else {
+        hooks::repo_hooks_dir(path)?
+    };
+    let exe = std::env::current_exe().context("Failed to determine executable path")?;
+    let hook_path = hooks::install_git_hook(
+        &hooks_dir,
+        "post-commit",
+        &hooks::post_commit_hook_script(&exe),
+    )?;
+    println!("Installed post-commit hook in {}", hook_path.display());
+    if global {
+        // git no longer runs the hooks in .git/hooks, so forward to them
+        for name in hooks::install_forwarding_hooks(&hooks_dir)? {
+            warn!(
+                hook = name,
+                hooks_dir = %hooks_dir.display(),
+                "Keeping hook of another tool, which doesn't run the hook of each repository"
+            );
+        }
+        println!(
+            "Installed hooks running the own hooks of each repository in {}",
+            hooks_dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// Read repository paths from `source`, one per line or NUL-terminated like the output
+/// of `--print0` (`-` reads from stdin).
+///
+/// Paths pointing at a `.git` directory are mapped to their working directory,
+/// so the output of e.g. `fd -H -t d '^\.git$'` can be used directly.
+fn read_paths(source: &Path) -> Result<Vec<PathBuf>> {
+    let content = if source == Path::new("-") {
+        let mut content = Vec::new();
+        io::stdin()
+            .read_to_end(&mut content)
+            .context("Failed to read paths from stdin")?;
+        content
+    } else {
+        fs::read(source).context(format!("Failed to read paths file: {}", source.display()))?
+    };
+
+    let separator = if content.contains(&0) { 0 } else { b'\n' };
+    Ok(content
+        .split(|&byte| byte == separator)
+        .map(<[u8]>::trim_ascii)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let path = paths::from_bytes(line);
+            match path.parent() {
+                Some(parent) if path.file_name() == Some(".git".as_ref()) => parent.to_path_buf(),
+                _ => path,
+            }
+        })
+        .collect())
+}
+
+/// File descriptors kept free for stdio, the database, log files and the walker
+const RESERVED_FDS: u64 = 32;
+/// File descriptors a check may hold per repository: index, pack files and their
+/// indexes, refs and config files
+const FDS_PER_REPO: u64 = 16;
+
+/// Caps how many repositories are open at once, so a large thread pool does not run
+/// out of file descriptors. Checks beyond the cap wait for a slot.
+struct OpenRepoSlots {
+    available: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl OpenRepoSlots {
+    /// Slots fitting into the open file limit of the process.
+    fn for_open_file_limit() -> Self {
+        let slots = platform::open_file_limit().map_or(usize::MAX, |limit| {
+            let slots = (limit.saturating_sub(RESERVED_FDS) / FDS_PER_REPO).max(1);
+            usize::try_from(slots).unwrap_or(usize::MAX)
+        });
+        if slots < rayon::current_num_threads() {
+            info!(
+                open_repos = slots,
+                threads = rayon::current_num_threads(),
+                "Limiting open repositories to the open file limit"
+            );
+        }
+        Self {
+            available: Mutex::new(slots),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Waits for a free slot, which is returned when the guard is dropped.
+    fn acquire(&self) -> OpenRepoSlot<'_> {
+        let mut available = self
+            .available
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        while *available == 0 {
+            available = self
+                .freed
+                .wait(available)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *available -= 1;
+        OpenRepoSlot(self)
+    }
+}
+
+struct OpenRepoSlot<'a>(&'a OpenRepoSlots);
+
+impl Drop for OpenRepoSlot<'_> {
+    fn drop(&mut self) {
+        *self
+            .0
+            .available
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) += 1;
+        self.0.freed.notify_one();
+    }
+}
+
+/// Print the paths the walker could not read to stderr. With `strict`, fail if there
+/// are any.
+fn report_unscannable(unscannable: Unscannable, strict: bool) -> Result<()> {
+    let paths = unscannable.into_paths();
+    if paths.is_empty() {
+        return Ok(());
+    }
+    eprintln!("{} unscannable path(s):", paths.len());
+    for (path, reason) in &paths {
+        eprintln!("  {}: {reason}", path.display());
+    }
+    if strict {
+        bail!("{} path(s) could not be scanned", paths.len());
+    }
+    Ok(())
+}
+
+/// Print the repositories pruned by an exclude pattern to stderr, for `--why-excluded`.
+fn report_excluded(excluded: Excluded) {
+    let excluded = excluded.into_repos();
+    if excluded.is_empty() {
+        return;
+    }
+    eprintln!("{} excluded repo(s):", excluded.len());
+    for (path, reason) in &excluded {
+        eprintln!("  {}: {reason}", path.display());
+    }
+}
+
+/// Maintenance advice collected with `--health`, independent of the checks.
+#[derive(Default)]
+struct Advisories(Mutex<Vec<(PathBuf, String)>>);
+
+impl Advisories {
+    fn inspect(&self, repo_path: &Path) {
+        let advisories = health::advisories(repo_path);
+        if advisories.is_empty() {
+            return;
+        }
+        let mut all = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        all.extend(
+            advisories
+                .into_iter()
+                .map(|advisory| (repo_path.to_path_buf(), advisory)),
+        );
+    }
+
+    /// Print the advisories to stderr, grouped by repository.
+    fn report(self) {
+        let mut advisories = self.0.into_inner().unwrap_or_else(PoisonError::into_inner);
+        if advisories.is_empty() {
+            return;
+        }
+        // Repos deferred for running maintenance may be inspected more than once
+        advisories.sort();
+        advisories.dedup();
+        eprintln!("{} maintenance advisory(ies):", advisories.len());
+        for (path, advisory) in &advisories {
+            eprintln!("  {}: {advisory}", path.display());
+        }
+    }
+}
+
+/// Statuses counted by `--count --all`, in the order they are printed.
+const COUNTED_STATUSES: [RepoStatus; 9] = [
+    RepoStatus::HasUnpushed,
+    RepoStatus::HasUnpushedLfs,
+    RepoStatus::HasUnpushedNotes,
+    RepoStatus::HasUnpushedRefs,
+    RepoStatus::TransferInProgress,
+    RepoStatus::Conflicted,
+    RepoStatus::OperationInProgress,
+    RepoStatus::MissingHead,
+    RepoStatus::Clean,
+];
+
+/// Number of checked repositories per status, and what the walk that found them
+/// visited and skipped.
+#[derive(Default)]
+struct StatusCounts {
+    /// By the position of the status in [`COUNTED_STATUSES`]
+    by_status: [AtomicUsize; COUNTED_STATUSES.len()],
+    /// Reported repositories that were acknowledged, whatever their status
+    acked: AtomicUsize,
+    error: AtomicUsize,
+    walk: WalkCounts,
+}
+
+impl StatusCounts {
+    fn add(&self, status: Option<RepoStatus>, acked: bool) {
+        let counter = match status {
+            _ if acked => &self.acked,
+            Some(status) => {
+                let index = COUNTED_STATUSES
+                    .iter()
+                    .position(|counted| *counted == status)
+                    .expect("every status is counted");
+                &self.by_status[index]
+            }
+            None => &self.error,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn print(&self) {
+        let count = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        for (status, counter) in COUNTED_STATUSES.iter().zip(&self.by_status) {
+            println!("{status}: {}", count(counter));
+        }
+        println!("acked: {}", count(&self.acked));
+        println!("error: {}", count(&self.error));
+        println!("directories: {}", count(&self.walk.directories));
+        println!("pruned: {}", count(&self.walk.pruned));
+        println!("unreadable: {}", count(&self.walk.unreadable));
+        println!("symlinks: {}", count(&self.walk.symlinks));
+    }
+}
+
+/// Where the time of a scan went, for `--stats`.
+struct ScanStats {
+    start: Instant,
+    /// Time spent waiting for the next repository, mostly walking directories
+    walk_nanos: AtomicU64,
+    /// Time spent checking repositories, summed over all threads
+    check_nanos: AtomicU64,
+    checked: AtomicUsize,
+}
+
+impl ScanStats {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            walk_nanos: AtomicU64::new(0),
+            check_nanos: AtomicU64::new(0),
+            checked: AtomicUsize::new(0),
+        }
+    }
+
+    fn time_walk<'a>(
+        &'a self,
+        mut repos: impl Iterator<Item = PathBuf> + Send + 'a,
+    ) -> impl Iterator<Item = PathBuf> + Send + 'a {
+        std::iter::from_fn(move || {
+            let start = Instant::now();
+            let next = repos.next();
+            add_elapsed(&self.walk_nanos, start);
+            next
+        })
+    }
+
+    fn time_check<T>(&self, check: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = check();
+        add_elapsed(&self.check_nanos, start);
+        self.checked.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    /// Print the stats and the counts of the `walk` to stderr, keeping stdout for the
+    /// results. As a JSON object with JSON output.
+    fn print(&self, output: Duration, walk: &WalkCounts, format: Format) {
+        let seconds =
+            |nanos: &AtomicU64| Duration::from_nanos(nanos.load(Ordering::Relaxed)).as_secs_f64();
+        let count = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        if format == Format::Json {
+            let stats = serde_json::json!({
+                "checked": count(&self.checked),
+                "seconds": self.start.elapsed().as_secs_f64(),
+                "walk_seconds": seconds(&self.walk_nanos),
+                "check_seconds": seconds(&self.check_nanos),
+                "output_seconds": output.as_secs_f64(),
+                "threads": rayon::current_num_threads(),
+                "directories": count(&walk.directories),
+                "pruned": count(&walk.pruned),
+                "unreadable": count(&walk.unreadable),
+                "symlinks": count(&walk.symlinks),
+            });
+            eprintln!("{stats}");
+            return;
+        }
+        eprintln!(
+            "Checked {} repos in {:.3}s",
+            count(&self.checked),
+            self.start.elapsed().as_secs_f64()
+        );
+        eprintln!("  walk:   {:.3}s", seconds(&self.walk_nanos));
+        eprintln!(
+            "    {} directories, {} pruned by patterns, {} unreadable, {} symlinks skipped",
+            count(&walk.directories),
+            count(&walk.pruned),
+            count(&walk.unreadable),
+            count(&walk.symlinks)
+        );
+        eprintln!(
+            "  checks: {:.3}s (summed over {} threads)",
+            seconds(&self.check_nanos),
+            rayon::current_num_threads()
+        );
+        eprintln!("  output: {:.3}s", output.as_secs_f64());
+    }
+}
+
+fn add_elapsed(nanos: &AtomicU64, start: Instant) {
+    let elapsed = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+    nanos.fetch_add(elapsed, Ordering::Relaxed);
+}
+
+/// Check a repository and decide whether it should be reported. Returns its status,
+/// `None` if it could not be checked, along with the decision.
+fn is_selected(
+    repo_path: &Path,
+    missing_head_mode: bool,
+    checks: &CheckSet,
+) -> (Option<RepoStatus>, bool) {
+    let span = info_span!(
+        "repo",
+        path = %repo_path.display(),
+        branch = field::Empty,
+        duration_ms = field::Empty,
+    );
+    let _entered = span.enter();
+    info!("Found repository");
+
+    let start = Instant::now();
+    let results = checks.check(repo_path);
+    if let Ok(CheckResults {
+        branch: Some(branch),
+        ..
+    }) = &results
+    {
+        span.record("branch", branch.as_str());
+    }
+    span.record(
+        "duration_ms",
+        u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+    );
+
+    let status = results.map(|results| results.status);
+    let selected = match status {
+        Ok(RepoStatus::MissingHead) => {
+            if !missing_head_mode {
+                warn!("Repository has no HEAD");
+            }
+            missing_head_mode
+        }
+        Ok(RepoStatus::Clean) => {
+            debug!("Repository is clean");
+            false
+        }
+        Ok(status) => {
+            debug!(%status, "Repository is nasty");
+            !missing_head_mode
+        }
+        Err(ref e) => {
+            warn!(error = %e, "Failed to check repository");
+            false
+        }
+    };
+    (status.ok(), selected)
+}
+
+/// Collect report details for the selected repositories in parallel, keeping their order.
+fn inspect_repos(selected: &[PathBuf], checks: &CheckSet) -> Vec<RepoReport> {
+    selected
+        .par_iter()
+        .filter_map(|repo_path| inspect_repo(repo_path, checks))
+        .collect()
+}
+
+/// Reports inspected at a time with `--low-memory`
+const LOW_MEMORY_BATCH: usize = 256;
+
+/// Inspect the selected repositories in order and pass their reports to `each`, in
+/// batches with `--low-memory` and all at once otherwise.
+fn inspect_batches(
+    args: &Args,
+    selected: &[PathBuf],
+    checks: &CheckSet,
+    mut each: impl FnMut(&[RepoReport]),
+) {
+    let batch = if args.low_memory {
+        LOW_MEMORY_BATCH
+    } else {
+        selected.len().max(1)
+    };
+    for batch in selected.chunks(batch) {
+        let mut reports = inspect_repos(batch, checks);
+        if let Some(limit) = args.show_commits {
+            for report in &mut reports {
+                report.unpushed_commits = Some(unpushed_commits(&report.path, limit, checks));
+            }
+        }
+        each(&reports);
+    }
+}
+
+/// Collect report details for a selected repository, logging failures.
+fn inspect_repo(repo_path: &Path, checks: &CheckSet) -> Option<RepoReport> {
+    match RepoReport::inspect_with(repo_path, checks) {
+        Ok(report) => Some(report),
+        Err(e) => {
+            warn!(
+                repo_path = %repo_path.display(),
+                error = %e,
+                "Failed to inspect repository"
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_extra_gitdir() {
+        let parse = |value| {
+            let extra = parse_extra_gitdir(value).unwrap();
+            (extra.git_dir, extra.work_tree)
+        };
+        assert_eq!(
+            parse("/home/me/.cfg:/home/me"),
+            ("/home/me/.cfg".into(), Some("/home/me".into()))
+        );
+        assert_eq!(parse("/home/me/.cfg"), ("/home/me/.cfg".into(), None));
+        assert_eq!(
+            parse(r"C:\cfg:C:\home"),
+            (r"C:\cfg".into(), Some(r"C:\home".into()))
+        );
+        assert_eq!(
+            parse(r"C:\cfg:/home"),
+            (r"C:\cfg".into(), Some("/home".into()))
+        );
+        assert_eq!(parse(r"C:\cfg"), (r"C:\cfg".into(), None));
+        assert!(parse_extra_gitdir(r"C:\cfg:").is_err());
+    }
 }
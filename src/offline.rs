@@ -0,0 +1,153 @@
+//! Opening repositories copied from another machine's disk.
+//!
+//! Paths are given as they were on the original machine, e.g. `/home/me/src/repo`, and
+//! resolved below the directory the disk is mounted or copied to. Absolute paths that
+//! git recorded inside a repository, in `.git` files and object alternates, are
+//! resolved the same way. Remotes are only read from the config and never contacted,
+//! so remotes pointing at paths that don't exist on this machine are harmless.
+
+use crate::discovery;
+use anyhow::{Context, Result};
+use git2::Repository;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Directory holding the files of another machine, e.g. a mounted disk image.
+#[derive(Debug)]
+pub struct OfflineRoot {
+    root: PathBuf,
+}
+
+impl OfflineRoot {
+    #[must_use]
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// Where `original`, a path on the original machine, is below the root.
+    ///
+    /// Relative paths are taken as relative to the root of the original machine.
+    #[must_use]
+    pub fn resolve(&self, original: &Path) -> PathBuf {
+        let mut resolved = self.root.clone();
+        for component in original.components() {
+            match component {
+                Component::Normal(name) => resolved.push(name),
+                Component::ParentDir => {
+                    if resolved != self.root {
+                        resolved.pop();
+                    }
+                }
+                Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+            }
+        }
+        resolved
+    }
+
+    /// Opens the repository whose working directory was at `original`.
+    ///
+    /// # Errors
+    /// Returns an error if there is no repository at the resolved path.
+    pub fn open(&self, original: &Path) -> Result<Repository> {
+        let workdir = self.resolve(original);
+        let dot_git = workdir.join(".git");
+        let repo = match self.foreign_git_dir(&dot_git) {
+            Some(git_dir) => Repository::open(&git_dir).and_then(|repo| {
+                repo.set_workdir(&workdir, false)?;
+                Ok(repo)
+            }),
+            None => Repository::open(&workdir),
+        }
+        .with_context(|| format!("Failed to open repository at {}", workdir.display()))?;
+        self.add_alternates(&repo)?;
+        Ok(repo)
+    }
+
+    /// The resolved git directory of a `.git` file with an absolute path that only
+    /// exists on the original machine.
+    fn foreign_git_dir(&self, dot_git: &Path) -> Option<PathBuf> {
+        if !discovery::is_git_file(dot_git) {
+            return None;
+        }
+        let content = fs::read_to_string(dot_git).ok()?;
+        let git_dir = Path::new(content.strip_prefix("gitdir: ")?.trim());
+        (git_dir.is_absolute() && !git_dir.exists()).then(|| self.resolve(git_dir))
+    }
+
+    /// Adds object alternates with absolute paths from the original machine, which
+    /// git would otherwise skip, leaving objects missing.
+    fn add_alternates(&self, repo: &Repository) -> Result<()> {
+        let alternates = repo.path().join("objects").join("info").join("alternates");
+        let Ok(content) = fs::read_to_string(alternates) else {
+            return Ok(());
+        };
+        let odb = repo.odb().context("Failed to open object database")?;
+        for line in content.lines().map(str::trim) {
+            let alternate = Path::new(line);
+            if line.starts_with('#') || !alternate.is_absolute() || alternate.exists() {
+                continue;
+            }
+            let resolved = self.resolve(alternate);
+            if resolved.is_dir() {
+                odb.add_disk_alternate(&resolved.to_string_lossy())
+                    .context(format!("Failed to add alternate {}", resolved.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn git(dir: &Path, args: &[&str]) {
+        Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_resolve() {
+        let root = OfflineRoot::new(Path::new("/mnt/disk"));
+        assert_eq!(
+            root.resolve(Path::new("/home/me/src")),
+            Path::new("/mnt/disk/home/me/src")
+        );
+        assert_eq!(
+            root.resolve(Path::new("../../etc")),
+            Path::new("/mnt/disk/etc")
+        );
+    }
+
+    #[test]
+    fn test_open_separate_git_dir_with_foreign_path() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let disk = temp_dir.path().join("disk");
+        let home = disk.join("home");
+        fs::create_dir_all(&home).unwrap();
+        git(&home, &["init", "--separate-git-dir", "repo.git", "repo"]);
+        git(&home.join("repo"), &["config", "user.name", "Test User"]);
+        git(
+            &home.join("repo"),
+            &["config", "user.email", "test@example.com"],
+        );
+        git(
+            &home.join("repo"),
+            &["commit", "--allow-empty", "-m", "Initial commit"],
+        );
+        // Pretend the disk was mounted at / on the original machine
+        fs::write(home.join("repo/.git"), "gitdir: /home/repo.git\n").unwrap();
+
+        let repo = OfflineRoot::new(&disk)
+            .open(Path::new("/home/repo"))
+            .unwrap();
+        assert_eq!(repo.workdir(), Some(home.join("repo").as_path()));
+        assert!(repo.head().unwrap().peel_to_commit().is_ok());
+    }
+}
@@ -0,0 +1,618 @@
+use crate::locale::HumanFormat;
+use crate::paths;
+use crate::report::{LastCommit, RepoReport};
+use crate::{describe_ahead, Comparison, RepoStatus};
+use serde_json::{json, Value};
+use std::fmt::Write;
+
+/// Markdown columns with findings of a check, only shown if any repository has them.
+const OPTIONAL_COLUMNS: &[(&str, &str)] =
+    &[("Large files", "lfs"), ("Notes", "notes"), ("Refs", "refs")];
+
+/// Renders reports as a Markdown table, suitable for pasting into issues or wiki pages.
+///
+/// Columns for large files, notes and refs are added if any repository has such findings.
+/// Numbers and dates follow `format`.
+#[must_use]
+pub fn markdown(reports: &[RepoReport], format: &HumanFormat) -> String {
+    let optional_columns: Vec<&(&str, &str)> = OPTIONAL_COLUMNS
+        .iter()
+        .filter(|(_, check)| reports.iter().any(|r| !r.findings(check).is_empty()))
+        .collect();
+
+    let mut out = String::from("| Path | Branch | Ahead | Dirty | Last commit |");
+    for (name, _) in &optional_columns {
+        let _ = write!(out, " {name} |");
+    }
+    out.push_str("\n|------|--------|-------|-------|-------------|");
+    for (name, _) in &optional_columns {
+        let _ = write!(out, "{}|", "-".repeat(name.len() + 2));
+    }
+    out.push('\n');
+    for report in reports {
+        let ahead = report
+            .ahead
+            .map_or_else(|| "no upstream".to_string(), |n| format.number(n));
+        let _ = write!(
+            out,
+            "| {} | {} | {} | {} | {} |",
+            escape_markdown(&paths::escape(&report.path)),
+            escape_markdown(report.branch.as_deref().unwrap_or("-")),
+            ahead,
+            if report.dirty { "yes" } else { "no" },
+            escape_markdown(&format_last_commit(report.last_commit.as_ref(), format)),
+        );
+        for (_, check) in &optional_columns {
+            let _ = write!(
+                out,
+                " {} |",
+                escape_markdown(&format_list(report.findings(check)))
+            );
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Version of the `--format json` objects, bumped on incompatible changes such as
+/// removed or renamed fields. New fields don't change it.
+pub const JSON_SCHEMA_VERSION: u64 = 1;
+
+/// Renders reports as a JSON array with one object per repository, see [`json_schema`].
+#[must_use]
+pub fn json(reports: &[RepoReport]) -> Value {
+    reports
+        .iter()
+        .map(|report| {
+            let mut object = json!({
+                "schema_version": JSON_SCHEMA_VERSION,
+                "path": paths::escape(&report.path),
+                "status": report.status.to_string(),
+                "branch": report.branch,
+                "ahead": report.ahead,
+                "dirty": report.dirty,
+                "conflicted": report.conflicted,
+                "operation": report.operation,
+                "shallow": report.shallow,
+                "partial": report.partial,
+                "sparse": report.sparse,
+                "last_commit": report.last_commit.as_ref().map(|commit| json!({
+                    "time": commit.time,
+                    "summary": commit.summary,
+                })),
+                "last_push": report.last_push,
+                "owner": report.owner,
+                "remote": report.remote,
+                "remote_url": report.remote_url,
+                "identity": report.identity,
+                "push_remote": report.push_remote,
+                "push_ahead": report.push_ahead,
+                "local_only_branches": report.local_only_branches,
+                "findings": report.findings,
+                "unpushed_commits": report.unpushed_commits,
+            });
+            if report.path.to_str().is_none() {
+                object["path_bytes"] = json!(report.path.as_os_str().as_encoded_bytes());
+            }
+            object
+        })
+        .collect()
+}
+
+/// Renders reports like [`json`], one compact object per line, with keys sorted and `/`
+/// as path separator on every platform. The same repositories always give the same
+/// bytes, so reports can be diffed or checksummed across runs and machines.
+#[must_use]
+pub fn canonical_json(reports: &[RepoReport]) -> Vec<String> {
+    json(reports)
+        .as_array()
+        .into_iter()
+        .flatten()
+        .zip(reports)
+        .map(|(object, report)| {
+            let mut object = sorted(object.clone());
+            object["path"] = json!(paths::escape_portable(&report.path));
+            object.to_string()
+        })
+        .collect()
+}
+
+/// `value` with the keys of all objects in sorted order, whatever order the map type
+/// of `serde_json` keeps them in.
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut entries: Vec<_> = object.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sorted(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(array) => Value::Array(array.into_iter().map(sorted).collect()),
+        value => value,
+    }
+}
+
+/// JSON Schema (draft 2020-12) of the [`json`] output, for consumers to validate against.
+#[must_use]
+pub fn json_schema() -> Value {
+    let nullable = |kind: &str| json!({ "type": [kind, "null"] });
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": format!("https://github.com/felixhummel/nasty-boii/schema/v{JSON_SCHEMA_VERSION}.json"),
+        "title": "nasty-boii report",
+        "type": "array",
+        "items": {
+            "type": "object",
+            "required": ["schema_version", "path", "status", "findings"],
+            "properties": {
+                "schema_version": { "const": JSON_SCHEMA_VERSION },
+                "path": {
+                    "type": "string",
+                    "description": "Bytes that are not valid UTF-8 are written as \\xNN",
+                },
+                "path_bytes": {
+                    "type": "array",
+                    "items": { "type": "integer", "minimum": 0, "maximum": 255 },
+                    "description": "The exact path, only if it is not valid UTF-8",
+                },
+                "status": {
+                    "enum": [
+                        "clean",
+                        "unpushed",
+                        "unpushed-lfs",
+                        "unpushed-notes",
+                        "unpushed-refs",
+                        "transfer-in-progress",
+                        "conflicted",
+                        "operation-in-progress",
+                        "missing-head",
+                    ],
+                },
+                "branch": nullable("string"),
+                "ahead": { "type": ["integer", "null"], "minimum": 0 },
+                "dirty": { "type": "boolean" },
+                "conflicted": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "Files with unresolved merge conflicts",
+                },
+                "operation": {
+                    "enum": ["merge", "revert", "cherry-pick", "rebase", "am", null],
+                    "description": "Operation that was started but not finished",
+                },
+                "shallow": { "type": "boolean" },
+                "partial": { "type": "boolean" },
+                "sparse": { "type": "boolean" },
+                "last_commit": {
+                    "type": ["object", "null"],
+                    "required": ["time", "summary"],
+                    "properties": {
+                        "time": { "type": "integer", "description": "Seconds since the Unix epoch" },
+                        "summary": { "type": "string" },
+                    },
+                },
+                "last_push": {
+                    "type": ["integer", "null"],
+                    "description": "When the branch was last pushed, in seconds since the Unix epoch, or else the commit time of its upstream",
+                },
+                "owner": {
+                    "type": ["string", "null"],
+                    "description": "User owning the working directory, by name or else by uid, null outside of Unix",
+                },
+                "remote": nullable("string"),
+                "remote_url": nullable("string"),
+                "identity": {
+                    "type": ["string", "null"],
+                    "description": "Remote URL without scheme, user and .git suffix, the same for all clones of a project",
+                },
+                "push_remote": nullable("string"),
+                "push_ahead": { "type": ["integer", "null"], "minimum": 0 },
+                "local_only_branches": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Local branches that were never pushed anywhere",
+                },
+                "findings": {
+                    "type": "object",
+                    "description": "Findings by check name, only for checks that found something",
+                    "additionalProperties": { "type": "array", "items": { "type": "string" } },
+                },
+                "unpushed_commits": {
+                    "type": ["array", "null"],
+                    "items": { "type": "string" },
+                    "description": "Subjects of unpushed commits, newest first, with --show-commits",
+                },
+            },
+        },
+    })
+}
+
+/// CSV columns, in order.
+const CSV_COLUMNS: &[&str] = &[
+    "path",
+    "status",
+    "branch",
+    "ahead",
+    "dirty",
+    "conflicted",
+    "shallow",
+    "partial",
+    "sparse",
+    "last_commit",
+    "remote",
+    "remote_url",
+    "findings",
+];
+
+/// Renders reports as CSV with a header row, e.g. for spreadsheets. Missing values
+/// are empty.
+#[must_use]
+pub fn csv(reports: &[RepoReport]) -> String {
+    let mut out = CSV_COLUMNS.join(",");
+    out.push('\n');
+    for report in reports {
+        let fields = [
+            paths::escape(&report.path).into_owned(),
+            report.status.to_string(),
+            report.branch.clone().unwrap_or_default(),
+            report.ahead.map(|n| n.to_string()).unwrap_or_default(),
+            report.dirty.to_string(),
+            report.conflicted.to_string(),
+            report.shallow.to_string(),
+            report.partial.to_string(),
+            report.sparse.to_string(),
+            report
+                .last_commit
+                .as_ref()
+                .map(|commit| format_last_commit(Some(commit), &HumanFormat::iso()))
+                .unwrap_or_default(),
+            report.remote.clone().unwrap_or_default(),
+            report.remote_url.clone().unwrap_or_default(),
+            if report.findings.is_empty() {
+                String::new()
+            } else {
+                format_findings(report)
+            },
+        ];
+        let row: Vec<String> = fields.iter().map(|field| escape_csv(field)).collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// SARIF rules as ID and short description.
+const SARIF_RULES: &[(&str, &str)] = &[
+    ("unpushed", "Branch has commits that are not pushed"),
+    ("no-upstream", "Branch has no upstream configured"),
+    ("unpushed-lfs", "git-lfs or git-annex content is not pushed"),
+    ("unpushed-notes", "Git notes are not pushed"),
+    ("unpushed-refs", "Refs outside of refs/heads are not pushed"),
+    ("conflicted", "Index has unresolved merge conflicts"),
+    (
+        "operation-in-progress",
+        "A rebase, merge or similar operation is not finished",
+    ),
+    (
+        "transfer-in-progress",
+        "A git-lfs or git-annex transfer is not finished",
+    ),
+    ("check", "An optional or custom check found something"),
+    ("missing-head", "Repository has no HEAD"),
+];
+
+/// Renders reports as a SARIF 2.1.0 log, so CI systems can show them as annotations.
+#[must_use]
+pub fn sarif(reports: &[RepoReport]) -> Value {
+    let results: Vec<Value> = reports
+        .iter()
+        .map(|report| {
+            let (rule_id, message) = sarif_rule(report);
+            json!({
+                "ruleId": rule_id,
+                "level": "warning",
+                "message": { "text": message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {
+                            "uri": paths::escape(&report.path).replace('\\', "/"),
+                        },
+                    },
+                }],
+            })
+        })
+        .collect();
+    let rules: Vec<Value> = SARIF_RULES
+        .iter()
+        .map(|(id, description)| json!({ "id": id, "shortDescription": { "text": description } }))
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "nasty-boii",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+/// The SARIF rule a report violates, with a message describing the violation.
+fn sarif_rule(report: &RepoReport) -> (&'static str, String) {
+    let branch = report.branch.as_deref().unwrap_or("-");
+    match (&report.status, report.ahead) {
+        (RepoStatus::MissingHead, _) => ("missing-head", "Repository has no HEAD".to_string()),
+        // Flagged by an optional or custom check rather than unpushed commits
+        (RepoStatus::HasUnpushed, _)
+            if !report.findings.is_empty() && report.findings("ahead").is_empty() =>
+        {
+            ("check", format_findings(report))
+        }
+        (RepoStatus::HasUnpushedLfs, _) => (
+            "unpushed-lfs",
+            format!(
+                "Large files not pushed: {}",
+                format_list(report.findings("lfs"))
+            ),
+        ),
+        (RepoStatus::HasUnpushedNotes, _) => (
+            "unpushed-notes",
+            format!(
+                "Notes not pushed: {}",
+                format_list(report.findings("notes"))
+            ),
+        ),
+        (RepoStatus::HasUnpushedRefs, _) => (
+            "unpushed-refs",
+            format!("Refs not pushed: {}", format_list(report.findings("refs"))),
+        ),
+        (RepoStatus::TransferInProgress, _) => (
+            "transfer-in-progress",
+            format!(
+                "Transfer in progress: {}",
+                format_list(report.findings("transfers"))
+            ),
+        ),
+        (RepoStatus::Conflicted, _) => (
+            "conflicted",
+            format!(
+                "Merge conflicts: {}",
+                format_list(report.findings("conflicts"))
+            ),
+        ),
+        (RepoStatus::OperationInProgress, _) => (
+            "operation-in-progress",
+            format!(
+                "Not finished: {}",
+                format_list(report.findings("operation"))
+            ),
+        ),
+        // Compared against the push remote rather than the upstream
+        (_, _) if report.push_remote.is_some() => ("unpushed", format_findings(report)),
+        // The ahead check described it as it compared, e.g. with --against
+        (_, ahead) if !report.findings("ahead").is_empty() => (
+            if ahead.is_some() {
+                "unpushed"
+            } else {
+                "no-upstream"
+            },
+            report.findings("ahead").join(", "),
+        ),
+        (_, None) => (
+            "no-upstream",
+            describe_ahead(branch, None, &Comparison::default()),
+        ),
+        (_, ahead) => (
+            "unpushed",
+            describe_ahead(branch, ahead, &Comparison::default()),
+        ),
+    }
+}
+
+pub(crate) fn format_last_commit(last_commit: Option<&LastCommit>, format: &HumanFormat) -> String {
+    let Some(commit) = last_commit else {
+        return "-".to_string();
+    };
+    format!("{} {}", format.date(commit.time), commit.summary)
+}
+
+/// Ref names as a comma-separated list, or `-`.
+pub(crate) fn format_list(names: &[String]) -> String {
+    if names.is_empty() {
+        "-".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+/// All findings as `check: finding, finding; check: finding`, or `-`.
+pub(crate) fn format_findings(report: &RepoReport) -> String {
+    if report.findings.is_empty() {
+        return "-".to_string();
+    }
+    report
+        .findings
+        .iter()
+        .map(|(check, findings)| format!("{check}: {}", findings.join(", ")))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Quotes a CSV field if needed, see RFC 4180.
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Escapes characters that would break a Markdown table cell.
+fn escape_markdown(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    fn unpushed_report() -> RepoReport {
+        RepoReport {
+            path: PathBuf::from("/src/a|b"),
+            status: RepoStatus::HasUnpushed,
+            branch: Some("main".to_string()),
+            ahead: Some(2),
+            dirty: true,
+            conflicted: 0,
+            operation: None,
+            shallow: false,
+            partial: false,
+            sparse: false,
+            last_commit: Some(LastCommit {
+                time: 0,
+                summary: "Initial commit".to_string(),
+            }),
+            last_push: None,
+            owner: None,
+            remote: None,
+            remote_url: None,
+            identity: None,
+            push_remote: None,
+            push_ahead: None,
+            local_only_branches: Vec::new(),
+            findings: BTreeMap::new(),
+            unpushed_commits: None,
+        }
+    }
+
+    #[test]
+    fn test_markdown_table() {
+        let reports = vec![unpushed_report()];
+
+        let table = markdown(&reports, &HumanFormat::iso());
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[2],
+            "| /src/a\\|b | main | 2 | yes | 1970-01-01 Initial commit |"
+        );
+    }
+
+    #[test]
+    fn test_markdown_notes_column() {
+        let mut report = unpushed_report();
+        report
+            .findings
+            .insert("notes".to_string(), vec!["commits".to_string()]);
+
+        let table = markdown(&[report, unpushed_report()], &HumanFormat::iso());
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].ends_with("| Last commit | Notes |"));
+        assert!(lines[2].ends_with("| commits |"));
+        assert!(lines[3].ends_with("| - |"));
+    }
+
+    #[test]
+    fn test_json() {
+        let mut report = unpushed_report();
+        report.remote = Some("origin".to_string());
+        report.remote_url = Some("https://example.com/repo.git".to_string());
+
+        let json = json(&[report]);
+        assert_eq!(json[0]["path"], "/src/a|b");
+        assert_eq!(json[0]["status"], "unpushed");
+        assert_eq!(json[0]["ahead"], 2);
+        assert_eq!(json[0]["last_commit"]["summary"], "Initial commit");
+        assert_eq!(json[0]["remote"], "origin");
+        assert_eq!(json[0]["remote_url"], "https://example.com/repo.git");
+        assert_eq!(json[0]["schema_version"], JSON_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_json_schema_covers_all_fields() {
+        let json = json(&[unpushed_report()]);
+        let schema = json_schema();
+        let properties = schema["items"]["properties"].as_object().unwrap();
+        let fields = json[0].as_object().unwrap();
+        assert_eq!(
+            fields.keys().collect::<Vec<_>>(),
+            properties
+                .keys()
+                .filter(|key| *key != "path_bytes")
+                .collect::<Vec<_>>()
+        );
+        let statuses = properties["status"]["enum"].as_array().unwrap();
+        assert!(statuses.contains(&json[0]["status"]));
+    }
+
+    #[test]
+    fn test_canonical_json() {
+        let mut report = unpushed_report();
+        report
+            .findings
+            .insert("lfs".to_string(), vec!["2 objects".to_string()]);
+        let lines = canonical_json(&[report]);
+        assert_eq!(lines.len(), 1);
+        assert!(!lines[0].contains('\n'));
+        let object: Value = serde_json::from_str(&lines[0]).unwrap();
+        let keys: Vec<_> = object.as_object().unwrap().keys().collect();
+        let mut sorted_keys = keys.clone();
+        sorted_keys.sort();
+        assert_eq!(keys, sorted_keys);
+        assert!(lines[0].starts_with(r#"{"ahead":2,"branch":"#));
+        assert_eq!(object["path"], "/src/a|b");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_json_non_utf8_path() {
+        let mut report = unpushed_report();
+        report.path = paths::from_bytes(b"/src/caf\xe9");
+        let json = json(&[report]);
+        assert_eq!(json[0]["path"], r"/src/caf\xE9");
+        assert_eq!(json[0]["path_bytes"], json!(b"/src/caf\xe9".to_vec()));
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut report = unpushed_report();
+        report.branch = Some("feature,\"quoted\"".to_string());
+        report.remote = Some("origin".to_string());
+
+        assert_eq!(
+            csv(&[report]),
+            "path,status,branch,ahead,dirty,conflicted,shallow,partial,sparse,last_commit,remote,remote_url,findings\n\
+             /src/a|b,unpushed,\"feature,\"\"quoted\"\"\",2,true,0,false,false,false,1970-01-01 Initial commit,origin,,\n"
+        );
+    }
+
+    #[test]
+    fn test_sarif_results() {
+        let sarif = sarif(&[unpushed_report()]);
+
+        assert_eq!(sarif["version"], "2.1.0");
+        let result = &sarif["runs"][0]["results"][0];
+        assert_eq!(result["ruleId"], "unpushed");
+        assert_eq!(
+            result["message"]["text"],
+            "Branch main is 2 commit(s) ahead of its upstream"
+        );
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "/src/a|b"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Canonical paths that compare equal across platforms.
+//!
+//! On Windows, [`Path::canonicalize`] returns verbatim paths like `\\?\C:\src\repo`,
+//! which are awkward to read and don't match the same repository written as
+//! `c:\src\repo`. Paths used as keys, e.g. in the registry or scan history, go through
+//! [`canonicalize`] so every spelling ends up in the same form.
+//!
+//! Paths need not be valid UTF-8 on Unix. Output that can carry bytes writes them as
+//! they are, text output goes through [`escape`] so no two paths look the same.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Paths at least this long need the verbatim prefix on Windows.
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// Device names that only work in verbatim paths on Windows.
+#[cfg(windows)]
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Resolves `path` like [`Path::canonicalize`], then applies [`normalize`].
+///
+/// # Errors
+/// Returns an error if the path does not exist or cannot be resolved.
+pub fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    path.canonicalize().map(normalize)
+}
+
+/// Brings a canonical path into the form used for comparisons and output.
+///
+/// On Windows, the verbatim prefix is dropped unless the path needs it, i.e. it is
+/// longer than `MAX_PATH` or contains names that are only valid in verbatim paths,
+/// and the drive letter is upper-cased. Other platforms return the path unchanged.
+#[cfg(windows)]
+#[must_use]
+pub fn normalize(path: PathBuf) -> PathBuf {
+    match path.to_str() {
+        Some(s) => PathBuf::from(simplify(s)),
+        None => path,
+    }
+}
+
+#[cfg(not(windows))]
+#[must_use]
+pub fn normalize(path: PathBuf) -> PathBuf {
+    path
+}
+
+#[cfg(windows)]
+fn simplify(path: &str) -> String {
+    let (prefix, rest) = if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        (r"\\?\UNC\", rest)
+    } else if let Some(rest) = path.strip_prefix(r"\\?\") {
+        (r"\\?\", rest)
+    } else {
+        ("", path)
+    };
+    let rest = upper_case_drive_letter(rest);
+
+    if prefix.is_empty() || path.len() >= MAX_PATH || needs_verbatim(&rest) {
+        return format!("{prefix}{rest}");
+    }
+    if prefix == r"\\?\UNC\" {
+        format!(r"\\{rest}")
+    } else {
+        rest
+    }
+}
+
+#[cfg(windows)]
+fn upper_case_drive_letter(path: &str) -> String {
+    match path.as_bytes() {
+        [drive, b':', ..] if drive.is_ascii_alphabetic() => {
+            format!("{}{}", drive.to_ascii_uppercase() as char, &path[1..])
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// Whether a path without verbatim prefix would be interpreted differently by Windows.
+#[cfg(windows)]
+fn needs_verbatim(path: &str) -> bool {
+    path.contains('/')
+        || path.split('\\').any(|component| {
+            let stem = component.split('.').next().unwrap_or_default();
+            component.ends_with('.')
+                || component.ends_with(' ')
+                || RESERVED_NAMES
+                    .iter()
+                    .any(|name| stem.eq_ignore_ascii_case(name))
+        })
+}
+
+/// `path` as text, with bytes that are not valid UTF-8 written as `\xNN` instead of
+/// replacement characters.
+#[must_use]
+pub fn escape(path: &Path) -> Cow<'_, str> {
+    if let Some(path) = path.to_str() {
+        return Cow::Borrowed(path);
+    }
+    let mut escaped = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        escaped.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            let _ = write!(escaped, "\\x{byte:02X}");
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// `path` as text like [`escape`], with `/` as separator on every platform.
+#[must_use]
+pub fn escape_portable(path: &Path) -> Cow<'_, str> {
+    let escaped = escape(path);
+    if cfg!(windows) && escaped.contains('\\') {
+        Cow::Owned(escaped.replace('\\', "/"))
+    } else {
+        escaped
+    }
+}
+
+/// Quotes `path` as a single word for POSIX shells, e.g. in generated hook scripts.
+#[must_use]
+pub fn sh_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Quotes `path` as a single word for fish, which has escapes in single quotes.
+#[must_use]
+pub fn fish_quote(path: &Path) -> String {
+    format!(
+        "'{}'",
+        path.to_string_lossy()
+            .replace('\\', r"\\")
+            .replace('\'', r"\'")
+    )
+}
+
+/// The path with the bytes of [`OsStr::as_encoded_bytes`](std::ffi::OsStr::as_encoded_bytes),
+/// e.g. read from a file. Invalid UTF-8 is replaced outside of Unix.
+#[cfg(unix)]
+#[must_use]
+pub fn from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+#[must_use]
+pub fn from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(all(test, unix))]
+mod unix_tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_invalid_utf8() {
+        let path = from_bytes(b"/src/caf\xe9/\xff\xfe");
+        assert_eq!(escape(&path), r"/src/caf\xE9/\xFF\xFE");
+        assert_eq!(escape(Path::new("/src/café")), "/src/café");
+        assert_eq!(
+            path.as_os_str().as_encoded_bytes(),
+            b"/src/caf\xe9/\xff\xfe"
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        let path = Path::new(r"/opt/it's\here");
+        assert_eq!(sh_quote(path), r"'/opt/it'\''s\here'");
+        assert_eq!(fish_quote(path), r"'/opt/it\'s\\here'");
+    }
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simplify() {
+        assert_eq!(simplify(r"\\?\c:\src\repo"), r"C:\src\repo");
+        assert_eq!(simplify(r"c:\src\repo"), r"C:\src\repo");
+        assert_eq!(
+            simplify(r"\\?\UNC\server\share\repo"),
+            r"\\server\share\repo"
+        );
+        assert_eq!(simplify(r"\\?\C:\src\nul"), r"\\?\C:\src\nul");
+        assert_eq!(simplify(r"\\?\C:\src\trailing."), r"\\?\C:\src\trailing.");
+
+        let long = format!(r"\\?\C:\{}", "a".repeat(MAX_PATH));
+        assert_eq!(simplify(&long), long);
+    }
+
+    #[test]
+    fn test_canonicalize_matches_drive_letter_spellings() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().to_str().unwrap();
+        let lower = format!("{}{}", path[..1].to_ascii_lowercase(), &path[1..]);
+        let upper = format!("{}{}", path[..1].to_ascii_uppercase(), &path[1..]);
+        assert_eq!(
+            canonicalize(Path::new(&lower)).unwrap(),
+            canonicalize(Path::new(&upper)).unwrap()
+        );
+    }
+}
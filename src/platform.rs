@@ -0,0 +1,267 @@
+//! Platform-specific process tweaks.
+
+use anyhow::Result;
+#[cfg(any(target_os = "linux", not(unix)))]
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Niceness applied by `--nice`, the lowest CPU priority on Unix.
+#[cfg(unix)]
+const NICENESS: libc::c_int = 19;
+
+/// Lowers the CPU and, where supported, IO priority of the current process.
+///
+/// Must be called before any worker threads are spawned: on Linux, priorities are
+/// per thread and only inherited by threads created afterwards.
+///
+/// # Errors
+/// Returns an error if the priority cannot be changed or the platform is not supported.
+#[cfg(unix)]
+pub fn lower_priority() -> Result<()> {
+    // SAFETY: setpriority has no memory safety preconditions.
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, NICENESS) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    lower_io_priority()
+}
+
+#[cfg(not(unix))]
+pub fn lower_priority() -> Result<()> {
+    anyhow::bail!("Lowering process priority is not supported on this platform")
+}
+
+/// Moves the process into the idle IO scheduling class, like `ionice -c3`.
+#[cfg(target_os = "linux")]
+fn lower_io_priority() -> Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+
+    // SAFETY: ioprio_set only takes integer arguments.
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_ioprio_set,
+            IOPRIO_WHO_PROCESS,
+            0,
+            IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        )
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// IO priority is not adjustable through a stable API on other Unix platforms.
+#[cfg(all(unix, not(target_os = "linux")))]
+#[allow(clippy::unnecessary_wraps)]
+fn lower_io_priority() -> Result<()> {
+    Ok(())
+}
+
+/// Whether the machine is running on battery, `None` if unknown or without battery.
+#[cfg(target_os = "linux")]
+pub fn on_battery() -> Option<bool> {
+    on_battery_sysfs(Path::new("/sys/class/power_supply"))
+}
+
+#[cfg(target_os = "macos")]
+pub fn on_battery() -> Option<bool> {
+    let output = std::process::Command::new("pmset")
+        .args(["-g", "batt"])
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let first_line = stdout.lines().next()?;
+    Some(first_line.contains("'Battery Power'"))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn on_battery() -> Option<bool> {
+    None
+}
+
+/// Whether `path` cannot be written to, because the filesystem is mounted read-only or
+/// the current user lacks write permission.
+#[cfg(unix)]
+pub fn is_read_only(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: path is a valid NUL-terminated string that outlives the call.
+    // access fails with EROFS on read-only filesystems, even for root.
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) != 0 }
+}
+
+#[cfg(not(unix))]
+pub fn is_read_only(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().readonly())
+}
+
+/// The soft limit on open file descriptors of the process, `None` if unlimited or
+/// unknown.
+#[cfg(unix)]
+pub fn open_file_limit() -> Option<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: limit is a valid rlimit struct for getrlimit to fill in.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &raw mut limit) } != 0
+        || limit.rlim_cur == libc::RLIM_INFINITY
+    {
+        return None;
+    }
+    Some(limit.rlim_cur)
+}
+
+/// Windows has no per-process descriptor limit that a scan could run into.
+#[cfg(not(unix))]
+pub fn open_file_limit() -> Option<u64> {
+    None
+}
+
+/// A command that runs `command` through the system shell.
+#[cfg(unix)]
+pub fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+pub fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+/// Makes git and ssh in `command` fail instead of asking for credentials, so nothing
+/// can wait for an answer that never comes, e.g. in a scheduled run.
+///
+/// Prompts on the terminal are disabled through the environment, and on Unix the
+/// command also runs in a new session without a controlling terminal, so programs
+/// that open `/dev/tty` themselves, like ssh asking for a passphrase, fail too.
+#[cfg(not(feature = "read-only"))]
+pub fn deny_prompts(command: &mut Command) {
+    command
+        .env("GIT_TERMINAL_PROMPT", "0")
+        // An empty GIT_ASKPASS takes precedence over core.askPass and SSH_ASKPASS
+        .env("GIT_ASKPASS", "")
+        .env("SSH_ASKPASS_REQUIRE", "never")
+        .env("GCM_INTERACTIVE", "never");
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        // SAFETY: setsid is async-signal-safe and touches no memory of the parent.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+/// Lets ssh in `command` ask through the askpass program git uses, and prefer it over
+/// the terminal that parallel commands would otherwise take turns on.
+///
+/// git asks through `GIT_ASKPASS` or `SSH_ASKPASS`, but ssh only knows the latter and
+/// only uses it without a terminal unless `SSH_ASKPASS_REQUIRE` says otherwise.
+#[cfg(not(feature = "read-only"))]
+pub fn forward_askpass(command: &mut Command) {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let askpass = var("SSH_ASKPASS").or_else(|| var("GIT_ASKPASS"));
+    if let Some(askpass) = askpass {
+        command.env("SSH_ASKPASS", askpass);
+        if var("SSH_ASKPASS_REQUIRE").is_none() {
+            command.env("SSH_ASKPASS_REQUIRE", "prefer");
+        }
+    }
+}
+
+/// Quotes `path` as a single argument for [`shell_command`].
+#[cfg(unix)]
+pub fn shell_quote(path: &Path) -> String {
+    nasty_boii::paths::sh_quote(path)
+}
+
+#[cfg(windows)]
+pub fn shell_quote(path: &Path) -> String {
+    format!("\"{}\"", path.to_string_lossy())
+}
+
+/// Reads the power supplies exposed in sysfs, see `Documentation/ABI/testing/sysfs-class-power`.
+#[cfg(target_os = "linux")]
+fn on_battery_sysfs(power_supply_dir: &Path) -> Option<bool> {
+    let read = |dir: &Path, name: &str| {
+        fs::read_to_string(dir.join(name))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    let mut external_online = false;
+    let mut discharging = false;
+    let mut has_battery = false;
+    for entry in fs::read_dir(power_supply_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        let dir = entry.path();
+        match read(&dir, "type").as_str() {
+            "Battery" => {
+                has_battery = true;
+                discharging |= read(&dir, "status") == "Discharging";
+            }
+            "Mains" | "USB" => external_online |= read(&dir, "online") == "1",
+            _ => {}
+        }
+    }
+
+    has_battery.then_some(discharging && !external_online)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    fn power_supply(dir: &Path, name: &str, files: &[(&str, &str)]) {
+        let supply = dir.join(name);
+        fs::create_dir(&supply).unwrap();
+        for (file, content) in files {
+            fs::write(supply.join(file), format!("{content}\n")).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_on_battery_sysfs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        assert_eq!(on_battery_sysfs(temp_dir.path()), None);
+
+        power_supply(
+            temp_dir.path(),
+            "BAT0",
+            &[("type", "Battery"), ("status", "Discharging")],
+        );
+        assert_eq!(on_battery_sysfs(temp_dir.path()), Some(true));
+
+        power_supply(temp_dir.path(), "AC", &[("type", "Mains"), ("online", "1")]);
+        assert_eq!(on_battery_sysfs(temp_dir.path()), Some(false));
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        let path = Path::new("/src/it's here");
+        let output = shell_command(&format!("printf %s {}", shell_quote(path)))
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "/src/it's here");
+    }
+}
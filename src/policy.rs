@@ -0,0 +1,285 @@
+//! Rules deciding which audit findings are acceptable, read from a TOML policy file.
+//!
+//! ```toml
+//! # Findings no rule mentions are violations, like in a plain audit
+//! default = "deny"
+//!
+//! [[rule]]
+//! name = "work is pushed within a day"
+//! label = "work"
+//! deny = ["unpushed"]
+//! grace = "24h"
+//!
+//! [[rule]]
+//! path = "~/oss"
+//! allow = ["stashes"]
+//! ```
+//!
+//! Rules apply to repositories with their `label` in the registry and below their
+//! `path`, or to all repositories if they have neither. For each finding, the first
+//! rule applying to the repository that lists the finding's kind decides: `allow`
+//! accepts it, `deny` makes it a violation once it is as old as `grace`. Findings of
+//! unknown age, e.g. a dirty working tree, are violations right away.
+
+use crate::audit::{AuditReport, FindingKind};
+use crate::paths;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What happens to findings that no rule mentions.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Allow,
+    #[default]
+    Deny,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    default: Action,
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleFile>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    name: Option<String>,
+    label: Option<String>,
+    path: Option<PathBuf>,
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+    grace: Option<String>,
+}
+
+/// A rule of a [`Policy`].
+#[derive(Debug, PartialEq)]
+pub struct Rule {
+    /// Name for messages, `rule N` if the file does not give one
+    pub name: String,
+    pub label: Option<String>,
+    pub path: Option<PathBuf>,
+    pub allow: Vec<FindingKind>,
+    pub deny: Vec<FindingKind>,
+    /// Seconds a denied finding is accepted for
+    pub grace: i64,
+}
+
+/// Rules evaluated against audit reports.
+#[derive(Debug, PartialEq)]
+pub struct Policy {
+    pub default: Action,
+    pub rules: Vec<Rule>,
+}
+
+impl Policy {
+    /// Reads the policy file at `path`.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or is not a valid policy.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .context(format!("Failed to read policy file: {}", path.display()))?;
+        Self::parse(&contents).context(format!("Invalid policy file: {}", path.display()))
+    }
+
+    /// Parses a policy from TOML.
+    ///
+    /// # Errors
+    /// Returns an error if `contents` is not a valid policy.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let file: PolicyFile = toml::from_str(contents)?;
+        let rules = file
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(i, rule)| {
+                let name = rule.name.unwrap_or_else(|| format!("rule {}", i + 1));
+                let kinds = |kinds: Vec<String>| {
+                    kinds
+                        .iter()
+                        .map(|kind| kind.parse().map_err(anyhow::Error::msg))
+                        .collect::<Result<Vec<FindingKind>>>()
+                        .context(format!("In {name}"))
+                };
+                Ok(Rule {
+                    label: rule.label,
+                    path: rule.path.as_deref().map(expand_home),
+                    allow: kinds(rule.allow)?,
+                    deny: kinds(rule.deny)?,
+                    grace: rule
+                        .grace
+                        .as_deref()
+                        .map(parse_grace)
+                        .transpose()
+                        .context(format!("In {name}"))?
+                        .unwrap_or(0),
+                    name,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            default: file.default,
+            rules,
+        })
+    }
+
+    /// Drops the findings of `report` that the policy accepts at time `now`, keeping
+    /// the violations. `labels` are the registry labels of the repository.
+    pub fn apply(&self, report: &mut AuditReport, labels: &[String], now: i64) {
+        let canonical = paths::canonicalize(&report.path).unwrap_or_else(|_| report.path.clone());
+        let rules: Vec<&Rule> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.applies_to(&canonical, labels))
+            .collect();
+        report.findings.retain_mut(|finding| {
+            let rule = rules.iter().find(|rule| {
+                rule.allow.contains(&finding.kind) || rule.deny.contains(&finding.kind)
+            });
+            match rule {
+                Some(rule) if rule.allow.contains(&finding.kind) => false,
+                Some(rule) => {
+                    let violated = finding.since.is_none_or(|since| now - since >= rule.grace);
+                    if violated {
+                        finding.detail = format!("{} (violates {})", finding.detail, rule.name);
+                    }
+                    violated
+                }
+                None => self.default == Action::Deny,
+            }
+        });
+    }
+}
+
+impl Rule {
+    fn applies_to(&self, canonical: &Path, labels: &[String]) -> bool {
+        self.label
+            .as_ref()
+            .is_none_or(|label| labels.contains(label))
+            && self.path.as_ref().is_none_or(|path| {
+                let path = paths::canonicalize(path).unwrap_or_else(|_| path.clone());
+                canonical.starts_with(path)
+            })
+    }
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Parses a duration like `90m`, `24h` or `7d` into seconds.
+fn parse_grace(grace: &str) -> Result<i64> {
+    let split = grace
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(grace.len());
+    let (number, unit) = grace.split_at(split);
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!("Invalid grace {grace:?}, expected e.g. 90m, 24h or 7d"),
+    };
+    number
+        .parse::<i64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .context(format!(
+            "Invalid grace {grace:?}, expected e.g. 90m, 24h or 7d"
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::Finding;
+
+    fn report(path: &Path, findings: &[(FindingKind, Option<i64>)]) -> AuditReport {
+        AuditReport {
+            path: path.to_path_buf(),
+            findings: findings
+                .iter()
+                .map(|&(kind, since)| Finding {
+                    kind,
+                    detail: String::new(),
+                    since,
+                })
+                .collect(),
+        }
+    }
+
+    fn kinds(report: &AuditReport) -> Vec<FindingKind> {
+        report.findings.iter().map(|f| f.kind).collect()
+    }
+
+    #[test]
+    fn test_apply() {
+        let dir = tempfile::tempdir().unwrap();
+        let oss = dir.path().join("oss");
+        let policy = Policy::parse(&format!(
+            r#"
+            [[rule]]
+            label = "work"
+            deny = ["unpushed"]
+            grace = "24h"
+
+            [[rule]]
+            path = "{}"
+            allow = ["stashes"]
+            "#,
+            oss.display()
+        ))
+        .unwrap();
+        let findings = [
+            (FindingKind::Unpushed, Some(0)),
+            (FindingKind::Stashes, Some(0)),
+            (FindingKind::Dirty, None),
+        ];
+        let work = vec!["work".to_string()];
+
+        let mut recent = report(&dir.path().join("work"), &findings);
+        policy.apply(&mut recent, &work, 60 * 60);
+        assert_eq!(
+            kinds(&recent),
+            vec![FindingKind::Stashes, FindingKind::Dirty]
+        );
+
+        let mut old = report(&dir.path().join("work"), &findings);
+        policy.apply(&mut old, &work, 2 * 24 * 60 * 60);
+        assert_eq!(kinds(&old).len(), 3);
+
+        let mut oss = report(&oss.join("project"), &findings);
+        policy.apply(&mut oss, &[], 0);
+        assert_eq!(kinds(&oss), vec![FindingKind::Unpushed, FindingKind::Dirty]);
+    }
+
+    #[test]
+    fn test_default_allow() {
+        let policy = Policy::parse("default = \"allow\"\n[[rule]]\ndeny = [\"dirty\"]").unwrap();
+        let mut report = report(
+            Path::new("/src/repo"),
+            &[(FindingKind::Stashes, None), (FindingKind::Dirty, None)],
+        );
+        policy.apply(&mut report, &[], 0);
+        assert_eq!(kinds(&report), vec![FindingKind::Dirty]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Policy::parse("[[rule]]\nallow = [\"typo\"]").is_err());
+        assert!(Policy::parse("[[rule]]\ngrace = \"1w\"").is_err());
+        assert!(Policy::parse("[[rules]]").is_err());
+        assert_eq!(parse_grace("90m").unwrap(), 90 * 60);
+    }
+}
@@ -0,0 +1,272 @@
+//! Reading refs straight from the files in the git directory.
+//!
+//! Looking up references and branch config through git2 is most of what checking a
+//! clean repository costs. In the common case the checked-out branch points at the
+//! same commit as its upstream, which is plain to see from `HEAD`, the loose and packed
+//! refs and the repository config, without comparing any history, once the commit is
+//! found among the objects. Anything less plain, such as config includes, push
+//! remotes, custom refspecs or repository extensions like reftable, is left to git2.
+
+use git2::Config;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The git directory and the common git directory of the working directory
+/// `repo_path`, from its `.git` directory or the `.git` file of a linked worktree or
+/// submodule. `None` if there is neither.
+pub(crate) fn git_dirs(repo_path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let dot_git = repo_path.join(".git");
+    let git_dir = if dot_git.is_dir() {
+        dot_git
+    } else {
+        let content = fs::read_to_string(&dot_git).ok()?;
+        repo_path.join(content.strip_prefix("gitdir: ")?.trim_end())
+    };
+    let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common) => git_dir.join(common.trim_end()),
+        Err(_) => git_dir.clone(),
+    };
+    Some((git_dir, common_dir))
+}
+
+/// The branch HEAD is on if it points at the same commit as its upstream, `None` if it
+/// doesn't or if that cannot be told from the files alone.
+///
+/// `git_dir` holds `HEAD`, `common_dir` the refs and config; they differ in linked
+/// worktrees.
+pub(crate) fn branch_in_sync(git_dir: &Path, common_dir: &Path) -> Option<String> {
+    if !plain_global_config() {
+        return None;
+    }
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let branch = head.strip_prefix("ref: refs/heads/")?.trim_end();
+    if branch
+        .split('/')
+        .any(|part| part.is_empty() || part == "..")
+    {
+        return None;
+    }
+
+    let config = Config::open(&common_dir.join("config")).ok()?;
+    if !is_plain(&config, |_| false) {
+        return None;
+    }
+    let value = |name: String| config.get_entry(&name).ok()?.value().map(str::to_string);
+    let remote = value(format!("branch.{branch}.remote"))?;
+    let merge = value(format!("branch.{branch}.merge"))?;
+    let tracking = tracking_ref(&config, &remote, &merge)?;
+
+    let local = resolve(common_dir, &format!("refs/heads/{branch}"))?;
+    // The ref files outlive the objects they point to, e.g. after a broken copy
+    (resolve(common_dir, &tracking)? == local && has_object(common_dir, &local))
+        .then(|| branch.to_string())
+}
+
+/// Whether the global and system config leave branch config to the repositories, so
+/// that only the repository config needs to be read. Read once per process.
+fn plain_global_config() -> bool {
+    static PLAIN: OnceLock<bool> = OnceLock::new();
+    *PLAIN.get_or_init(|| {
+        Config::open_default()
+            .is_ok_and(|config| is_plain(&config, |name| name.starts_with("branch.")))
+    })
+}
+
+/// Whether `config` has none of the entries that change which refs git compares, nor
+/// any matching `also_rejected`. Names are compared lowercase.
+fn is_plain(config: &Config, also_rejected: impl Fn(&str) -> bool) -> bool {
+    let Ok(mut entries) = config.entries(None) else {
+        return false;
+    };
+    while let Some(entry) = entries.next() {
+        let Ok(entry) = entry else {
+            return false;
+        };
+        let Some(name) = entry.name().map(str::to_ascii_lowercase) else {
+            return false;
+        };
+        if name.starts_with("include.")
+            || name.starts_with("includeif.")
+            || name.starts_with("extensions.")
+            || name == "remote.pushdefault"
+            || (name.starts_with("branch.") && name.ends_with(".pushremote"))
+            || also_rejected(&name)
+        {
+            return false;
+        }
+    }
+    true
+}
+
+/// The remote-tracking ref of `merge` on `remote`, if the remote fetches with the
+/// default refspec.
+fn tracking_ref(config: &Config, remote: &str, merge: &str) -> Option<String> {
+    let branch = merge.strip_prefix("refs/heads/")?;
+    let mut refspecs = Vec::new();
+    let mut entries = config
+        .multivar(&format!("remote.{remote}.fetch"), None)
+        .ok()?;
+    while let Some(entry) = entries.next() {
+        refspecs.push(entry.ok()?.value()?.to_string());
+    }
+    let default = format!("refs/heads/*:refs/remotes/{remote}/*");
+    match refspecs.as_slice() {
+        [refspec] if refspec.trim_start_matches('+') == default => {
+            Some(format!("refs/remotes/{remote}/{branch}"))
+        }
+        _ => None,
+    }
+}
+
+/// The object id `name` points to, from its loose ref file or from `packed-refs`.
+fn resolve(common_dir: &Path, name: &str) -> Option<String> {
+    match fs::read_to_string(common_dir.join(name)) {
+        Ok(content) => object_id(content.trim_end()),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let packed = fs::read_to_string(common_dir.join("packed-refs")).ok()?;
+            packed
+                .lines()
+                .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+                .filter_map(|line| line.split_once(' '))
+                .find(|(_, ref_name)| *ref_name == name)
+                .and_then(|(oid, _)| object_id(oid))
+        }
+        Err(_) => None,
+    }
+}
+
+/// Whether the object `oid` is in the object database of `common_dir`, loose or in a
+/// pack. Objects only in alternates count as missing.
+fn has_object(common_dir: &Path, oid: &str) -> bool {
+    let objects = common_dir.join("objects");
+    if objects.join(&oid[..2]).join(&oid[2..]).is_file() {
+        return true;
+    }
+    let Ok(entries) = fs::read_dir(objects.join("pack")) else {
+        return false;
+    };
+    let id = object_bytes(oid);
+    entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "idx"))
+        .any(|idx| pack_index_contains(&idx, &id).unwrap_or(false))
+}
+
+/// Whether the version 2 pack index at `path` lists the object `id`, reading only the
+/// ids that share its first byte. See `gitformat-pack(5)`.
+fn pack_index_contains(path: &Path, id: &[u8]) -> io::Result<bool> {
+    const HEADER: [u8; 8] = [0xff, b't', b'O', b'c', 0, 0, 0, 2];
+    let mut file = File::open(path)?;
+    let mut header = [0; HEADER.len() + 256 * 4];
+    file.read_exact(&mut header)?;
+    if header[..HEADER.len()] != HEADER {
+        return Ok(false);
+    }
+    // Number of objects whose first byte is at most the index
+    let fanout: Vec<u32> = header[HEADER.len()..]
+        .chunks_exact(4)
+        .map(|count| u32::from_be_bytes([count[0], count[1], count[2], count[3]]))
+        .collect();
+    let first = usize::from(id[0]);
+    let start = if first == 0 { 0 } else { fanout[first - 1] };
+    let end = fanout[first];
+    if end <= start {
+        return Ok(false);
+    }
+    let len = u64::try_from(id.len()).map_err(io::Error::other)?;
+    let offset = u64::try_from(header.len()).map_err(io::Error::other)? + u64::from(start) * len;
+    file.seek(SeekFrom::Start(offset))?;
+    let size = usize::try_from(u64::from(end - start) * len).map_err(io::Error::other)?;
+    let mut ids = vec![0; size];
+    file.read_exact(&mut ids)?;
+    Ok(ids.chunks_exact(id.len()).any(|entry| entry == id))
+}
+
+/// The bytes of the hexadecimal object id `oid`, as checked by [`object_id`].
+fn object_bytes(oid: &str) -> Vec<u8> {
+    oid.as_bytes()
+        .chunks_exact(2)
+        .filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// `id` if it is a full SHA-1 or SHA-256 object id, lowercase.
+fn object_id(id: &str) -> Option<String> {
+    (matches!(id.len(), 40 | 64) && id.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| id.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+    }
+
+    #[test]
+    fn test_branch_in_sync() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo = temp_dir.path().join("repo");
+        git(
+            temp_dir.path(),
+            &["init", "--bare", "-b", "main", "remote.git"],
+        );
+        git(temp_dir.path(), &["clone", "-q", "remote.git", "repo"]);
+        git(&repo, &["config", "user.name", "Test User"]);
+        git(&repo, &["config", "user.email", "test@example.com"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "Initial"]);
+        git(&repo, &["push", "-q", "origin", "main"]);
+        let git_dir = repo.join(".git");
+
+        assert_eq!(git_dirs(&repo), Some((git_dir.clone(), git_dir.clone())));
+        assert_eq!(branch_in_sync(&git_dir, &git_dir).as_deref(), Some("main"));
+        git(&repo, &["pack-refs", "--all"]);
+        assert!(!git_dir.join("refs/heads/main").exists());
+        assert_eq!(branch_in_sync(&git_dir, &git_dir).as_deref(), Some("main"));
+
+        // Left to git2
+        git(&repo, &["config", "branch.main.pushRemote", "origin"]);
+        assert_eq!(branch_in_sync(&git_dir, &git_dir), None);
+        git(&repo, &["config", "--unset", "branch.main.pushRemote"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "Unpushed"]);
+        assert_eq!(branch_in_sync(&git_dir, &git_dir), None);
+        git(&repo, &["push", "-q", "origin", "main"]);
+        assert_eq!(branch_in_sync(&git_dir, &git_dir).as_deref(), Some("main"));
+        git(&repo, &["checkout", "-q", "--detach"]);
+        assert_eq!(branch_in_sync(&git_dir, &git_dir), None);
+    }
+
+    #[test]
+    fn test_has_object() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo = temp_dir.path();
+        git(repo, &["init", "-q", "-b", "main"]);
+        git(repo, &["config", "user.name", "Test User"]);
+        git(repo, &["config", "user.email", "test@example.com"]);
+        git(repo, &["commit", "-q", "--allow-empty", "-m", "Initial"]);
+        let git_dir = repo.join(".git");
+        let head = resolve(&git_dir, "refs/heads/main").unwrap();
+        let missing = "0".repeat(head.len());
+
+        assert!(has_object(&git_dir, &head));
+        assert!(!has_object(&git_dir, &missing));
+        git(repo, &["repack", "-q", "-a", "-d"]);
+        git(repo, &["prune-packed"]);
+        assert!(!git_dir
+            .join("objects")
+            .join(&head[..2])
+            .join(&head[2..])
+            .exists());
+        assert!(has_object(&git_dir, &head));
+        assert!(!has_object(&git_dir, &missing));
+    }
+}
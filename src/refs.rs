@@ -0,0 +1,213 @@
+use anyhow::{Context, Result};
+use git2::{Oid, Reference, Repository};
+use std::collections::HashMap;
+
+/// Namespaces holding copies of remote state rather than local work.
+const REMOTE_TRACKING_PREFIXES: &[&str] =
+    &["refs/remotes/", "refs/notes/remotes/", "refs/prefetch/"];
+
+/// Local namespaces that are covered by other checks or are local by nature.
+const SKIPPED_PREFIXES: &[&str] = &["refs/heads/", "refs/stash", "refs/bisect/"];
+
+/// Local refs outside of `refs/heads` whose targets no remote-tracking ref contains,
+/// e.g. `refs/replace/*`, tags or refs written by tools like git-annex.
+///
+/// Returns full ref names, sorted.
+pub(crate) fn unpushed_refs(repo: &Repository) -> Result<Vec<String>> {
+    let mut local: Vec<(String, Oid)> = Vec::new();
+    let mut remote: Vec<Oid> = Vec::new();
+
+    for reference in repo
+        .references_glob("refs/*")
+        .context("Failed to list references")?
+    {
+        let reference = reference.context("Failed to read reference")?;
+        let Some(name) = reference.name().map(str::to_string) else {
+            continue;
+        };
+        let Some(target) = peeled_target(&reference) else {
+            continue;
+        };
+
+        if REMOTE_TRACKING_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+        {
+            remote.push(target);
+        } else if !SKIPPED_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+        {
+            local.push((name, target));
+        }
+    }
+
+    let mut unpushed: Vec<String> = local
+        .into_iter()
+        .filter(|(_, oid)| !is_contained(repo, *oid, &remote))
+        .map(|(name, _)| name)
+        .collect();
+    unpushed.sort();
+    Ok(unpushed)
+}
+
+/// The commit a ref points at, or the raw target for refs to trees and blobs.
+fn peeled_target(reference: &Reference<'_>) -> Option<Oid> {
+    reference
+        .peel_to_commit()
+        .map(|commit| commit.id())
+        .ok()
+        .or_else(|| reference.target())
+}
+
+/// Whether `oid` is one of `remote_oids` or an ancestor of one of them.
+pub(crate) fn is_contained(repo: &Repository, oid: Oid, remote_oids: &[Oid]) -> bool {
+    remote_oids.iter().any(|&remote_oid| {
+        remote_oid == oid || repo.graph_descendant_of(remote_oid, oid).unwrap_or(false)
+    })
+}
+
+/// Local notes refs (`refs/notes/*`) whose commits are not on any remote.
+///
+/// Notes are not fetched by default, so they are compared against notes fetched
+/// into remote-tracking refs, either `refs/notes/remotes/<remote>/<name>` or
+/// `refs/remotes/<remote>/notes/<name>`. Returns the short names, e.g. `commits`.
+pub(crate) fn unpushed_notes(repo: &Repository) -> Result<Vec<String>> {
+    let mut local: Vec<(String, Oid)> = Vec::new();
+    let mut remote: HashMap<String, Vec<Oid>> = HashMap::new();
+
+    for reference in repo
+        .references_glob("refs/*")
+        .context("Failed to list references")?
+    {
+        let reference = reference.context("Failed to read reference")?;
+        let (Some(name), Some(target)) = (reference.name(), reference.target()) else {
+            continue;
+        };
+
+        if let Some(tracked) = name
+            .strip_prefix("refs/notes/remotes/")
+            .and_then(|rest| rest.split_once('/'))
+            .map(|(_remote, notes)| notes)
+            .or_else(|| {
+                name.strip_prefix("refs/remotes/")
+                    .and_then(|rest| rest.split_once("/notes/"))
+                    .map(|(_remote, notes)| notes)
+            })
+        {
+            remote.entry(tracked.to_string()).or_default().push(target);
+        } else if let Some(notes) = name.strip_prefix("refs/notes/") {
+            local.push((notes.to_string(), target));
+        }
+    }
+
+    let mut unpushed: Vec<String> = local
+        .into_iter()
+        .filter(|(notes, oid)| {
+            !remote
+                .get(notes)
+                .is_some_and(|remote_oids| is_contained(repo, *oid, remote_oids))
+        })
+        .map(|(notes, _)| notes)
+        .collect();
+    unpushed.sort();
+    Ok(unpushed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::process::Command;
+
+    fn git(repo_path: &Path, args: &[&str]) {
+        Command::new("git")
+            .args(args)
+            .current_dir(repo_path)
+            .output()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_unpushed_notes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        let remote_path = temp_dir.path().join("remote.git");
+        Command::new("git")
+            .args(["init", "--bare"])
+            .arg(&remote_path)
+            .output()
+            .unwrap();
+        Command::new("git")
+            .args(["init"])
+            .arg(&repo_path)
+            .output()
+            .unwrap();
+        git(&repo_path, &["config", "user.name", "Test User"]);
+        git(&repo_path, &["config", "user.email", "test@example.com"]);
+        git(
+            &repo_path,
+            &["commit", "--allow-empty", "-m", "Initial commit"],
+        );
+        git(
+            &repo_path,
+            &["remote", "add", "origin", remote_path.to_str().unwrap()],
+        );
+        git(&repo_path, &["notes", "add", "-m", "Reviewed"]);
+
+        let repo = Repository::open(&repo_path).unwrap();
+        assert_eq!(unpushed_notes(&repo).unwrap(), vec!["commits".to_string()]);
+
+        git(&repo_path, &["push", "origin", "refs/notes/*"]);
+        git(
+            &repo_path,
+            &[
+                "fetch",
+                "origin",
+                "refs/notes/*:refs/notes/remotes/origin/*",
+            ],
+        );
+        assert!(unpushed_notes(&repo).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unpushed_refs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        let remote_path = temp_dir.path().join("remote.git");
+        Command::new("git")
+            .args(["init", "--bare"])
+            .arg(&remote_path)
+            .output()
+            .unwrap();
+        Command::new("git")
+            .args(["init"])
+            .arg(&repo_path)
+            .output()
+            .unwrap();
+        git(&repo_path, &["config", "user.name", "Test User"]);
+        git(&repo_path, &["config", "user.email", "test@example.com"]);
+        git(
+            &repo_path,
+            &["commit", "--allow-empty", "-m", "Initial commit"],
+        );
+        git(
+            &repo_path,
+            &["remote", "add", "origin", remote_path.to_str().unwrap()],
+        );
+        git(&repo_path, &["push", "-u", "origin", "main"]);
+        git(&repo_path, &["tag", "pushed"]);
+        git(
+            &repo_path,
+            &["commit", "--allow-empty", "-m", "Replacement"],
+        );
+        git(&repo_path, &["update-ref", "refs/custom/tool", "HEAD"]);
+        git(&repo_path, &["reset", "--hard", "origin/main"]);
+
+        let repo = Repository::open(&repo_path).unwrap();
+        assert_eq!(
+            unpushed_refs(&repo).unwrap(),
+            vec!["refs/custom/tool".to_string()]
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use crate::db::Db;
+use crate::paths;
+use anyhow::{bail, Context, Result};
+use git2::Repository;
+use rusqlite::params;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// The persistent list of known repositories.
+#[derive(Debug)]
+pub struct Registry<'a> {
+    db: &'a Db,
+}
+
+impl<'a> Registry<'a> {
+    #[must_use]
+    pub fn new(db: &'a Db) -> Self {
+        Self { db }
+    }
+
+    /// Adds the repository at `repo_path`, returning its canonical path.
+    ///
+    /// # Errors
+    /// Returns an error if the path does not exist, is not a git repository or
+    /// cannot be stored.
+    pub fn add(&self, repo_path: &Path) -> Result<PathBuf> {
+        let canonical = paths::canonicalize(repo_path)
+            .context(format!("Failed to resolve path: {}", repo_path.display()))?;
+        if Repository::open(&canonical).is_err() {
+            bail!("Not a git repository: {}", canonical.display());
+        }
+
+        self.db
+            .conn()
+            .execute(
+                "INSERT OR IGNORE INTO registry (path) VALUES (?1)",
+                params![canonical.to_string_lossy()],
+            )
+            .context("Failed to register repository")?;
+        Ok(canonical)
+    }
+
+    /// Removes `repo_path` from the registry.
+    ///
+    /// # Errors
+    /// Returns an error if the path is not registered.
+    pub fn remove(&self, repo_path: &Path) -> Result<PathBuf> {
+        // Registered repos may have been deleted since, so fall back to the given path
+        let path = paths::canonicalize(repo_path).unwrap_or_else(|_| repo_path.to_path_buf());
+        let removed = self
+            .db
+            .conn()
+            .execute(
+                "DELETE FROM registry WHERE path = ?1",
+                params![path.to_string_lossy()],
+            )
+            .context("Failed to unregister repository")?;
+        if removed == 0 {
+            bail!("Not registered: {}", path.display());
+        }
+        Ok(path)
+    }
+
+    /// Registered repositories, sorted by path.
+    ///
+    /// # Errors
+    /// Returns an error if the registry cannot be read.
+    pub fn repos(&self) -> Result<Vec<PathBuf>> {
+        let mut stmt = self
+            .db
+            .conn()
+            .prepare("SELECT path FROM registry ORDER BY path")?;
+        let repos = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|path| path.map(PathBuf::from))
+            .collect::<Result<_, _>>()
+            .context("Failed to read registry")?;
+        Ok(repos)
+    }
+
+    /// Attaches `labels` to the registered repository at `repo_path`, returning its
+    /// canonical path.
+    ///
+    /// # Errors
+    /// Returns an error if the path is not registered or the labels cannot be stored.
+    pub fn add_labels(&self, repo_path: &Path, labels: &[String]) -> Result<PathBuf> {
+        let path = self.registered(repo_path)?;
+        for label in labels {
+            self.db
+                .conn()
+                .execute(
+                    "INSERT OR IGNORE INTO labels (path, label) VALUES (?1, ?2)",
+                    params![path.to_string_lossy(), label],
+                )
+                .context("Failed to add label")?;
+        }
+        Ok(path)
+    }
+
+    /// Detaches `labels` from the registered repository at `repo_path`, returning its
+    /// canonical path. Labels it does not have are ignored.
+    ///
+    /// # Errors
+    /// Returns an error if the path is not registered or the labels cannot be removed.
+    pub fn remove_labels(&self, repo_path: &Path, labels: &[String]) -> Result<PathBuf> {
+        let path = self.registered(repo_path)?;
+        for label in labels {
+            self.db
+                .conn()
+                .execute(
+                    "DELETE FROM labels WHERE path = ?1 AND label = ?2",
+                    params![path.to_string_lossy(), label],
+                )
+                .context("Failed to remove label")?;
+        }
+        Ok(path)
+    }
+
+    /// Labels of the repository at `path`, given canonical, sorted.
+    ///
+    /// # Errors
+    /// Returns an error if the registry cannot be read.
+    pub fn labels(&self, path: &Path) -> Result<Vec<String>> {
+        let mut stmt = self
+            .db
+            .conn()
+            .prepare("SELECT label FROM labels WHERE path = ?1 ORDER BY label")?;
+        let labels = stmt
+            .query_map([path.to_string_lossy()], |row| row.get(0))?
+            .collect::<Result<_, _>>()
+            .context("Failed to read labels")?;
+        Ok(labels)
+    }
+
+    /// Canonical paths of the registered repositories with any of `labels`.
+    ///
+    /// # Errors
+    /// Returns an error if the registry cannot be read.
+    pub fn labelled(&self, labels: &[String]) -> Result<BTreeSet<PathBuf>> {
+        let mut stmt = self
+            .db
+            .conn()
+            .prepare("SELECT path FROM labels WHERE label = ?1")?;
+        let mut repos = BTreeSet::new();
+        for label in labels {
+            for path in stmt.query_map([label], |row| row.get::<_, String>(0))? {
+                repos.insert(PathBuf::from(path.context("Failed to read labels")?));
+            }
+        }
+        Ok(repos)
+    }
+
+    /// Canonical path of `repo_path`, which must be registered.
+    fn registered(&self, repo_path: &Path) -> Result<PathBuf> {
+        let path = paths::canonicalize(repo_path).unwrap_or_else(|_| repo_path.to_path_buf());
+        if !self.repos()?.contains(&path) {
+            bail!(
+                "Not registered: {}, add it with `nasty-boii register` first",
+                path.display()
+            );
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_add_list_remove() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        Command::new("git")
+            .args(["init"])
+            .arg(&repo_path)
+            .output()
+            .unwrap();
+        let db = Db::open(&temp_dir.path().join("data").join("nasty-boii.db")).unwrap();
+
+        let registry = Registry::new(&db);
+        assert!(registry.repos().unwrap().is_empty());
+        let added = registry.add(&repo_path).unwrap();
+        // Adding twice is a no-op
+        registry.add(&repo_path).unwrap();
+
+        assert_eq!(registry.repos().unwrap(), vec![added]);
+        registry.remove(&repo_path).unwrap();
+        assert!(registry.remove(&repo_path).is_err());
+    }
+
+    #[test]
+    fn test_labels() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        Command::new("git")
+            .args(["init"])
+            .arg(&repo_path)
+            .output()
+            .unwrap();
+        let db = Db::open(&temp_dir.path().join("nasty-boii.db")).unwrap();
+        let registry = Registry::new(&db);
+        let labels = |labels: &[&str]| labels.iter().map(ToString::to_string).collect::<Vec<_>>();
+
+        assert!(registry.add_labels(&repo_path, &labels(&["work"])).is_err());
+        let added = registry.add(&repo_path).unwrap();
+        registry
+            .add_labels(&repo_path, &labels(&["work", "critical", "work"]))
+            .unwrap();
+        assert_eq!(
+            registry.labels(&added).unwrap(),
+            labels(&["critical", "work"])
+        );
+        assert_eq!(
+            registry.labelled(&labels(&["oss", "work"])).unwrap(),
+            BTreeSet::from([added.clone()])
+        );
+
+        registry
+            .remove_labels(&repo_path, &labels(&["work"]))
+            .unwrap();
+        assert!(registry.labelled(&labels(&["work"])).unwrap().is_empty());
+        // Unregistering drops the labels
+        registry.remove(&repo_path).unwrap();
+        assert!(registry.labels(&added).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_add_rejects_non_repo() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Db::open(&temp_dir.path().join("nasty-boii.db")).unwrap();
+
+        assert!(Registry::new(&db).add(temp_dir.path()).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+//! Checkpoints of running scans, so that an interrupted scan can continue where it
+//! stopped, e.g. after hours on slow network storage.
+//!
+//! While a scan runs, every checked repository is stored with its result, and so is
+//! every directory directly below the search path once all repositories in it were
+//! checked. Progress is stored in batches of [`Progress`], each in one transaction. A resumed scan doesn't walk completed directories again and reuses the
+//! stored results instead of checking repositories again. Paths are stored relative to
+//! the search path. A scan that runs to the end removes its checkpoint.
+
+use crate::db::Db;
+use anyhow::{Context, Result};
+use rusqlite::params;
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+/// Progress of an interrupted scan.
+#[derive(Debug, Default, PartialEq)]
+pub struct Checkpoint {
+    /// Whether each checked repository was nasty
+    pub repos: HashMap<PathBuf, bool>,
+    /// Directories below the search path whose repositories were all checked
+    pub subtrees: HashSet<PathBuf>,
+}
+
+impl Checkpoint {
+    /// Checked repositories in completed directories, which a resumed scan doesn't
+    /// walk again.
+    pub fn completed_repos(&self) -> impl Iterator<Item = &Path> {
+        self.repos
+            .keys()
+            .filter(|path| subtree(path).is_some_and(|subtree| self.subtrees.contains(&subtree)))
+            .map(PathBuf::as_path)
+    }
+}
+
+/// A step of a running scan, with paths relative to the search path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    /// The repository at the path was checked
+    Repo { path: PathBuf, nasty: bool },
+    /// All repositories in the directory were checked
+    Subtree(PathBuf),
+}
+
+/// Checkpoints of scans, stored in the database by scan root.
+#[derive(Debug)]
+pub struct Checkpoints<'a> {
+    db: &'a Db,
+}
+
+impl<'a> Checkpoints<'a> {
+    #[must_use]
+    pub fn new(db: &'a Db) -> Self {
+        Self { db }
+    }
+
+    /// The checkpoint of an interrupted scan of `root`, empty if there is none.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be read.
+    pub fn load(&self, root: &str) -> Result<Checkpoint> {
+        let conn = self.db.conn();
+        let repos = conn
+            .prepare("SELECT path, nasty FROM checkpoint_repos WHERE root = ?1")?
+            .query_map([root], |row| {
+                Ok((PathBuf::from(row.get::<_, String>(0)?), row.get(1)?))
+            })?
+            .collect::<rusqlite::Result<_>>()
+            .context("Failed to read checkpoint")?;
+        let subtrees = conn
+            .prepare("SELECT path FROM checkpoint_subtrees WHERE root = ?1")?
+            .query_map([root], |row| Ok(PathBuf::from(row.get::<_, String>(0)?)))?
+            .collect::<rusqlite::Result<_>>()
+            .context("Failed to read checkpoint")?;
+        Ok(Checkpoint { repos, subtrees })
+    }
+
+    /// Stores `progress` in one transaction. Later steps may depend on earlier ones, so
+    /// they are stored in order and all or none of them are.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be written.
+    pub fn add(&self, root: &str, progress: &[Progress]) -> Result<()> {
+        let tx = self.db.conn().unchecked_transaction()?;
+        for step in progress {
+            match step {
+                Progress::Repo { path, nasty } => tx.execute(
+                    "INSERT OR REPLACE INTO checkpoint_repos (root, path, nasty) \
+                     VALUES (?1, ?2, ?3)",
+                    params![root, path.to_string_lossy(), nasty],
+                ),
+                Progress::Subtree(path) => tx.execute(
+                    "INSERT OR IGNORE INTO checkpoint_subtrees (root, path) VALUES (?1, ?2)",
+                    params![root, path.to_string_lossy()],
+                ),
+            }
+            .context("Failed to update checkpoint")?;
+        }
+        tx.commit().context("Failed to update checkpoint")
+    }
+
+    /// Stores the result of the repository at `path`, relative to the search path.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be written.
+    pub fn add_repo(&self, root: &str, path: &Path, nasty: bool) -> Result<()> {
+        self.add(
+            root,
+            &[Progress::Repo {
+                path: path.to_path_buf(),
+                nasty,
+            }],
+        )
+    }
+
+    /// Marks the directory `path`, relative to the search path, as completed.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be written.
+    pub fn add_subtree(&self, root: &str, path: &Path) -> Result<()> {
+        self.add(root, &[Progress::Subtree(path.to_path_buf())])
+    }
+
+    /// Removes the checkpoint of `root`, when a scan starts over or is complete.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be written.
+    pub fn clear(&self, root: &str) -> Result<()> {
+        let tx = self.db.conn().unchecked_transaction()?;
+        tx.execute("DELETE FROM checkpoint_repos WHERE root = ?1", [root])?;
+        tx.execute("DELETE FROM checkpoint_subtrees WHERE root = ?1", [root])?;
+        tx.commit().context("Failed to clear checkpoint")
+    }
+}
+
+/// Tells when all repositories in a directory below the search path were checked.
+///
+/// Repositories must be passed to [`SubtreeTracker::discovered`] in the order the
+/// walker finds them, which leaves one directory before entering the next, and to
+/// [`SubtreeTracker::checked`] in any order.
+#[derive(Debug, Default)]
+pub struct SubtreeTracker {
+    /// Repositories found but not checked yet, by directory
+    pending: HashMap<PathBuf, usize>,
+    /// The directory the walker is in
+    current: Option<PathBuf>,
+}
+
+impl SubtreeTracker {
+    /// Notes a repository found at `path`, relative to the search path. Returns the
+    /// directory the walker left if that completed it.
+    pub fn discovered(&mut self, path: &Path) -> Option<PathBuf> {
+        let subtree = subtree(path)?;
+        *self.pending.entry(subtree.clone()).or_default() += 1;
+        if self.current.as_ref() == Some(&subtree) {
+            return None;
+        }
+        let left = self.current.replace(subtree)?;
+        (!self.pending.contains_key(&left)).then_some(left)
+    }
+
+    /// Notes that the repository at `path`, relative to the search path, was checked.
+    /// Returns its directory if that completed it.
+    pub fn checked(&mut self, path: &Path) -> Option<PathBuf> {
+        let subtree = subtree(path)?;
+        let pending = self.pending.get_mut(&subtree)?;
+        *pending -= 1;
+        if *pending > 0 {
+            return None;
+        }
+        self.pending.remove(&subtree);
+        (self.current.as_ref() != Some(&subtree)).then_some(subtree)
+    }
+}
+
+/// The directory directly below the search path that `path` is in, `None` for the
+/// search path itself.
+fn subtree(path: &Path) -> Option<PathBuf> {
+    match path.components().next()? {
+        Component::Normal(name) => Some(PathBuf::from(name)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoints() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Db::open(&temp_dir.path().join("nasty-boii.db")).unwrap();
+        let checkpoints = Checkpoints::new(&db);
+
+        checkpoints
+            .add_repo("/src", Path::new("a/x"), true)
+            .unwrap();
+        checkpoints
+            .add(
+                "/src",
+                &[
+                    Progress::Repo {
+                        path: PathBuf::from("b/y"),
+                        nasty: false,
+                    },
+                    Progress::Subtree(PathBuf::from("a")),
+                ],
+            )
+            .unwrap();
+        checkpoints
+            .add_repo("/other", Path::new("z"), true)
+            .unwrap();
+
+        let checkpoint = checkpoints.load("/src").unwrap();
+        assert_eq!(checkpoint.repos.len(), 2);
+        assert!(checkpoint.repos[Path::new("a/x")]);
+        assert_eq!(checkpoint.subtrees, HashSet::from([PathBuf::from("a")]));
+        assert_eq!(
+            checkpoint.completed_repos().collect::<Vec<_>>(),
+            [Path::new("a/x")]
+        );
+
+        checkpoints.clear("/src").unwrap();
+        assert_eq!(checkpoints.load("/src").unwrap(), Checkpoint::default());
+        assert_eq!(checkpoints.load("/other").unwrap().repos.len(), 1);
+    }
+
+    #[test]
+    fn test_subtree_tracker() {
+        let mut tracker = SubtreeTracker::default();
+        assert_eq!(tracker.discovered(Path::new("a/x")), None);
+        assert_eq!(tracker.discovered(Path::new("a/y")), None);
+        assert_eq!(tracker.checked(Path::new("a/x")), None);
+        // The walker left a, but a/y is still being checked
+        assert_eq!(tracker.discovered(Path::new("b/z")), None);
+        assert_eq!(tracker.checked(Path::new("a/y")), Some(PathBuf::from("a")));
+
+        // Checked before the walker left c
+        assert_eq!(tracker.discovered(Path::new("c/w")), None);
+        assert_eq!(tracker.checked(Path::new("b/z")), Some(PathBuf::from("b")));
+        assert_eq!(tracker.checked(Path::new("c/w")), None);
+        assert_eq!(
+            tracker.discovered(Path::new("d/v")),
+            Some(PathBuf::from("c"))
+        );
+
+        // The search path itself is no directory below it
+        assert_eq!(tracker.discovered(Path::new("")), None);
+        assert_eq!(tracker.checked(Path::new("")), None);
+    }
+}
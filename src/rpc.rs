@@ -0,0 +1,191 @@
+//! JSON-RPC 2.0 over stdin/stdout, for editor extensions embedding nasty-boii.
+//!
+//! Messages are single lines of JSON. Methods:
+//! - `scan {"root": PATH}` returns the nasty repos like `--format json` and records the
+//!   scan in the history.
+//! - `status {"path": PATH}` returns the report of a single repository, nasty or not.
+//! - `subscribe {"root": PATH, "interval": SECONDS}` returns a subscription id and sends
+//!   a `nasty` notification with the paths of the nasty repos whenever they change,
+//!   starting with the current ones. `interval` defaults to 60 seconds.
+//! - `unsubscribe {"subscription": ID}` stops a subscription.
+//!
+//! The process exits when stdin is closed.
+
+use crate::serve;
+use anyhow::{Context, Result};
+use nasty_boii::scanner::Scanner;
+use nasty_boii::{output, CheckSet, RepoReport};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Application error, e.g. a path that is not a repository
+const SERVER_ERROR: i64 = -32000;
+
+const DEFAULT_INTERVAL: Duration = Duration::from_mins(1);
+/// How often subscriptions check whether they were cancelled
+const CANCEL_POLL: Duration = Duration::from_millis(100);
+
+/// Writes a message as one line, holding the lock so subscriptions don't interleave.
+fn send(message: &Value) {
+    let mut stdout = io::stdout().lock();
+    // The client is gone if stdout is closed, the loop ends with stdin
+    let _ = writeln!(stdout, "{message}");
+    let _ = stdout.flush();
+}
+
+/// Error answered to a request, with a JSON-RPC error code.
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Answers requests from stdin until it is closed.
+pub fn run() -> Result<()> {
+    let mut subscriptions: HashMap<u64, Arc<AtomicBool>> = HashMap::new();
+    let mut next_subscription = 1;
+
+    for line in io::stdin().lock().lines() {
+        let line = line.context("Failed to read request")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                send(&error_response(
+                    &Value::Null,
+                    &RpcError::new(PARSE_ERROR, e.to_string()),
+                ));
+                continue;
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        debug!(method = %request["method"], "Request");
+
+        let result = match request["method"].as_str() {
+            Some("scan") => path_param(&params, "root").and_then(|root| scan(&root)),
+            Some("status") => path_param(&params, "path").and_then(|path| status(&path)),
+            Some("subscribe") => path_param(&params, "root").map(|root| {
+                let id = next_subscription;
+                next_subscription += 1;
+                let cancelled = Arc::new(AtomicBool::new(false));
+                subscriptions.insert(id, Arc::clone(&cancelled));
+                let interval = params["interval"]
+                    .as_u64()
+                    .map_or(DEFAULT_INTERVAL, Duration::from_secs);
+                thread::spawn(move || subscribe(id, &root, interval, &cancelled));
+                json!(id)
+            }),
+            Some("unsubscribe") => match params["subscription"]
+                .as_u64()
+                .and_then(|id| subscriptions.remove(&id))
+            {
+                Some(cancelled) => {
+                    cancelled.store(true, Ordering::Relaxed);
+                    Ok(json!(true))
+                }
+                None => Err(RpcError::new(INVALID_PARAMS, "Unknown subscription")),
+            },
+            Some(method) => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method {method}"),
+            )),
+            None => Err(RpcError::new(INVALID_REQUEST, "Missing method")),
+        };
+
+        // Requests without id are notifications and get no response
+        if request.get("id").is_none() {
+            continue;
+        }
+        send(&match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => error_response(&id, &e),
+        });
+    }
+
+    for cancelled in subscriptions.values() {
+        cancelled.store(true, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+fn error_response(id: &Value, error: &RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+fn path_param(params: &Value, name: &str) -> Result<PathBuf, RpcError> {
+    params[name]
+        .as_str()
+        .map(PathBuf::from)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Missing parameter {name}")))
+}
+
+fn server_error(e: &anyhow::Error) -> RpcError {
+    RpcError::new(SERVER_ERROR, format!("{e:#}"))
+}
+
+fn scan(root: &Path) -> Result<Value, RpcError> {
+    serve::scan(root).map_err(|e| server_error(&e))
+}
+
+fn status(path: &Path) -> Result<Value, RpcError> {
+    let report =
+        RepoReport::inspect_with(path, &CheckSet::default()).map_err(|e| server_error(&e))?;
+    Ok(output::json(&[report])[0].take())
+}
+
+/// Rescans `root` every `interval` and notifies about changed nasty repos until cancelled.
+fn subscribe(subscription: u64, root: &Path, interval: Duration, cancelled: &AtomicBool) {
+    let scanner = Scanner::new().events(serve::scan_events());
+    let mut previous: Option<Vec<PathBuf>> = None;
+    while !cancelled.load(Ordering::Relaxed) {
+        match serve::find_nasty(root, &scanner) {
+            Ok((_, nasty)) if previous.as_ref() != Some(&nasty) => {
+                send(&json!({
+                    "jsonrpc": "2.0",
+                    "method": "nasty",
+                    "params": {
+                        "subscription": subscription,
+                        "repos": nasty
+                            .iter()
+                            .map(|path| path.to_string_lossy())
+                            .collect::<Vec<_>>(),
+                    },
+                }));
+                previous = Some(nasty);
+            }
+            Ok(_) => {}
+            Err(e) => warn!(subscription, error = format!("{e:#}"), "Failed to scan"),
+        }
+
+        let start = Instant::now();
+        while start.elapsed() < interval && !cancelled.load(Ordering::Relaxed) {
+            thread::sleep(CANCEL_POLL);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Estimating how many repositories are nasty from a random sample, for a quick look at
+//! machines with thousands of clones.
+//!
+//! Each discovered repository is checked with the same probability, decided by a hash
+//! of its path with a key that is random for each run. The estimate extrapolates the
+//! share of nasty repos in the sample to all discovered repos, with a 95% Wilson score
+//! interval corrected for sampling without replacement.
+
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Display};
+use std::hash::BuildHasher;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// z-score of a 95% confidence interval
+const Z: f64 = 1.96;
+
+/// Picks a random share of the repositories it is shown and counts them.
+#[derive(Debug)]
+pub struct Sampler {
+    /// Share of repositories to keep, from 0 to 1
+    fraction: f64,
+    hasher: RandomState,
+    discovered: AtomicUsize,
+    sampled: AtomicUsize,
+}
+
+impl Sampler {
+    /// Keeps about `percent` percent of the repositories.
+    #[must_use]
+    pub fn new(percent: f64) -> Self {
+        Self {
+            fraction: percent / 100.0,
+            hasher: RandomState::new(),
+            discovered: AtomicUsize::new(0),
+            sampled: AtomicUsize::new(0),
+        }
+    }
+
+    /// Whether the repository at `path` is part of the sample.
+    pub fn keep(&self, path: &Path) -> bool {
+        self.discovered.fetch_add(1, Ordering::Relaxed);
+        // The upper 32 bits of the hash, uniformly spread over [0, 1)
+        let position = f64::from((self.hasher.hash_one(path) >> 32) as u32) / 2f64.powi(32);
+        let keep = position < self.fraction;
+        if keep {
+            self.sampled.fetch_add(1, Ordering::Relaxed);
+        }
+        keep
+    }
+
+    /// Estimate of all nasty repositories, given `nasty` of the sampled ones were.
+    #[must_use]
+    pub fn estimate(&self, nasty: usize) -> Estimate {
+        Estimate::new(
+            self.discovered.load(Ordering::Relaxed),
+            self.sampled.load(Ordering::Relaxed),
+            nasty,
+        )
+    }
+}
+
+/// Nasty repositories among all discovered ones, extrapolated from a sample.
+#[derive(Debug, PartialEq)]
+pub struct Estimate {
+    pub discovered: usize,
+    pub sampled: usize,
+    /// Nasty repositories in the sample
+    pub nasty: usize,
+    /// Most likely number of nasty repositories among all discovered ones
+    pub total: usize,
+    /// Bounds of the 95% confidence interval of `total`
+    pub low: usize,
+    pub high: usize,
+}
+
+impl Estimate {
+    /// Extrapolates `nasty` of `sampled` repositories to all `discovered` ones.
+    #[must_use]
+    // Repository counts are far below 2^52, so they convert to f64 and back exactly
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn new(discovered: usize, sampled: usize, nasty: usize) -> Self {
+        if sampled == 0 {
+            return Self {
+                discovered,
+                sampled,
+                nasty,
+                total: 0,
+                low: 0,
+                high: discovered,
+            };
+        }
+        let (population, n) = (discovered as f64, sampled as f64);
+        let share = nasty as f64 / n;
+        let denominator = 1.0 + Z * Z / n;
+        let center = (share + Z * Z / (2.0 * n)) / denominator;
+        let correction = if discovered > 1 {
+            ((population - n) / (population - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        let margin = Z * (share * (1.0 - share) / n + Z * Z / (4.0 * n * n)).sqrt() / denominator
+            * correction;
+        // The sampled repos are known, only the others are estimated
+        let unsampled_max = nasty + (discovered - sampled);
+        let clamp = |value: f64| (value.max(0.0) as usize).clamp(nasty, unsampled_max);
+        let (low, high) = if correction == 0.0 {
+            (nasty, nasty)
+        } else {
+            (
+                clamp((population * (center - margin)).ceil()),
+                clamp((population * (center + margin)).floor()),
+            )
+        };
+        let total = clamp((population * share).round());
+        Self {
+            discovered,
+            sampled,
+            nasty,
+            total,
+            low: low.min(total),
+            high: high.max(total),
+        }
+    }
+}
+
+impl Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Sampled {} of {} repos, {} nasty. Estimated {} nasty repos in total \
+             (95% confidence: {} to {})",
+            self.sampled, self.discovered, self.nasty, self.total, self.low, self.high
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        let estimate = Estimate::new(1000, 100, 10);
+        assert_eq!(estimate.total, 100);
+        assert_eq!((estimate.low, estimate.high), (59, 171));
+
+        // Everything was checked, so nothing is uncertain
+        let estimate = Estimate::new(50, 50, 5);
+        assert_eq!((estimate.total, estimate.low, estimate.high), (5, 5, 5));
+
+        // The nasty repos in the sample are a lower bound
+        let estimate = Estimate::new(10, 9, 9);
+        assert_eq!((estimate.total, estimate.low, estimate.high), (10, 9, 10));
+    }
+
+    #[test]
+    fn test_sampler() {
+        let sampler = Sampler::new(10.0);
+        let kept = (0..10_000)
+            .filter(|i| sampler.keep(Path::new(&format!("/src/{i}"))))
+            .count();
+        assert!((800..1200).contains(&kept), "kept {kept}");
+        assert_eq!(sampler.estimate(0).discovered, 10_000);
+        assert!(Sampler::new(100.0).keep(Path::new("/src/a")));
+    }
+}
@@ -0,0 +1,283 @@
+//! Scanning a directory for nasty repositories from other programs.
+//!
+//! Repositories are checked in parallel on a rayon thread pool that is passed to the
+//! [`Scanner`] or, by default, built for each scan. The global rayon pool is never
+//! configured or used, so programs that set up rayon themselves can embed the scanner.
+//! Repositories are found with the same walker as on the command line, see [`walk`].
+//! Subscribers to an [`EventBus`] passed to [`Scanner::events`] follow the scan as it
+//! goes.
+
+use crate::events::{Event, EventBus};
+use crate::walk::{self, Nested, Unscannable, WalkOptions};
+use crate::{CheckSet, RepoStatus};
+use anyhow::{Context, Result};
+use ignore::gitignore::Gitignore;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// What a [`Scanner`] found.
+#[derive(Debug, Default)]
+pub struct ScanResults {
+    /// Nasty repositories and their status, sorted by path
+    pub nasty: Vec<(PathBuf, RepoStatus)>,
+    /// Repositories that could not be checked, sorted by path
+    pub failed: Vec<(PathBuf, anyhow::Error)>,
+    /// Directories that could not be read and why, sorted by path
+    pub unscannable: Vec<(PathBuf, String)>,
+}
+
+/// Finds repositories below a directory and checks them.
+#[derive(Default)]
+pub struct Scanner<'a> {
+    checks: CheckSet,
+    pool: Option<Arc<ThreadPool>>,
+    threads: Option<usize>,
+    gitignore: Option<Gitignore>,
+    boundaries: HashSet<PathBuf>,
+    nested: Nested,
+    events: EventBus<'a>,
+}
+
+impl<'a> Scanner<'a> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `checks` instead of the [`CheckSet::default`] checks.
+    #[must_use]
+    pub fn checks(mut self, checks: CheckSet) -> Self {
+        self.checks = checks;
+        self
+    }
+
+    /// Checks repositories on `pool`, e.g. one shared with the rest of the program.
+    #[must_use]
+    pub fn thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Builds a pool of `threads` threads for each scan, unless a pool is passed with
+    /// [`Scanner::thread_pool`]. Defaults to one thread per CPU.
+    #[must_use]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Skips paths matching `gitignore`, e.g. the exclude files loaded with
+    /// [`walk::load_gitignore`].
+    #[must_use]
+    pub fn excludes(mut self, gitignore: Gitignore) -> Self {
+        self.gitignore = Some(gitignore);
+        self
+    }
+
+    /// Never walks into `boundaries`, e.g. mount points of other filesystems.
+    /// Boundaries that cannot be resolved are ignored.
+    #[must_use]
+    pub fn boundaries(mut self, boundaries: &[PathBuf]) -> Self {
+        self.boundaries = walk::canonicalize_boundaries(boundaries);
+        self
+    }
+
+    /// How repositories nested inside other repositories are handled, all are checked
+    /// by default.
+    #[must_use]
+    pub fn nested(mut self, nested: Nested) -> Self {
+        self.nested = nested;
+        self
+    }
+
+    /// Emits the events of each scan to `events`: every repository found and checked,
+    /// and the end of the scan.
+    #[must_use]
+    pub fn events(mut self, events: EventBus<'a>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Checks all repositories below `root`.
+    ///
+    /// # Errors
+    /// Returns an error if the thread pool cannot be built.
+    pub fn scan(&self, root: &Path) -> Result<ScanResults> {
+        let pool = match &self.pool {
+            Some(pool) => Arc::clone(pool),
+            None => Arc::new(
+                ThreadPoolBuilder::new()
+                    .num_threads(self.threads.unwrap_or(0))
+                    .build()
+                    .context("Failed to set up thread pool")?,
+            ),
+        };
+        let unscannable = Unscannable::default();
+        let options = WalkOptions {
+            gitignore: self.gitignore.as_ref(),
+            boundaries: self.boundaries.clone(),
+            nested: self.nested,
+            unscannable: Some(&unscannable),
+            ..WalkOptions::default()
+        };
+        let mut repos: Vec<PathBuf> = walk::find_repos(root, options)
+            .inspect(|repo_path| self.events.emit(&Event::RepoDiscovered { path: repo_path }))
+            .collect();
+        repos.sort();
+        let statuses: Vec<_> = pool.install(|| {
+            repos
+                .into_par_iter()
+                .map(|repo_path| {
+                    let status = self.checks.status(&repo_path);
+                    self.events.emit(&Event::RepoChecked {
+                        path: &repo_path,
+                        nasty: status
+                            .as_ref()
+                            .is_ok_and(|status| *status != RepoStatus::Clean),
+                    });
+                    (repo_path, status)
+                })
+                .collect()
+        });
+        let checked = statuses.len();
+        let mut results = ScanResults {
+            unscannable: unscannable.into_paths(),
+            ..ScanResults::default()
+        };
+        for (repo_path, status) in statuses {
+            match status {
+                Ok(RepoStatus::Clean) => {}
+                Ok(status) => results.nasty.push((repo_path, status)),
+                Err(e) => results.failed.push((repo_path, e)),
+            }
+        }
+        self.events.emit(&Event::ScanFinished {
+            checked,
+            nasty: results.nasty.len(),
+            interrupted: false,
+        });
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Repository, Signature};
+    use ignore::gitignore::GitignoreBuilder;
+    use std::sync::Mutex;
+
+    /// A repository at `path` with a commit and no upstream.
+    fn unpushed_repo(path: &Path) {
+        let repo = Repository::init(path).unwrap();
+        let signature = Signature::now("Test User", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "Initial", &tree, &[])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_scan_on_own_pool() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        unpushed_repo(&temp_dir.path().join("unpushed"));
+        Repository::init_bare(temp_dir.path().join("bare.git")).unwrap();
+
+        // As in a host program that set up rayon before
+        let _ = ThreadPoolBuilder::new().num_threads(1).build_global();
+        let pool = Arc::new(ThreadPoolBuilder::new().num_threads(2).build().unwrap());
+        let results = Scanner::new()
+            .thread_pool(pool)
+            .scan(temp_dir.path())
+            .unwrap();
+        assert_eq!(
+            results.nasty,
+            [(temp_dir.path().join("unpushed"), RepoStatus::HasUnpushed)]
+        );
+        assert!(results.failed.is_empty());
+        let results = Scanner::new().threads(1).scan(temp_dir.path()).unwrap();
+        assert_eq!(results.nasty.len(), 1);
+    }
+
+    #[test]
+    fn test_scan_walks_like_the_command_line() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path();
+        unpushed_repo(&root.join("outer"));
+        unpushed_repo(&root.join("outer/inner"));
+        unpushed_repo(&root.join("node_modules/dep"));
+        unpushed_repo(&root.join("mnt/other"));
+        let mut excludes = GitignoreBuilder::new(root);
+        excludes.add_line(None, "node_modules/").unwrap();
+
+        let results = Scanner::new()
+            .threads(1)
+            .excludes(excludes.build().unwrap())
+            .boundaries(&[root.join("mnt")])
+            .nested(Nested::Skip)
+            .scan(root)
+            .unwrap();
+        let nasty: Vec<_> = results.nasty.into_iter().map(|(path, _)| path).collect();
+        assert_eq!(nasty, [root.join("outer")]);
+    }
+
+    #[test]
+    fn test_scan_events() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pushed = temp_dir.path().join("pushed");
+        let unpushed = temp_dir.path().join("unpushed");
+        unpushed_repo(&pushed);
+        unpushed_repo(&unpushed);
+        // Pushed as far as the remote-tracking branch tells
+        let repo = Repository::open(&pushed).unwrap();
+        let head = repo.head().unwrap();
+        let branch = head.shorthand().unwrap();
+        repo.remote("origin", "https://example.com/pushed.git")
+            .unwrap();
+        repo.reference(
+            &format!("refs/remotes/origin/{branch}"),
+            head.target().unwrap(),
+            false,
+            "",
+        )
+        .unwrap();
+        repo.find_branch(branch, git2::BranchType::Local)
+            .unwrap()
+            .set_upstream(Some(&format!("origin/{branch}")))
+            .unwrap();
+
+        let seen = Mutex::new(Vec::new());
+        let mut events = EventBus::default();
+        events.subscribe(|event: &Event<'_>| {
+            seen.lock().unwrap().push(match *event {
+                Event::RepoDiscovered { path } => format!("discovered {}", path.display()),
+                Event::RepoChecked { path, nasty } => {
+                    format!("checked {} {nasty}", path.display())
+                }
+                Event::ScanFinished { checked, nasty, .. } => format!("finished {checked} {nasty}"),
+            });
+        });
+        Scanner::new()
+            .threads(1)
+            .events(events)
+            .scan(temp_dir.path())
+            .unwrap();
+
+        let mut seen = seen.into_inner().unwrap();
+        assert_eq!(seen.pop().unwrap(), "finished 2 1");
+        seen.sort();
+        assert_eq!(
+            seen,
+            [
+                format!("checked {} false", pushed.display()),
+                format!("checked {} true", unpushed.display()),
+                format!("discovered {}", pushed.display()),
+                format!("discovered {}", unpushed.display()),
+            ]
+        );
+    }
+}
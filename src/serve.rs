@@ -0,0 +1,204 @@
+//! HTTP server answering scan requests with JSON, for dashboards and other services.
+//!
+//! Endpoints:
+//! - `GET /scan?root=PATH` scans `PATH` and returns the nasty repos like `--format json`.
+//!   The scan is recorded in the history like any other.
+//! - `GET /last-report[?root=PATH]` returns the last recorded scan, of `PATH` or of any
+//!   directory, without scanning.
+//!
+//! Requests are handled one at a time, each scan uses all threads of a pool of its own.
+
+use crate::{inspect_repos, record_history};
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat};
+use nasty_boii::db::Db;
+use nasty_boii::events::{Event, EventBus};
+use nasty_boii::history::History;
+use nasty_boii::scanner::Scanner;
+use nasty_boii::{output, paths, CheckSet, RepoStatus};
+use rayon::ThreadPoolBuilder;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{debug, info, warn};
+
+/// Serves requests on `addr`, e.g. `127.0.0.1:7878`, until the process is stopped.
+pub fn serve(addr: &str) -> Result<()> {
+    let server = Server::http(addr)
+        .map_err(|e| anyhow::anyhow!(e))
+        .context(format!("Failed to listen on {addr}"))?;
+    // Print the bound address, the port may have been chosen by the OS
+    println!("Listening on http://{}", server.server_addr());
+
+    for request in server.incoming_requests() {
+        info!(method = %request.method(), url = request.url(), "Request");
+        let (status, body) = handle(&request);
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(
+                Header::from_bytes("Content-Type", "application/json")
+                    .expect("static header is valid"),
+            );
+        if let Err(e) = request.respond(response) {
+            warn!(error = %e, "Failed to send response");
+        }
+    }
+    Ok(())
+}
+
+/// Status code and JSON body for `request`.
+fn handle(request: &Request) -> (u16, Value) {
+    if request.method() != &Method::Get {
+        return error(405, "Only GET is supported");
+    }
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    let root = query_param(query, "root");
+    let result = match path {
+        "/scan" => match &root {
+            Some(root) => scan(Path::new(root)).map(Some),
+            None => return error(400, "Missing query parameter root"),
+        },
+        "/last-report" => last_report(root.as_deref()),
+        _ => return error(404, "Not found"),
+    };
+    match result {
+        Ok(Some(body)) => (200, body),
+        Ok(None) => error(404, "No scan recorded yet"),
+        Err(e) => {
+            warn!(error = format!("{e:#}"), "Request failed");
+            error(500, &format!("{e:#}"))
+        }
+    }
+}
+
+fn error(status: u16, message: &str) -> (u16, Value) {
+    (status, json!({ "error": message }))
+}
+
+/// Scans `root` with the default checks and records the scan in the history.
+pub(crate) fn scan(root: &Path) -> Result<Value> {
+    let pool = Arc::new(
+        ThreadPoolBuilder::new()
+            .build()
+            .context("Failed to set up thread pool")?,
+    );
+    let scanner = Scanner::new()
+        .thread_pool(Arc::clone(&pool))
+        .events(scan_events());
+    let (root, selected) = find_nasty(root, &scanner)?;
+    if let Err(e) = record_history(root.display().to_string(), &selected) {
+        warn!(error = %e, "Failed to record scan history");
+    }
+    let checks = CheckSet::default();
+    Ok(output::json(
+        &pool.install(|| inspect_repos(&selected, &checks)),
+    ))
+}
+
+/// Events of a scan run for a client, logged as the scan goes.
+pub(crate) fn scan_events() -> EventBus<'static> {
+    let mut events = EventBus::default();
+    events.subscribe(|event: &Event<'_>| match *event {
+        Event::RepoChecked { path, nasty } => {
+            debug!(repo_path = %path.display(), nasty, "Checked repository");
+        }
+        Event::ScanFinished { checked, nasty, .. } => debug!(checked, nasty, "Scan finished"),
+        _ => {}
+    });
+    events
+}
+
+/// The canonical `root` and the nasty repositories below it found by `scanner`, sorted.
+/// Repositories without HEAD are left out, as on the command line.
+pub(crate) fn find_nasty(root: &Path, scanner: &Scanner) -> Result<(PathBuf, Vec<PathBuf>)> {
+    let root =
+        paths::canonicalize(root).context(format!("Failed to resolve path: {}", root.display()))?;
+    let results = scanner.scan(&root)?;
+    for (repo_path, e) in &results.failed {
+        warn!(repo_path = %repo_path.display(), error = %e, "Failed to check repository");
+    }
+    for (path, reason) in &results.unscannable {
+        warn!(path = %path.display(), error = reason, "Cannot scan path");
+    }
+    let selected = results
+        .nasty
+        .into_iter()
+        .filter(|(_, status)| *status != RepoStatus::MissingHead)
+        .map(|(repo_path, _)| repo_path)
+        .collect();
+    Ok((root, selected))
+}
+
+/// The last recorded scan of `root`, or of any directory.
+fn last_report(root: Option<&str>) -> Result<Option<Value>> {
+    let root = root
+        .map(|root| paths::canonicalize(Path::new(root)).map(|root| root.display().to_string()))
+        .transpose()
+        .context("Failed to resolve path")?;
+    let db = Db::open_default()?;
+    let Some(record) = History::new(&db).latest(root.as_deref())? else {
+        return Ok(None);
+    };
+    Ok(Some(json!({
+        "root": record.root,
+        "time": DateTime::from_timestamp(record.time, 0)
+            .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        "repos": record
+            .repos
+            .iter()
+            .map(|path| path.to_string_lossy())
+            .collect::<Vec<_>>(),
+    })))
+}
+
+/// The percent-decoded value of `name` in a query string like `a=1&root=%2Fsrc`.
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| percent_decode(value))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                if let Some(byte) = hex {
+                    decoded.push(byte);
+                    i += 3;
+                    continue;
+                }
+                decoded.push(b'%');
+            }
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_param() {
+        let query = "format=json&root=%2Fhome%2Fme%2Fmy+src&bad=%zz";
+        assert_eq!(
+            query_param(query, "root").as_deref(),
+            Some("/home/me/my src")
+        );
+        assert_eq!(query_param(query, "bad").as_deref(), Some("%zz"));
+        assert_eq!(query_param(query, "missing"), None);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::locale::HumanFormat;
+use crate::output::{format_findings, format_last_commit, format_list};
+use crate::paths;
+use crate::report::RepoReport;
+use anyhow::{bail, Result};
+
+/// Fields that can be used as `{placeholder}` in a template.
+const FIELDS: &[&str] = &[
+    "path",
+    "status",
+    "branch",
+    "ahead",
+    "dirty",
+    "conflicted",
+    "operation",
+    "shallow",
+    "partial",
+    "sparse",
+    "last_commit",
+    "last_push",
+    "owner",
+    "lfs",
+    "notes",
+    "refs",
+    "findings",
+    "remote",
+    "remote_url",
+    "identity",
+    "push_remote",
+    "push_ahead",
+    "local_only_branches",
+];
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(&'static str),
+}
+
+/// A user-supplied line format such as `{path}\t{branch}\t{ahead}`.
+///
+/// `{{` and `}}` produce literal braces, and `\t`, `\n` and `\\` are unescaped so
+/// templates can be passed in single quotes on the shell.
+#[derive(Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parses a template.
+    ///
+    /// # Errors
+    /// Returns an error for unknown placeholders or unbalanced braces.
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => bail!("Unclosed {{{name} in template"),
+                        }
+                    }
+                    let Some(field) = FIELDS.iter().find(|&&field| field == name) else {
+                        bail!(
+                            "Unknown placeholder {{{name}}}, expected one of: {}",
+                            FIELDS.join(", ")
+                        );
+                    };
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                '}' => bail!("Unmatched }} in template, use }}}} for a literal brace"),
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') | None => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                },
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Renders the template for one repository.
+    #[must_use]
+    pub fn render(&self, report: &RepoReport) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => out.push_str(s),
+                Part::Field(field) => out.push_str(&field_value(field, report)),
+            }
+        }
+        out
+    }
+}
+
+fn field_value(field: &str, report: &RepoReport) -> String {
+    match field {
+        "path" => paths::escape(&report.path).into_owned(),
+        "status" => report.status.to_string(),
+        "branch" => report.branch.clone().unwrap_or_else(|| "-".to_string()),
+        "ahead" => report
+            .ahead
+            .map_or_else(|| "-".to_string(), |n| n.to_string()),
+        "dirty" => yes_no(report.dirty),
+        "conflicted" => report.conflicted.to_string(),
+        "operation" => report.operation.clone().unwrap_or_else(|| "-".to_string()),
+        "shallow" => yes_no(report.shallow),
+        "partial" => yes_no(report.partial),
+        "sparse" => yes_no(report.sparse),
+        "last_commit" => format_last_commit(report.last_commit.as_ref(), &HumanFormat::iso()),
+        "last_push" => report
+            .last_push
+            .map_or_else(|| "-".to_string(), |time| HumanFormat::iso().date(time)),
+        "owner" => report.owner.clone().unwrap_or_else(|| "-".to_string()),
+        "lfs" | "notes" | "refs" => format_list(report.findings(field)),
+        "findings" => format_findings(report),
+        "remote" => report.remote.clone().unwrap_or_else(|| "-".to_string()),
+        "remote_url" => report.remote_url.clone().unwrap_or_else(|| "-".to_string()),
+        "identity" => report.identity.clone().unwrap_or_else(|| "-".to_string()),
+        "push_remote" => report
+            .push_remote
+            .clone()
+            .unwrap_or_else(|| "-".to_string()),
+        "push_ahead" => report
+            .push_ahead
+            .map_or_else(|| "-".to_string(), |n| n.to_string()),
+        "local_only_branches" => format_list(&report.local_only_branches),
+        _ => unreachable!("placeholders are validated when parsing"),
+    }
+}
+
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RepoStatus;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    fn report() -> RepoReport {
+        RepoReport {
+            path: PathBuf::from("/src/repo"),
+            status: RepoStatus::HasUnpushed,
+            branch: Some("main".to_string()),
+            ahead: None,
+            dirty: false,
+            conflicted: 0,
+            operation: None,
+            shallow: false,
+            partial: false,
+            sparse: false,
+            last_commit: None,
+            last_push: None,
+            owner: None,
+            remote: None,
+            remote_url: None,
+            identity: None,
+            push_remote: None,
+            push_ahead: None,
+            local_only_branches: Vec::new(),
+            findings: BTreeMap::new(),
+            unpushed_commits: None,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let template = Template::parse(r"{path}\t{branch}\t{ahead} {{{status}}}").unwrap();
+        assert_eq!(template.render(&report()), "/src/repo\tmain\t- {unpushed}");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Template::parse("{nope}").is_err());
+        assert!(Template::parse("{path").is_err());
+        assert!(Template::parse("path}").is_err());
+    }
+}
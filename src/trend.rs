@@ -0,0 +1,145 @@
+//! Reports of the number of nasty repositories over time, from the scan history.
+
+use crate::history::DayCount;
+use std::fmt::Write;
+
+/// Width of the longest bar in text reports, in characters
+const TEXT_BAR_WIDTH: usize = 40;
+
+/// Size of the chart in HTML reports, in pixels
+const CHART_WIDTH: usize = 720;
+const CHART_HEIGHT: usize = 240;
+/// Space below and left of the bars for the axis labels
+const CHART_MARGIN: usize = 40;
+
+/// One line per day with the count and a bar, e.g. `2024-05-01   3 ###`.
+#[must_use]
+pub fn text(days: &[DayCount]) -> String {
+    let max = max_count(days);
+    let mut out = String::new();
+    for day in days {
+        let bar = "#".repeat(day.nasty * TEXT_BAR_WIDTH / max);
+        let _ = writeln!(out, "{} {:>4} {bar}", day.day, day.nasty);
+    }
+    out
+}
+
+/// Self-contained HTML page with a bar chart and a table of the counts for `root`.
+#[must_use]
+pub fn html(root: &str, days: &[DayCount]) -> String {
+    let root = escape_html(root);
+    let mut out = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>nasty-boii: {root}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+rect {{ fill: #c0392b; }}
+text {{ font-size: 12px; }}
+td, th {{ padding: 0.2em 1em; text-align: right; }}
+</style>
+</head>
+<body>
+<h1>Nasty repos in {root}</h1>
+"#
+    );
+    out.push_str(&chart(days));
+    out.push_str("<table>\n<tr><th>Day</th><th>Nasty repos</th></tr>\n");
+    for day in days.iter().rev() {
+        let _ = writeln!(out, "<tr><td>{}</td><td>{}</td></tr>", day.day, day.nasty);
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
+
+/// SVG bar chart with one bar per day, the oldest on the left.
+fn chart(days: &[DayCount]) -> String {
+    let max = max_count(days);
+    let plot_width = CHART_WIDTH - CHART_MARGIN;
+    let plot_height = CHART_HEIGHT - CHART_MARGIN;
+    let bar_width = (plot_width / days.len().max(1)).max(1);
+
+    let mut out = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{CHART_WIDTH}" height="{CHART_HEIGHT}">
+<text x="0" y="12">{max}</text>
+<text x="0" y="{plot_height}">0</text>
+"#
+    );
+    for (i, day) in days.iter().enumerate() {
+        let height = day.nasty * plot_height / max;
+        let _ = writeln!(
+            out,
+            r#"<rect x="{}" y="{}" width="{}" height="{height}"><title>{}: {}</title></rect>"#,
+            CHART_MARGIN + i * bar_width,
+            plot_height - height,
+            bar_width.saturating_sub(1).max(1),
+            day.day,
+            day.nasty
+        );
+    }
+    if let (Some(first), Some(last)) = (days.first(), days.last()) {
+        let label_y = CHART_HEIGHT - CHART_MARGIN / 2;
+        let _ = writeln!(
+            out,
+            r#"<text x="{CHART_MARGIN}" y="{label_y}">{}</text>"#,
+            first.day
+        );
+        let _ = writeln!(
+            out,
+            r#"<text x="{CHART_WIDTH}" y="{label_y}" text-anchor="end">{}</text>"#,
+            last.day
+        );
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+/// The highest count, at least 1 so bars can be scaled by it.
+fn max_count(days: &[DayCount]) -> usize {
+    days.iter().map(|day| day.nasty).max().unwrap_or(0).max(1)
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn days() -> Vec<DayCount> {
+        vec![
+            DayCount {
+                day: "2024-05-01".to_string(),
+                nasty: 4,
+            },
+            DayCount {
+                day: "2024-05-02".to_string(),
+                nasty: 1,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_text() {
+        let bar = "#".repeat(TEXT_BAR_WIDTH);
+        assert_eq!(
+            text(&days()),
+            format!("2024-05-01    4 {bar}\n2024-05-02    1 ##########\n")
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        let html = html("/src/<a>", &days());
+        assert!(html.contains("<h1>Nasty repos in /src/&lt;a&gt;</h1>"));
+        assert!(html.contains("<title>2024-05-01: 4</title>"));
+        assert!(html.contains(&format!(r#"height="{}""#, CHART_HEIGHT - CHART_MARGIN)));
+        assert!(html.contains("<tr><td>2024-05-02</td><td>1</td></tr>"));
+    }
+}
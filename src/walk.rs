@@ -0,0 +1,535 @@
+//! Walking a directory tree for git repositories.
+//!
+//! The walker is shared by the command line, the server and [`Scanner`]. It prunes
+//! hidden directories, exclude patterns and boundaries before descending, skips bare
+//! repositories and decides about nested repositories as it goes, so that even a walk
+//! of the whole home directory only reads the directories that can hold a result.
+//!
+//! [`Scanner`]: crate::scanner::Scanner
+
+use crate::discovery::{self, Layout};
+use crate::paths;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use ignore::gitignore::{Gitignore, GitignoreBuilder, Glob};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, warn};
+use walkdir::{DirEntry, WalkDir};
+
+/// How to handle repositories nested inside other repositories.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Nested {
+    /// Don't descend into repositories, only report the outermost ones
+    Skip,
+    /// Report all repositories
+    #[default]
+    Include,
+    /// Only report repositories nested inside another repository
+    Only,
+}
+
+/// Patterns of `--default-excludes`: dependencies and caches, not the user's own work
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    "node_modules/",
+    ".cargo/",
+    ".cache/",
+    "**/go/pkg/",
+    "venv/",
+    ".venv/",
+    "virtualenvs/",
+    ".tox/",
+];
+
+/// Load the exclude patterns, if any.
+///
+/// Like in gitignore, the last matching pattern wins, so the global gitignore comes
+/// first, then the default excludes, and each file overrides the ones before it.
+///
+/// # Errors
+/// Returns an error if a file cannot be read or holds an invalid pattern.
+pub fn load_gitignore(
+    exclude_files: &[PathBuf],
+    default_excludes: bool,
+    global_gitignore: bool,
+    base_path: &Path,
+) -> Result<Option<Gitignore>> {
+    if exclude_files.is_empty() && !default_excludes && !global_gitignore {
+        return Ok(None);
+    }
+    let mut builder = GitignoreBuilder::new(base_path);
+    if let Some(global) = global_gitignore.then(global_gitignore_path).flatten() {
+        // Like git, don't mind a global gitignore that doesn't exist
+        if global.exists() {
+            if let Some(err) = builder.add(&global) {
+                return Err(err).context(format!(
+                    "Failed to read global gitignore: {}",
+                    global.display()
+                ));
+            }
+        }
+    }
+    if default_excludes {
+        for pattern in DEFAULT_EXCLUDES {
+            builder
+                .add_line(None, pattern)
+                .context("Failed to add default exclude")?;
+        }
+    }
+    for exclude_file in exclude_files {
+        if let Some(err) = builder.add(exclude_file) {
+            return Err(err).context(format!(
+                "Failed to read exclude file: {}",
+                exclude_file.display()
+            ));
+        }
+    }
+    Ok(Some(
+        builder
+            .build()
+            .context("Failed to build gitignore matcher")?,
+    ))
+}
+
+/// The global gitignore: `core.excludesFile`, or `git/ignore` in `$XDG_CONFIG_HOME` or
+/// `~/.config`, as git looks it up.
+pub fn global_gitignore_path() -> Option<PathBuf> {
+    if let Ok(path) =
+        git2::Config::open_default().and_then(|config| config.get_path("core.excludesFile"))
+    {
+        return Some(path);
+    }
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(config_home.join("git").join("ignore"))
+}
+
+/// Settings for walking the search path.
+#[derive(Default)]
+pub struct WalkOptions<'a> {
+    pub gitignore: Option<&'a Gitignore>,
+    /// Sleep after each directory to reduce IO load
+    pub delay: Option<Duration>,
+    /// Canonical paths of directories the walker never descends into
+    pub boundaries: HashSet<PathBuf>,
+    pub nested: Nested,
+    /// The search path is inside a repository, so all repositories below it are nested
+    pub enclosed: bool,
+    /// Walk into hidden directories other than `.git`
+    pub hidden: bool,
+    /// Collects directories that cannot be read instead of skipping them silently
+    pub unscannable: Option<&'a Unscannable>,
+    /// Collects repositories pruned by an exclude pattern, for `--why-excluded`
+    pub excluded: Option<&'a Excluded>,
+    /// Only repositories matching these patterns are found, for `--include-only`
+    pub include_only: Option<&'a IncludeOnly>,
+    /// Directories directly below the search path that `--resume` doesn't walk again
+    pub completed: Option<&'a HashSet<PathBuf>>,
+    /// Counts what the walker visited and skipped, for `--stats`
+    pub counts: Option<&'a WalkCounts>,
+    /// Stops the walk once set, e.g. by Ctrl-C
+    pub interrupt: Option<&'a AtomicBool>,
+}
+
+impl WalkOptions<'_> {
+    /// Whether `entry` is a directory completed before `--resume`.
+    fn is_completed(&self, entry: &DirEntry) -> bool {
+        let completed = entry.depth() == 1
+            && self
+                .completed
+                .is_some_and(|completed| completed.contains(Path::new(entry.file_name())));
+        if completed {
+            debug!(path = %entry.path().display(), "Skipping directory completed before");
+        }
+        completed
+    }
+
+    /// Whether `entry` matches an exclude pattern.
+    fn is_excluded(&self, entry: &DirEntry) -> bool {
+        let Some(gi) = self.gitignore else {
+            return false;
+        };
+        // Use matched_path_or_any_parents to check if this path or any parent is ignored
+        let is_dir = entry.file_type().is_dir();
+        match gi.matched_path_or_any_parents(entry.path(), is_dir) {
+            ignore::Match::Ignore(glob) => {
+                debug!(
+                    path = %entry.path().display(),
+                    pattern = glob.original(),
+                    "Excluding path based on pattern"
+                );
+                WalkCounts::add(self.counts, |counts| &counts.pruned);
+                if let Some(excluded) = self.excluded {
+                    if is_dir && is_repo(entry.path()) {
+                        excluded.push(entry.path(), glob);
+                    }
+                }
+                true
+            }
+            ignore::Match::None | ignore::Match::Whitelist(_) => false,
+        }
+    }
+
+    /// Whether the directory `entry` below `root` can hold a repository included with
+    /// `--include-only`.
+    fn may_include(&self, entry: &DirEntry, root: &Path) -> bool {
+        let Some(include_only) = self.include_only else {
+            return true;
+        };
+        let may_include = !entry.file_type().is_dir()
+            || entry
+                .path()
+                .strip_prefix(root)
+                .map_or(true, |relative| include_only.may_contain(relative));
+        if !may_include {
+            WalkCounts::add(self.counts, |counts| &counts.pruned);
+        }
+        may_include
+    }
+}
+
+/// Paths the walker could not read, e.g. because of missing permissions.
+#[derive(Default)]
+pub struct Unscannable(Mutex<Vec<(PathBuf, String)>>);
+
+impl Unscannable {
+    fn push(&self, error: &walkdir::Error) {
+        let path = error.path().map(Path::to_path_buf).unwrap_or_default();
+        let reason = error
+            .io_error()
+            .map_or_else(|| error.to_string(), ToString::to_string);
+        debug!(path = %path.display(), error = %reason, "Cannot scan path");
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((path, reason));
+    }
+
+    /// The paths and why they could not be read, sorted.
+    #[must_use]
+    pub fn into_paths(self) -> Vec<(PathBuf, String)> {
+        let mut paths = self.0.into_inner().unwrap_or_else(PoisonError::into_inner);
+        paths.sort();
+        paths
+    }
+}
+
+/// Repositories pruned by an exclude pattern, with the pattern and the file it is from.
+#[derive(Default)]
+pub struct Excluded(Mutex<Vec<(PathBuf, String)>>);
+
+impl Excluded {
+    /// Records that the repository at `path` was excluded by `glob`.
+    pub fn push(&self, path: &Path, glob: &Glob) {
+        let reason = match glob.from() {
+            Some(file) => format!("{} in {}", glob.original(), file.display()),
+            None => format!("{} in --default-excludes", glob.original()),
+        };
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((path.to_path_buf(), reason));
+    }
+
+    /// The repositories and the patterns that excluded them, sorted.
+    #[must_use]
+    pub fn into_repos(self) -> Vec<(PathBuf, String)> {
+        let mut excluded = self.0.into_inner().unwrap_or_else(PoisonError::into_inner);
+        excluded.sort();
+        excluded
+    }
+}
+
+/// Patterns of `--include-only`, the inverse of exclude patterns.
+pub struct IncludeOnly {
+    /// Matches the included paths, i.e. reports them as ignored
+    matcher: Gitignore,
+    /// Directories relative to the search path that hold all matches of a pattern,
+    /// `None` for patterns that can match anywhere
+    prefixes: Vec<Option<PathBuf>>,
+}
+
+impl IncludeOnly {
+    /// Builds the matcher for `patterns` below `base_path`, `None` without patterns.
+    ///
+    /// Patterns starting with `~/` are below the home directory, so `'~/src/**'` works
+    /// when the search path is the home directory or above.
+    ///
+    /// # Errors
+    /// Returns an error if a pattern is invalid or a `~/` pattern is not below
+    /// `base_path`.
+    pub fn new(patterns: &[String], base_path: &Path) -> Result<Option<Self>> {
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+        let canonical_base = paths::canonicalize(base_path).unwrap_or_else(|_| base_path.into());
+        let mut builder = GitignoreBuilder::new(base_path);
+        let mut prefixes = Vec::new();
+        for pattern in patterns {
+            let pattern = match absolute_pattern(pattern) {
+                Some(absolute) => {
+                    let relative = absolute
+                        .strip_prefix(&canonical_base)
+                        .or_else(|_| absolute.strip_prefix(base_path))
+                        .with_context(|| {
+                            format!("--include-only {pattern} is not below the search path")
+                        })?;
+                    format!("/{}", relative.display())
+                }
+                None => pattern.clone(),
+            };
+            builder
+                .add_line(None, &pattern)
+                .context(format!("Invalid --include-only pattern: {pattern}"))?;
+            prefixes.push(literal_prefix(&pattern));
+        }
+        Ok(Some(Self {
+            matcher: builder
+                .build()
+                .context("Failed to build --include-only matcher")?,
+            prefixes,
+        }))
+    }
+
+    /// Whether the directory at `relative` to the search path can hold a match.
+    fn may_contain(&self, relative: &Path) -> bool {
+        self.prefixes.iter().any(|prefix| {
+            prefix
+                .as_ref()
+                .is_none_or(|prefix| prefix.starts_with(relative) || relative.starts_with(prefix))
+        })
+    }
+
+    /// Whether the repository at `path` below the search path is included.
+    #[must_use]
+    pub fn includes(&self, path: &Path) -> bool {
+        self.matcher
+            .matched_path_or_any_parents(path, true)
+            .is_ignore()
+    }
+}
+
+/// `pattern` as an absolute path if it starts with `~/`, `None` for patterns relative
+/// to the search path. A leading `/` anchors a pattern to the search path, as in
+/// exclude files.
+fn absolute_pattern(pattern: &str) -> Option<PathBuf> {
+    Some(dirs::home_dir()?.join(pattern.strip_prefix("~/")?))
+}
+
+/// The directories of a gitignore-style pattern before the first glob, `None` if the
+/// pattern is not anchored and can match at any depth.
+fn literal_prefix(pattern: &str) -> Option<PathBuf> {
+    let pattern = pattern.trim_end_matches('/');
+    if !pattern.contains('/') || pattern.starts_with('!') {
+        return None;
+    }
+    Some(
+        pattern
+            .trim_start_matches('/')
+            .split('/')
+            .take_while(|component| !component.contains(['*', '?', '[', '\\']))
+            .collect(),
+    )
+}
+
+/// Resolve boundary paths so they can be compared against walked directories.
+#[must_use]
+pub fn canonicalize_boundaries(boundaries: &[PathBuf]) -> HashSet<PathBuf> {
+    boundaries
+        .iter()
+        .filter_map(|boundary| match paths::canonicalize(boundary) {
+            Ok(canonical) => Some(canonical),
+            Err(e) => {
+                warn!(
+                    boundary = %boundary.display(),
+                    error = %e,
+                    "Ignoring boundary that cannot be resolved"
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// Whether `path` is the working directory of a repository.
+#[must_use]
+pub fn is_repo(path: &Path) -> bool {
+    matches!(
+        discovery::detect(path),
+        Some(Layout::GitDir | Layout::GitFile)
+    )
+}
+
+/// Walk `path` and yield the working directories of all git repositories found.
+pub fn find_repos<'a>(
+    path: &Path,
+    options: WalkOptions<'a>,
+) -> impl Iterator<Item = PathBuf> + Send + 'a {
+    // Walked paths are not canonical, so resolve them relative to the canonical root
+    // when checking boundaries. Symlinks are not followed, so this is enough.
+    let root = path.to_path_buf();
+    let canonical_root = paths::canonicalize(path).unwrap_or_else(|_| root.clone());
+
+    let nested = options.nested;
+    let enclosed = options.enclosed;
+    let unscannable = options.unscannable;
+    let include_only = options.include_only;
+    let counts = options.counts;
+    let interrupt = options.interrupt;
+    let mut walker = WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(move |e| {
+            if let Some(delay) = options.delay {
+                if e.file_type().is_dir() {
+                    thread::sleep(delay);
+                }
+            }
+
+            // Always allow the root directory (depth 0)
+            if e.depth() == 0 {
+                return true;
+            }
+
+            // Junctions and other reparse points can form cycles on Windows
+            if e.file_type().is_dir() && is_reparse_point(e) {
+                debug!(path = %e.path().display(), "Skipping reparse point");
+                return false;
+            }
+
+            if options.is_completed(e) {
+                return false;
+            }
+
+            // Never cross boundaries
+            if !options.boundaries.is_empty() && e.file_type().is_dir() {
+                if let Ok(relative) = e.path().strip_prefix(&root) {
+                    if options.boundaries.contains(&canonical_root.join(relative)) {
+                        debug!(path = %e.path().display(), "Stopping at boundary");
+                        return false;
+                    }
+                }
+            }
+
+            if options.is_excluded(e) || !options.may_include(e, &root) {
+                return false;
+            }
+
+            // Skip hidden directories, repositories are detected from their working directory
+            if options.hidden {
+                e.file_name() != ".git"
+            } else {
+                !e.file_name().to_string_lossy().starts_with('.')
+            }
+        });
+
+    // Depths of the repositories enclosing the current entry
+    let mut repo_depths: Vec<usize> = Vec::new();
+    std::iter::from_fn(move || {
+        while let Some(entry) = walker.next() {
+            if interrupt.is_some_and(|interrupt| interrupt.load(Ordering::Relaxed)) {
+                return None;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    WalkCounts::add(counts, |counts| &counts.unreadable);
+                    if let Some(unscannable) = unscannable {
+                        unscannable.push(&e);
+                    }
+                    continue;
+                }
+            };
+            if entry.path_is_symlink() && entry.path().is_dir() {
+                // Symlinks are not followed, so the repositories behind them aren't found
+                debug!(path = %entry.path().display(), "Skipping symlink to directory");
+                WalkCounts::add(counts, |counts| &counts.symlinks);
+            }
+            if !entry.file_type().is_dir() {
+                continue;
+            }
+            WalkCounts::add(counts, |counts| &counts.directories);
+
+            while repo_depths.last().is_some_and(|&d| d >= entry.depth()) {
+                repo_depths.pop();
+            }
+            match discovery::detect(entry.path()) {
+                None => continue,
+                Some(Layout::Bare) => {
+                    // Nothing in a repository without working directory can be unpushed
+                    debug!(repo_path = %entry.path().display(), "Skipping bare repository");
+                    walker.skip_current_dir();
+                    continue;
+                }
+                Some(Layout::GitDir | Layout::GitFile) => {}
+            }
+            let is_nested = enclosed || !repo_depths.is_empty();
+            repo_depths.push(entry.depth());
+            // Repositories that aren't included are walked, nested ones may be
+            if include_only.is_some_and(|include_only| !include_only.includes(entry.path())) {
+                continue;
+            }
+
+            match (nested, is_nested) {
+                (Nested::Skip, true) => {
+                    debug!(repo_path = %entry.path().display(), "Skipping nested repository");
+                    walker.skip_current_dir();
+                }
+                (Nested::Include, _) | (Nested::Skip, false) | (Nested::Only, true) => {
+                    return Some(entry.into_path());
+                }
+                (Nested::Only, false) => {}
+            }
+        }
+        None
+    })
+}
+
+/// Whether a walked directory is a junction, mount point or other reparse point.
+///
+/// Symlinks are already not followed, but other reparse points are reported as plain
+/// directories and can lead back to an ancestor.
+#[cfg(windows)]
+fn is_reparse_point(entry: &DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+
+    // walkdir caches the metadata of entries on Windows, so this doesn't hit the disk
+    entry
+        .metadata()
+        .is_ok_and(|metadata| metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT != 0)
+}
+
+#[cfg(not(windows))]
+fn is_reparse_point(_entry: &DirEntry) -> bool {
+    false
+}
+
+/// What the walker visited and skipped, for `--stats` and `--count --all`, e.g. to
+/// verify that exclude patterns prune what they should.
+#[derive(Default)]
+pub struct WalkCounts {
+    /// Directories walked into
+    pub directories: AtomicUsize,
+    /// Paths pruned by exclude or `--include-only` patterns
+    pub pruned: AtomicUsize,
+    /// Directories that could not be read, e.g. because of missing permissions
+    pub unreadable: AtomicUsize,
+    /// Symlinks to directories, which are not followed
+    pub symlinks: AtomicUsize,
+}
+
+impl WalkCounts {
+    /// Increments the `counter` of `counts`, if counting.
+    fn add(counts: Option<&Self>, counter: impl FnOnce(&Self) -> &AtomicUsize) {
+        if let Some(counts) = counts {
+            counter(counts).fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -2,13 +2,25 @@ mod fixtures;
 
 use assert_cmd::cargo::cargo_bin_cmd;
 use fixtures::TestRepos;
+use nasty_boii::db::Db;
+use nasty_boii::paths::canonicalize;
+use nasty_boii::resume::Checkpoints;
 use predicates::prelude::*;
+use std::path::Path;
+
+/// The binary with its data directory in `data_dir`, so that scans don't record history
+/// or checkpoints in the real one.
+fn nasty_boii(data_dir: &Path) -> assert_cmd::Command {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.env("NASTY_BOII_DATA_DIR", data_dir);
+    cmd
+}
 
 #[test]
 fn test_finds_nasty_repo() {
     let repos = TestRepos::new();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg(repos.path())
         .assert()
         .success()
@@ -19,7 +31,7 @@ fn test_finds_nasty_repo() {
 fn test_does_not_list_clean_repo() {
     let repos = TestRepos::new();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg(repos.path())
         .assert()
         .success()
@@ -30,7 +42,7 @@ fn test_does_not_list_clean_repo() {
 fn test_finds_no_upstream_repo() {
     let repos = TestRepos::new();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg(repos.path())
         .assert()
         .success()
@@ -45,7 +57,7 @@ fn test_missing_head_flag() {
     // This test verifies that the flag doesn't crash and runs successfully.
     let repos = TestRepos::new();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg("--missing-head")
         .arg(repos.path())
         .assert()
@@ -59,7 +71,7 @@ fn test_missing_head_flag() {
 fn test_missing_head_flag_does_not_show_nasty_repo() {
     let repos = TestRepos::new();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg("--missing-head")
         .arg(repos.path())
         .assert()
@@ -73,7 +85,7 @@ fn test_default_mode_does_not_show_missing_head() {
 
     // In default mode, missing-head repos should not appear in stdout
     // (they appear in warnings instead)
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg(repos.path())
         .assert()
         .success()
@@ -85,7 +97,7 @@ fn test_behind_repo_not_listed() {
     let repos = TestRepos::new();
 
     // Repos that are behind (but not ahead) should not be listed
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg(repos.path())
         .assert()
         .success()
@@ -96,7 +108,7 @@ fn test_behind_repo_not_listed() {
 fn test_verbose_flag() {
     let repos = TestRepos::new();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg("--verbose")
         .arg(repos.path())
         .assert()
@@ -108,7 +120,7 @@ fn test_verbose_flag() {
 fn test_log_level_debug() {
     let repos = TestRepos::new();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg("--log-level")
         .arg("debug")
         .arg(repos.path())
@@ -123,7 +135,7 @@ fn test_current_directory_default() {
     // We run it in the fixtures directory
     let repos = TestRepos::new();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .current_dir(repos.path())
         .assert()
         .success();
@@ -133,7 +145,7 @@ fn test_current_directory_default() {
 fn test_threads_flag() {
     let repos = TestRepos::new();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg("--threads")
         .arg("2")
         .arg("--verbose")
@@ -145,17 +157,20 @@ fn test_threads_flag() {
 
 #[test]
 fn test_nonexistent_directory() {
-    cargo_bin_cmd!()
+    let data_dir = tempfile::tempdir().unwrap();
+
+    nasty_boii(data_dir.path())
         .arg("/nonexistent/path/that/does/not/exist")
         .assert()
-        .success(); // WalkDir just returns no results for nonexistent paths
+        .success(); // Reported as unscannable, which only fails with --strict-walk
 }
 
 #[test]
 fn test_empty_directory() {
+    let data_dir = tempfile::tempdir().unwrap();
     let temp_dir = tempfile::tempdir().unwrap();
 
-    cargo_bin_cmd!()
+    nasty_boii(data_dir.path())
         .arg(temp_dir.path())
         .assert()
         .success()
@@ -170,7 +185,7 @@ fn test_exclude_from_file() {
     let exclude_file = repos.path().join(".exclude");
     std::fs::write(&exclude_file, "nasty-repo/\n").unwrap();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg("--exclude-from")
         .arg(&exclude_file)
         .arg(repos.path())
@@ -180,6 +195,65 @@ fn test_exclude_from_file() {
         .stdout(predicate::str::contains("no-upstream-repo"));
 }
 
+#[test]
+fn test_include_only() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--include-only", "nasty-*"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(format!("{}\n", repos.nasty_repo.display()));
+
+    // clean-repo isn't reported, but the walker descends into it
+    let nested = repos.add_nested_repo();
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--include-only", "/clean-repo/vendor/*"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(format!("{}\n", nested.display()));
+}
+
+#[test]
+fn test_hidden() {
+    let repos = TestRepos::new();
+    let hidden = repos.path().join(".dotfiles");
+    std::fs::rename(repos.add_nested_repo(), &hidden).unwrap();
+
+    nasty_boii(repos.data_dir())
+        .arg("--no-history")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(".dotfiles").not());
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--hidden"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("{}\n", hidden.display())));
+}
+
+#[test]
+fn test_global_gitignore() {
+    let repos = TestRepos::new();
+    let home = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(home.path().join(".config/git")).unwrap();
+    std::fs::write(home.path().join(".config/git/ignore"), "nasty-repo/\n").unwrap();
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--global-gitignore"])
+        .arg(repos.path())
+        .env("HOME", home.path())
+        .env_remove("XDG_CONFIG_HOME")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nasty-repo").not())
+        .stdout(predicate::str::contains("no-upstream-repo"));
+}
+
 #[test]
 fn test_exclude_from_wildcard() {
     let repos = TestRepos::new();
@@ -188,7 +262,7 @@ fn test_exclude_from_wildcard() {
     let exclude_file = repos.path().join(".exclude");
     std::fs::write(&exclude_file, "*-repo/\n").unwrap();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg("--exclude-from")
         .arg(&exclude_file)
         .arg(repos.path())
@@ -209,7 +283,7 @@ fn test_exclude_from_with_comments() {
     )
     .unwrap();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg("--exclude-from")
         .arg(&exclude_file)
         .arg(repos.path())
@@ -228,7 +302,7 @@ fn test_exclude_from_negation() {
     let exclude_file = repos.path().join(".exclude");
     std::fs::write(&exclude_file, "*-repo/\n!nasty-repo/\n").unwrap();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg("--exclude-from")
         .arg(&exclude_file)
         .arg(repos.path())
@@ -239,14 +313,2793 @@ fn test_exclude_from_negation() {
         .stdout(predicate::str::contains("no-upstream-repo").not());
 }
 
+#[test]
+fn test_default_excludes_and_multiple_exclude_files() {
+    let repos = TestRepos::new();
+    let dependency = repos.path().join("node_modules").join("dependency");
+    std::fs::create_dir_all(&dependency).unwrap();
+    for args in [
+        &["init"][..],
+        &[
+            "-c",
+            "user.name=Test User",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "--allow-empty",
+            "-m",
+            "Initial commit",
+        ],
+    ] {
+        std::process::Command::new("git")
+            .args(args)
+            .current_dir(&dependency)
+            .output()
+            .unwrap();
+    }
+    let exclude_nasty = repos.path().join("exclude-nasty");
+    std::fs::write(&exclude_nasty, "nasty-repo/\n").unwrap();
+    let include_dependencies = repos.path().join("include-dependencies");
+    std::fs::write(&include_dependencies, "!node_modules/\n").unwrap();
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--default-excludes"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("dependency").not())
+        .stdout(predicate::str::contains("nasty-repo"));
+
+    // Later files override earlier ones and the defaults
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--default-excludes", "--exclude-from"])
+        .arg(&exclude_nasty)
+        .arg("--exclude-from")
+        .arg(&include_dependencies)
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("dependency"))
+        .stdout(predicate::str::contains("nasty-repo").not());
+}
+
+#[test]
+fn test_why_excluded() {
+    let repos = TestRepos::new();
+    let exclude_file = repos.path().join(".exclude");
+    std::fs::write(&exclude_file, "# Not for backup\nnasty-*/\n").unwrap();
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--why-excluded", "--exclude-from"])
+        .arg(&exclude_file)
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("1 excluded repo(s):"))
+        .stderr(predicate::str::contains(format!(
+            "  {}: nasty-*/ in {}",
+            repos.nasty_repo.display(),
+            exclude_file.display()
+        )));
+}
+
 #[test]
 fn test_exclude_from_nonexistent_file() {
     let repos = TestRepos::new();
 
-    cargo_bin_cmd!()
+    nasty_boii(repos.data_dir())
         .arg("--exclude-from")
         .arg("/nonexistent/exclude/file")
         .arg(repos.path())
         .assert()
         .failure();
 }
+
+#[test]
+fn test_low_memory_output_matches() {
+    let repos = TestRepos::new();
+    let output = |args: &[&str]| {
+        nasty_boii(repos.data_dir())
+            .arg("--no-history")
+            .args(args)
+            .arg(repos.path())
+            .output()
+            .unwrap()
+            .stdout
+    };
+
+    for format in ["json", "csv"] {
+        assert_eq!(
+            output(&["--format", format, "--low-memory"]),
+            output(&["--format", format])
+        );
+    }
+    assert_eq!(
+        output(&["--template", "{path} {branch}", "--low-memory"]),
+        output(&["--template", "{path} {branch}"])
+    );
+
+    nasty_boii(repos.data_dir())
+        .args(["--format", "sarif", "--low-memory"])
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--low-memory only works with plain, JSON and CSV output",
+        ));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_non_utf8_paths() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let repos = TestRepos::new();
+    let name = std::ffi::OsStr::from_bytes(b"no-upstream-\xff");
+    std::fs::rename(&repos.no_upstream_repo, repos.path().join(name)).unwrap();
+    let raw = [repos.path().as_os_str().as_bytes(), b"/no-upstream-\xff"].concat();
+    let contains = |haystack: &[u8], needle: &[u8]| {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    };
+
+    let plain = nasty_boii(repos.data_dir())
+        .arg("--no-history")
+        .arg(repos.path())
+        .output()
+        .unwrap();
+    assert!(contains(&plain.stdout, &[&raw[..], b"\n"].concat()));
+
+    let print0 = nasty_boii(repos.data_dir())
+        .args(["--no-history", "--print0"])
+        .arg(repos.path())
+        .output()
+        .unwrap();
+    assert!(contains(&print0.stdout, &[&raw[..], b"\0"].concat()));
+    assert!(!print0.stdout.contains(&b'\n'));
+
+    // NUL-terminated paths read back losslessly
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--print0", "--paths-from", "-"])
+        .write_stdin(print0.stdout.clone())
+        .assert()
+        .success()
+        .stdout(print0.stdout.clone());
+
+    let json = nasty_boii(repos.data_dir())
+        .args(["--no-history", "--format", "json"])
+        .arg(repos.path())
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json.stdout).unwrap();
+    let repo = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|repo| repo.get("path_bytes").is_some())
+        .unwrap();
+    assert!(repo["path"]
+        .as_str()
+        .unwrap()
+        .ends_with(r"/no-upstream-\xFF"));
+    assert_eq!(repo["path_bytes"], serde_json::json!(raw));
+}
+
+#[test]
+fn test_format_markdown() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .arg("--format")
+        .arg("markdown")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "| Path | Branch | Ahead | Dirty | Last commit |",
+        ))
+        .stdout(predicate::str::contains("nasty-repo | main | 1 | no |"))
+        .stdout(predicate::str::contains(
+            "no-upstream-repo | main | no upstream |",
+        ))
+        .stdout(predicate::str::contains("clean-repo").not());
+}
+
+#[test]
+fn test_format_sarif() {
+    let repos = TestRepos::new();
+
+    let output = nasty_boii(repos.data_dir())
+        .arg("--format")
+        .arg("sarif")
+        .arg(repos.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let sarif: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let results = sarif["runs"][0]["results"].as_array().unwrap();
+    let rule_ids: Vec<&str> = results
+        .iter()
+        .map(|r| r["ruleId"].as_str().unwrap())
+        .collect();
+    assert_eq!(results.len(), 2);
+    assert!(rule_ids.contains(&"unpushed"));
+    assert!(rule_ids.contains(&"no-upstream"));
+}
+
+#[test]
+fn test_format_sarif_transfer_in_progress() {
+    let repos = TestRepos::new();
+    let lfs_tmp = repos.clean_repo.join(".git").join("lfs").join("tmp");
+    std::fs::create_dir_all(&lfs_tmp).unwrap();
+    std::fs::write(lfs_tmp.join("partial"), "").unwrap();
+
+    let output = nasty_boii(repos.data_dir())
+        .args(["--format", "sarif"])
+        .arg(&repos.clean_repo)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // Every result must refer to a declared rule, or strict consumers reject the log
+    let sarif: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let run = &sarif["runs"][0];
+    assert_eq!(run["results"][0]["ruleId"], "transfer-in-progress");
+    assert!(run["tool"]["driver"]["rules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|rule| rule["id"] == "transfer-in-progress"));
+}
+
+#[test]
+fn test_format_json_includes_remote() {
+    let repos = TestRepos::new();
+
+    let output = nasty_boii(repos.data_dir())
+        .args(["--format", "json"])
+        .arg(repos.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let reports: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let reports = reports.as_array().unwrap();
+    assert_eq!(reports.len(), 2);
+    let nasty = reports
+        .iter()
+        .find(|r| r["path"].as_str().unwrap().ends_with("nasty-repo"))
+        .unwrap();
+    assert_eq!(nasty["remote"], "origin");
+    assert!(nasty["remote_url"]
+        .as_str()
+        .unwrap()
+        .ends_with("nasty-repo-remote.git"));
+    assert_eq!(nasty["local_only_branches"], serde_json::json!([]));
+
+    let no_upstream = reports
+        .iter()
+        .find(|r| r["path"].as_str().unwrap().ends_with("no-upstream-repo"))
+        .unwrap();
+    assert_eq!(
+        no_upstream["local_only_branches"],
+        serde_json::json!(["main"])
+    );
+}
+
+#[test]
+fn test_search_path_in_repo() {
+    let repos = TestRepos::new();
+    let src = repos.nasty_repo.join("src");
+    std::fs::create_dir(&src).unwrap();
+    let nasty_repo = canonicalize(&repos.nasty_repo).unwrap();
+
+    nasty_boii(repos.data_dir())
+        .arg("--no-history")
+        .arg(&src)
+        .assert()
+        .success()
+        .stdout(format!("{}\n", nasty_repo.display()));
+
+    // vendor is inside clean-repo, so inner is nested
+    let nested = repos.add_nested_repo();
+    let vendor = repos.clean_repo.join("vendor");
+    nasty_boii(repos.data_dir())
+        .arg("--no-history")
+        .arg(&vendor)
+        .assert()
+        .success()
+        .stdout(format!("{}\n", nested.display()));
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--nested", "skip"])
+        .arg(&vendor)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--stop-at-repo"])
+        .arg(&vendor)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--stop-at-repo"])
+        .arg(&repos.nasty_repo)
+        .assert()
+        .success()
+        .stdout(format!("{}\n", repos.nasty_repo.display()));
+}
+
+#[test]
+fn test_dedupe_by_identity() {
+    let repos = TestRepos::new();
+    let nested = repos.add_nested_repo();
+    for (repo, url) in [
+        (&repos.nasty_repo, "git@github.com:acme/app.git"),
+        (&nested, "https://github.com/acme/app"),
+    ] {
+        std::process::Command::new("git")
+            .args(["remote", "set-url", "origin", url])
+            .current_dir(repo)
+            .output()
+            .unwrap();
+    }
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--dedupe-by", "identity"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(format!(
+            "github.com/acme/app\n  {}\n  {}\nWithout a remote\n  {}\n",
+            nested.display(),
+            repos.nasty_repo.display(),
+            repos.no_upstream_repo.display()
+        ));
+
+    let output = nasty_boii(repos.data_dir())
+        .args(["--no-history", "--format", "json"])
+        .arg(repos.path())
+        .output()
+        .unwrap();
+    let reports: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(reports[0]["identity"], "github.com/acme/app");
+}
+
+#[test]
+fn test_group_by() {
+    let repos = TestRepos::new();
+    let nested = repos.add_nested_repo();
+    for (repo, url) in [
+        (&repos.nasty_repo, "git@github.com:acme/nasty.git"),
+        (&nested, "https://github.com/other/inner.git"),
+    ] {
+        std::process::Command::new("git")
+            .args(["remote", "set-url", "origin", url])
+            .current_dir(repo)
+            .output()
+            .unwrap();
+    }
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--group-by", "remote-host"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout("2 nasty repos under github.com\n1 nasty repo without a hosted remote\n");
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--group-by", "org"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "1 nasty repo under github.com/acme\n",
+        ))
+        .stdout(predicate::str::contains(
+            "1 nasty repo under github.com/other\n",
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_group_by_owner() {
+    let repos = TestRepos::new();
+    let user = std::process::Command::new("id")
+        .arg("-un")
+        .output()
+        .unwrap();
+    let user = String::from_utf8(user.stdout).unwrap();
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--group-by", "owner"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(format!("2 nasty repos owned by {}\n", user.trim()));
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--template", "{owner}"])
+        .arg(&repos.nasty_repo)
+        .assert()
+        .success()
+        .stdout(format!("{}\n", user.trim()));
+}
+
+#[test]
+fn test_show_counts() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--show-counts"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"nasty-repo \+[1-9]\d* -0\n").unwrap())
+        .stdout(predicate::str::contains("no-upstream-repo (no upstream)\n"));
+}
+
+#[test]
+fn test_schema() {
+    let output = cargo_bin_cmd!().arg("schema").output().unwrap();
+    assert!(output.status.success());
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(schema["type"], "array");
+    assert_eq!(
+        schema["items"]["properties"]["schema_version"]["const"],
+        nasty_boii::output::JSON_SCHEMA_VERSION
+    );
+}
+
+#[test]
+fn test_man() {
+    cargo_bin_cmd!()
+        .arg("man")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(".ie"))
+        .stdout(predicate::str::contains(r"\-\-verify\-on\-host"));
+}
+
+#[test]
+fn test_format_csv() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .args(["--format", "csv"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "path,status,branch,ahead,dirty,conflicted,shallow,partial,sparse,last_commit,remote,remote_url,findings\n",
+        ))
+        .stdout(
+            predicate::str::is_match(r"no-upstream-repo,unpushed,main,,false,0,false,false,false,[^,]*,,,ahead: ")
+                .unwrap(),
+        );
+}
+
+#[test]
+fn test_format_sarif_empty_directory() {
+    let data_dir = tempfile::tempdir().unwrap();
+    let temp_dir = tempfile::tempdir().unwrap();
+
+    nasty_boii(data_dir.path())
+        .arg("--format")
+        .arg("sarif")
+        .arg(temp_dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"results\": []"));
+}
+
+#[test]
+fn test_paths_from_stdin() {
+    let repos = TestRepos::new();
+
+    let input = format!(
+        "{}\n\n{}\n",
+        repos.clean_repo.display(),
+        repos.nasty_repo.join(".git").display()
+    );
+
+    nasty_boii(repos.data_dir())
+        .arg("--paths-from")
+        .arg("-")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(format!("{}\n", repos.nasty_repo.display()));
+}
+
+#[test]
+fn test_paths_from_file() {
+    let repos = TestRepos::new();
+
+    let paths_file = repos.path().join("paths.txt");
+    std::fs::write(
+        &paths_file,
+        format!("{}\n", repos.no_upstream_repo.display()),
+    )
+    .unwrap();
+
+    nasty_boii(repos.data_dir())
+        .arg("--paths-from")
+        .arg(&paths_file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("no-upstream-repo"))
+        .stdout(predicate::str::contains("nasty-repo").not());
+}
+
+#[test]
+fn test_register_list_unregister() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+    let nasty_repo = canonicalize(&repos.nasty_repo).unwrap();
+
+    nasty_boii(data_dir.path())
+        .arg("register")
+        .arg(&repos.nasty_repo)
+        .assert()
+        .success();
+
+    nasty_boii(data_dir.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(format!("{}\n", nasty_repo.display()));
+
+    nasty_boii(data_dir.path())
+        .arg("unregister")
+        .arg(&repos.nasty_repo)
+        .assert()
+        .success();
+
+    nasty_boii(data_dir.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+}
+
+#[test]
+fn test_label() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+    let nasty_repo = canonicalize(&repos.nasty_repo).unwrap();
+
+    nasty_boii(data_dir.path())
+        .args(["label", repos.nasty_repo.to_str().unwrap(), "work"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Not registered"));
+
+    for repo in [&repos.nasty_repo, &repos.behind_repo] {
+        nasty_boii(data_dir.path())
+            .arg("register")
+            .arg(repo)
+            .assert()
+            .success();
+    }
+    nasty_boii(data_dir.path())
+        .args([
+            "label",
+            repos.nasty_repo.to_str().unwrap(),
+            "work",
+            "critical",
+        ])
+        .assert()
+        .success();
+
+    nasty_boii(data_dir.path())
+        .args(["list", "--label", "work"])
+        .assert()
+        .success()
+        .stdout(format!("{}\tcritical,work\n", nasty_repo.display()));
+
+    nasty_boii(data_dir.path())
+        .args(["--no-history", "--label", "work"])
+        .arg(repos.temp_dir.path())
+        .assert()
+        .success()
+        .stdout(format!("{}\n", repos.nasty_repo.display()));
+
+    nasty_boii(data_dir.path())
+        .args([
+            "label",
+            "--remove",
+            repos.nasty_repo.to_str().unwrap(),
+            "work",
+        ])
+        .assert()
+        .success();
+
+    nasty_boii(data_dir.path())
+        .args(["--no-history", "--registered", "--label", "work"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+}
+
+#[test]
+fn test_register_non_repo_fails() {
+    let data_dir = tempfile::tempdir().unwrap();
+
+    nasty_boii(data_dir.path())
+        .arg("register")
+        .arg(data_dir.path())
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_registered_only_checks_registered_repos() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+
+    for repo in [&repos.clean_repo, &repos.no_upstream_repo] {
+        nasty_boii(data_dir.path())
+            .arg("register")
+            .arg(repo)
+            .assert()
+            .success();
+    }
+
+    nasty_boii(data_dir.path())
+        .arg("--registered")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("no-upstream-repo"))
+        .stdout(predicate::str::contains("nasty-repo").not());
+}
+
+#[test]
+fn test_install_hook_shutdown() {
+    let data_dir = tempfile::tempdir().unwrap();
+    let profile = data_dir.path().join(".bashrc");
+
+    nasty_boii(data_dir.path())
+        .args(["install-hook", "shutdown", "--profile"])
+        .arg(&profile)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Installed shutdown hook"));
+
+    let hook = data_dir.path().join("hooks").join("shutdown.sh");
+    assert!(std::fs::read_to_string(&profile)
+        .unwrap()
+        .contains(&hook.display().to_string()));
+    assert!(std::fs::read_to_string(&hook)
+        .unwrap()
+        .contains("poweroff()"));
+}
+
+#[test]
+fn test_audit_fails_with_breakdown() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .arg("audit")
+        .arg(repos.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "unpushed: Branch main is 1 commit(s) ahead of its upstream",
+        ))
+        .stdout(predicate::str::contains(
+            "unpushed: Branch main has no upstream",
+        ))
+        .stdout(predicate::str::contains("Verdict: FAIL"));
+}
+
+#[test]
+fn test_audit_policy() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+    let policy = repos.path().join("policy.toml");
+    let audit = || {
+        let mut cmd = nasty_boii(data_dir.path());
+        cmd.arg("audit")
+            .arg("--policy")
+            .arg(&policy)
+            .arg(&repos.nasty_repo);
+        cmd
+    };
+
+    // The unpushed commit is younger than the grace period
+    std::fs::write(
+        &policy,
+        "default = \"allow\"\n[[rule]]\ndeny = [\"unpushed\"]\ngrace = \"24h\"\n",
+    )
+    .unwrap();
+    audit()
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Verdict: PASS"));
+
+    std::fs::write(
+        &policy,
+        "default = \"allow\"\n[[rule]]\nname = \"push now\"\ndeny = [\"unpushed\"]\n",
+    )
+    .unwrap();
+    audit().assert().code(1).stdout(predicate::str::contains(
+        "unpushed: Branch main is 1 commit(s) ahead of its upstream (violates push now)",
+    ));
+
+    std::fs::write(&policy, "[[rule]]\nallow = [\"unpushd\"]\n").unwrap();
+    audit()
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown finding kind \"unpushd\""));
+}
+
+#[test]
+fn test_clones() {
+    let repos = TestRepos::new();
+    let remote = repos.path().join("nasty-repo-remote.git");
+    let copy = repos.path().join("nasty-copy");
+    std::process::Command::new("git")
+        .arg("clone")
+        .arg(&remote)
+        .arg(&copy)
+        .output()
+        .unwrap();
+
+    nasty_boii(repos.data_dir())
+        .arg("clones")
+        .arg(repos.path())
+        .assert()
+        .code(1)
+        .stdout(format!(
+            "{}\n  {}: nothing the others lack\n  {}: main\n\
+             Found 2 projects with several clones, 1 diverged\n",
+            remote.with_extension("").display(),
+            copy.display(),
+            repos.nasty_repo.display()
+        ));
+
+    // Once the copy has the unpushed commit too, nothing is lost by removing either
+    std::process::Command::new("git")
+        .args(["pull", "--quiet"])
+        .arg(&repos.nasty_repo)
+        .arg("main")
+        .current_dir(&copy)
+        .output()
+        .unwrap();
+    nasty_boii(repos.data_dir())
+        .arg("clones")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout("Found 2 projects with several clones, 0 diverged\n");
+}
+
+#[test]
+fn test_audit_passes_on_clean_repo() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .arg("audit")
+        .arg(&repos.clean_repo)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Audited 1 repositories, 0 at risk",
+        ))
+        .stdout(predicate::str::contains("Verdict: PASS"));
+}
+
+#[test]
+fn test_nice_flag() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .arg("--nice")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nasty-repo"))
+        .stdout(predicate::str::contains("Failed to lower process priority").not());
+}
+
+#[test]
+fn test_throttle_on_battery_flag() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .arg("--throttle-on-battery")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nasty-repo"));
+}
+
+#[test]
+fn test_boundary_stops_walker() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .arg("--boundary")
+        .arg(&repos.nasty_repo)
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nasty-repo").not())
+        .stdout(predicate::str::contains("no-upstream-repo"));
+}
+
+#[test]
+fn test_nested_include_by_default() {
+    let repos = TestRepos::new();
+    repos.add_nested_repo();
+
+    nasty_boii(repos.data_dir())
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("inner"))
+        .stdout(predicate::str::contains("nasty-repo"));
+}
+
+#[test]
+fn test_nested_skip() {
+    let repos = TestRepos::new();
+    repos.add_nested_repo();
+
+    nasty_boii(repos.data_dir())
+        .args(["--nested", "skip"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("inner").not())
+        .stdout(predicate::str::contains("nasty-repo"));
+}
+
+#[test]
+fn test_nested_only() {
+    let repos = TestRepos::new();
+    repos.add_nested_repo();
+
+    nasty_boii(repos.data_dir())
+        .args(["--nested", "only"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("inner"))
+        .stdout(predicate::str::contains("nasty-repo").not());
+}
+
+#[test]
+fn test_diff_between_scans() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+
+    nasty_boii(data_dir.path())
+        .arg(repos.path())
+        .assert()
+        .success();
+
+    // Clean up nasty-repo by pushing it
+    std::process::Command::new("git")
+        .args(["push", "--quiet"])
+        .current_dir(&repos.nasty_repo)
+        .output()
+        .unwrap();
+    let new_repo = repos.add_nested_repo();
+
+    nasty_boii(data_dir.path())
+        .arg(repos.path())
+        .assert()
+        .success();
+
+    nasty_boii(data_dir.path())
+        .arg("diff")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "+ {}",
+            canonicalize(&new_repo).unwrap().display()
+        )))
+        .stdout(predicate::str::contains(format!(
+            "- {}",
+            canonicalize(&repos.nasty_repo).unwrap().display()
+        )))
+        .stdout(predicate::str::contains("no-upstream-repo").not());
+}
+
+#[test]
+fn test_digest_since_last_run() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+    let scan = || {
+        nasty_boii(data_dir.path())
+            .arg(repos.path())
+            .assert()
+            .success();
+    };
+    let digest = || {
+        nasty_boii(data_dir.path())
+            .arg("digest")
+            .arg(repos.path())
+            .assert()
+            .success()
+    };
+
+    scan();
+    std::process::Command::new("git")
+        .args(["push", "--quiet"])
+        .current_dir(&repos.nasty_repo)
+        .output()
+        .unwrap();
+    let new_repo = repos.add_nested_repo();
+    scan();
+
+    digest()
+        .stdout(predicate::str::contains(format!(
+            "New (1):\n  {}\n",
+            canonicalize(&new_repo).unwrap().display()
+        )))
+        .stdout(predicate::str::contains(format!(
+            "Fixed (1):\n  {}\n",
+            canonicalize(&repos.nasty_repo).unwrap().display()
+        )))
+        .stdout(predicate::str::contains("Still nasty (1):"))
+        .stdout(predicate::str::contains(
+            "no-upstream-repo (less than a day)",
+        ));
+
+    // Nothing was scanned since the last digest
+    digest()
+        .stdout(predicate::str::contains("New").not())
+        .stdout(predicate::str::contains("Still nasty (2):"));
+}
+
+#[test]
+fn test_diff_without_history_fails() {
+    let data_dir = tempfile::tempdir().unwrap();
+
+    nasty_boii(data_dir.path()).arg("diff").assert().failure();
+}
+
+#[test]
+fn test_db_query() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+
+    nasty_boii(data_dir.path())
+        .arg(repos.path())
+        .assert()
+        .success();
+
+    nasty_boii(data_dir.path())
+        .args(["db", "query", "SELECT COUNT(*) AS nasty FROM scan_repos"])
+        .assert()
+        .success()
+        .stdout("nasty\n2\n");
+}
+
+#[test]
+fn test_count() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .arg("--count")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout("2\n");
+}
+
+#[test]
+fn test_count_all() {
+    let repos = TestRepos::new();
+
+    let lfs_tmp = repos.clean_repo.join(".git").join("lfs").join("tmp");
+    std::fs::create_dir_all(&lfs_tmp).unwrap();
+    std::fs::write(lfs_tmp.join("partial"), "").unwrap();
+    nasty_boii(repos.data_dir())
+        .arg("ack")
+        .arg(&repos.nasty_repo)
+        .assert()
+        .success();
+
+    // Each status is counted on its own, acknowledged repos apart from them
+    nasty_boii(repos.data_dir())
+        .args(["--count", "--all"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "unpushed: 1\nunpushed-lfs: 0\nunpushed-notes: 0\nunpushed-refs: 0\n\
+             transfer-in-progress: 1\nconflicted: 0\noperation-in-progress: 0\n\
+             missing-head: 0\nclean: 2\nacked: 1\nerror: 0\ndirectories: ",
+        ));
+
+    // Followed by what the walker skipped
+    let exclude_file = repos.path().join(".exclude");
+    std::fs::write(&exclude_file, "nasty-repo/\n").unwrap();
+    nasty_boii(repos.data_dir())
+        .args(["--count", "--all", "--exclude-from"])
+        .arg(&exclude_file)
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::is_match(r"\ndirectories: [1-9]\d*\npruned: 1\nunreadable: 0\n")
+                .unwrap(),
+        );
+    nasty_boii(repos.data_dir())
+        .arg("--count")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout("2\n");
+}
+
+#[test]
+fn test_prompt_uses_last_scan() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+
+    nasty_boii(data_dir.path())
+        .arg("prompt")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+
+    nasty_boii(data_dir.path())
+        .arg(repos.path())
+        .assert()
+        .success();
+
+    nasty_boii(data_dir.path())
+        .args(["prompt", "--symbol", "!"])
+        .assert()
+        .success()
+        .stdout("! 2\n");
+}
+
+#[test]
+fn test_check() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .arg("check")
+        .arg(&repos.clean_repo)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::ends_with("clean-repo: clean\n"));
+    nasty_boii(repos.data_dir())
+        .arg("check")
+        .arg(&repos.nasty_repo)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("nasty-repo: unpushed\n  "));
+    nasty_boii(repos.data_dir())
+        .args(["check", "--format", "json"])
+        .arg(&repos.nasty_repo)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::starts_with("{"))
+        .stdout(predicate::str::contains(r#""status": "unpushed""#));
+    nasty_boii(repos.data_dir())
+        .arg("check")
+        .arg(repos.path())
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Error:"));
+}
+
+#[test]
+fn test_shell_hook() {
+    cargo_bin_cmd!()
+        .args(["shell-hook", "zsh"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "add-zsh-hook chpwd __nasty_boii_leave",
+        ))
+        .stdout(predicate::str::contains("' check \"$top\""));
+    cargo_bin_cmd!()
+        .args(["shell-hook", "fish"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("--on-variable PWD"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_install_git_hook() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+
+    nasty_boii(data_dir.path())
+        .args(["install-git-hook", "--post-commit"])
+        .arg(&repos.clean_repo)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Installed post-commit hook"));
+
+    let committed = std::process::Command::new("git")
+        .env("NASTY_BOII_DATA_DIR", data_dir.path())
+        .current_dir(&repos.clean_repo)
+        .args([
+            "-c",
+            "user.name=Test User",
+            "-c",
+            "user.email=test@example.com",
+        ])
+        .args([
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "-m",
+            "Registered by hook",
+        ])
+        .status()
+        .unwrap();
+    assert!(committed.success());
+
+    nasty_boii(data_dir.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean-repo"));
+}
+
+#[test]
+fn test_install_git_hook_global() {
+    let home = tempfile::tempdir().unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+
+    nasty_boii(data_dir.path())
+        .env("HOME", home.path())
+        .args(["install-git-hook", "--post-commit", "--global"])
+        .assert()
+        .success();
+
+    assert!(data_dir.path().join("git-hooks/post-commit").is_file());
+    let gitconfig = std::fs::read_to_string(home.path().join(".gitconfig")).unwrap();
+    assert!(gitconfig.contains("hooksPath"), "{gitconfig}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_install_git_hook_global_runs_own_hooks() {
+    let repos = TestRepos::new();
+    let home = tempfile::tempdir().unwrap();
+
+    nasty_boii(repos.data_dir())
+        .env("HOME", home.path())
+        .args(["install-git-hook", "--post-commit", "--global"])
+        .assert()
+        .success();
+    assert!(repos.data_dir().join("git-hooks/pre-push").is_file());
+
+    let pre_commit = repos.clean_repo.join(".git/hooks/pre-commit");
+    std::fs::write(
+        &pre_commit,
+        "#!/bin/sh\ntouch \"$(git rev-parse --git-dir)/ran\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(
+        &pre_commit,
+        std::os::unix::fs::PermissionsExt::from_mode(0o755),
+    )
+    .unwrap();
+    let committed = std::process::Command::new("git")
+        .env("HOME", home.path())
+        .env("NASTY_BOII_DATA_DIR", repos.data_dir())
+        .current_dir(&repos.clean_repo)
+        .args([
+            "-c",
+            "user.name=Test User",
+            "-c",
+            "user.email=test@example.com",
+        ])
+        .args(["commit", "--quiet", "--allow-empty", "-m", "Own hook"])
+        .status()
+        .unwrap();
+    assert!(committed.success());
+    assert!(repos.clean_repo.join(".git/ran").exists());
+}
+
+#[test]
+fn test_template() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .arg("--template")
+        .arg(r"{status}\t{branch}\t{ahead}")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout("unpushed\tmain\t1\nunpushed\tmain\t-\n");
+}
+
+#[test]
+fn test_template_unknown_placeholder() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .arg("--template")
+        .arg("{nope}")
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown placeholder {nope}"));
+}
+
+#[test]
+fn test_include_notes() {
+    let repos = TestRepos::new();
+    std::process::Command::new("git")
+        .args(["notes", "add", "-m", "Reviewed"])
+        .current_dir(&repos.clean_repo)
+        .output()
+        .unwrap();
+
+    nasty_boii(repos.data_dir())
+        .arg("--no-history")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean-repo").not());
+
+    nasty_boii(repos.data_dir())
+        .args([
+            "--no-history",
+            "--include-notes",
+            "--template",
+            "{status}\t{notes}",
+        ])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("unpushed-notes\tcommits\n"));
+}
+
+#[test]
+fn test_all_refs() {
+    let repos = TestRepos::new();
+    // Keep a replacement commit only reachable from refs/replace
+    std::fs::write(repos.clean_repo.join("README.md"), "# Replaced\n").unwrap();
+    for args in [
+        &["commit", "-am", "Replacement"][..],
+        &["update-ref", "refs/replace/local", "HEAD"],
+        &["reset", "--hard", "origin/main"],
+    ] {
+        std::process::Command::new("git")
+            .args(args)
+            .current_dir(&repos.clean_repo)
+            .output()
+            .unwrap();
+    }
+
+    nasty_boii(repos.data_dir())
+        .args([
+            "--no-history",
+            "--all-refs",
+            "--template",
+            "{status}\t{refs}",
+        ])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "unpushed-refs\trefs/replace/local\n",
+        ));
+}
+
+#[test]
+fn test_retries_repo_under_maintenance() {
+    let repos = TestRepos::new();
+    std::fs::write(repos.nasty_repo.join(".git").join("gc.pid"), "1 localhost").unwrap();
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--max-retries", "1"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Maintenance still running"))
+        .stdout(predicate::str::contains("nasty-repo"));
+}
+
+#[test]
+fn test_explain() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .arg("--explain")
+        .arg(&repos.nasty_repo)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("HEAD: refs/heads/main at "))
+        .stdout(predicate::str::contains("Upstream: origin/main at "))
+        .stdout(predicate::str::contains("Ahead/behind: 1 ahead, 0 behind"))
+        .stdout(predicate::str::contains(
+            "  ahead: fired: Branch main is 1 commit(s) ahead of its upstream",
+        ))
+        .stdout(predicate::str::contains(
+            "Status: unpushed, from the first check that fired",
+        ));
+
+    nasty_boii(repos.data_dir())
+        .args(["--explain"])
+        .arg(&repos.clean_repo)
+        .args(["--checks", "ahead,dirty"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("  dirty: passed"))
+        .stdout(predicate::str::contains("Status: clean, no check fired"));
+}
+
+#[test]
+fn test_extra_gitdir() {
+    let home = tempfile::tempdir().unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    let cfg = home.path().join(".cfg");
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .arg("--git-dir")
+            .arg(&cfg)
+            .arg("--work-tree")
+            .arg(home.path())
+            .args([
+                "-c",
+                "user.name=Test User",
+                "-c",
+                "user.email=test@example.com",
+            ])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+    };
+    std::process::Command::new("git")
+        .args(["init", "--bare"])
+        .arg(&cfg)
+        .output()
+        .unwrap();
+    git(&["config", "status.showUntrackedFiles", "no"]);
+    std::fs::write(home.path().join(".bashrc"), "set -o vi\n").unwrap();
+    git(&["add", ".bashrc"]);
+    git(&["commit", "-m", "Add bashrc"]);
+    std::fs::create_dir(home.path().join("src")).unwrap();
+    let mut extra_gitdir = cfg.clone().into_os_string();
+    extra_gitdir.push(":");
+    extra_gitdir.push(home.path());
+
+    // Everything else in the home directory is untracked, which doesn't count here
+    nasty_boii(data_dir.path())
+        .args(["--no-history", "--checks", "dirty", "--extra-gitdir"])
+        .arg(&extra_gitdir)
+        .arg(home.path().join("src"))
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+
+    std::fs::write(home.path().join(".bashrc"), "set -o emacs\n").unwrap();
+    nasty_boii(data_dir.path())
+        .args(["--no-history", "--checks", "dirty", "--extra-gitdir"])
+        .arg(&extra_gitdir)
+        .arg(home.path().join("src"))
+        .assert()
+        .success()
+        .stdout(format!("{}\n", cfg.display()));
+}
+
+#[test]
+fn test_canonical_json() {
+    let repos = TestRepos::new();
+
+    let output = nasty_boii(repos.data_dir())
+        .args(["--no-history", "--format", "json", "--canonical"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<_> = output.lines().collect();
+    assert_eq!(lines.first(), Some(&"["));
+    assert_eq!(lines.last(), Some(&"]"));
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].starts_with(r#"{"ahead":"#));
+    let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 2);
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--canonical"])
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--canonical only works with JSON output",
+        ));
+}
+
+#[test]
+fn test_sort() {
+    let repos = TestRepos::new();
+    // An old commit in no-upstream-repo, which comes after nasty-repo by path
+    let output = std::process::Command::new("git")
+        .args([
+            "commit",
+            "--allow-empty",
+            "-m",
+            "Old",
+            "--date",
+            "2001-01-01T00:00:00",
+        ])
+        .env("GIT_COMMITTER_DATE", "2001-01-01T00:00:00")
+        .current_dir(&repos.no_upstream_repo)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--sort", "last-commit"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}\n{}\n",
+            repos.no_upstream_repo.display(),
+            repos.nasty_repo.display()
+        ));
+
+    // Never pushed comes first
+    nasty_boii(repos.data_dir())
+        .args([
+            "--no-history",
+            "--sort",
+            "last-push",
+            "--template",
+            "{last_push}",
+        ])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"^-\n\d{4}-\d{2}-\d{2}\n$").unwrap());
+}
+
+#[test]
+fn test_show_commits() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--show-commits"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}\n  Add unpushed changes\n{}\n  Initial commit\n",
+            repos.nasty_repo.display(),
+            repos.no_upstream_repo.display()
+        ));
+
+    let output = nasty_boii(repos.data_dir())
+        .args(["--no-history", "--show-commits=1", "--format", "json"])
+        .arg(&repos.nasty_repo)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(
+        json[0]["unpushed_commits"],
+        serde_json::json!(["Add unpushed changes"])
+    );
+}
+
+#[test]
+fn test_checks() {
+    let repos = TestRepos::new();
+    std::fs::write(repos.clean_repo.join("untracked.txt"), "content").unwrap();
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--checks", "dirty"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean-repo"))
+        .stdout(predicate::str::contains("nasty-repo").not());
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--checks", "nope"])
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown check nope"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_open_file_limit() {
+    let repos = TestRepos::new();
+
+    // Room for a single open repository, the other threads have to wait
+    let output = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(r#"ulimit -n 48 && exec "$0" "$@""#)
+        .arg(assert_cmd::cargo::cargo_bin!("nasty-boii"))
+        .args(["--no-history", "--verbose", "--threads", "8"])
+        .arg(repos.path())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    assert!(stdout.contains("Limiting open repositories to the open file limit"));
+    assert!(stdout.contains(&format!("{}\n", repos.nasty_repo.display())));
+    assert!(stdout.contains(&format!("{}\n", repos.no_upstream_repo.display())));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_hook_check() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let repos = TestRepos::new();
+    let script_dir = tempfile::tempdir().unwrap();
+    let script = script_dir.path().join("env-check.sh");
+    std::fs::write(
+        &script,
+        "#!/bin/sh\n[ -e .env ] && echo 'Found .env' && exit 1\nexit 0\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::write(repos.clean_repo.join(".env"), "SECRET=1").unwrap();
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--template", "{findings}", "--hook-check"])
+        .arg(&script)
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hook:env-check.sh: Found .env\n"));
+}
+
+#[test]
+fn test_ack_hides_repo_until_head_moves() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+
+    nasty_boii(data_dir.path())
+        .args(["ack", "--reason", "experiment", "--until", "2999-01-01"])
+        .arg(&repos.nasty_repo)
+        .assert()
+        .success();
+
+    nasty_boii(data_dir.path())
+        .arg("--no-history")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nasty-repo").not())
+        .stdout(predicate::str::contains("no-upstream-repo"));
+
+    nasty_boii(data_dir.path())
+        .args(["--no-history", "--show-acked"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nasty-repo"));
+
+    std::process::Command::new("git")
+        .args(["commit", "--allow-empty", "-m", "More work"])
+        .current_dir(&repos.nasty_repo)
+        .output()
+        .unwrap();
+    nasty_boii(data_dir.path())
+        .arg("--no-history")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nasty-repo"));
+}
+
+#[test]
+fn test_ack_rejects_invalid_date() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .args(["ack", "--until", "tomorrow"])
+        .arg(&repos.nasty_repo)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected YYYY-MM-DD"));
+}
+
+#[cfg(all(unix, not(feature = "read-only")))]
+#[test]
+fn test_exec() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .args([
+            "--allow-writes",
+            "--exec",
+            "git -C {} rev-parse --show-toplevel",
+            "--exec-jobs",
+            "2",
+        ])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nasty-repo"))
+        .stdout(predicate::str::contains("no-upstream-repo"))
+        .stderr(predicate::str::contains("2 succeeded, 0 failed"));
+    // Commands are logged in the data directory of the test
+    let audit_log = std::fs::read_to_string(repos.data_dir().join("audit.log")).unwrap();
+    assert_eq!(audit_log.lines().count(), 2);
+}
+
+#[cfg(all(unix, not(feature = "read-only")))]
+#[test]
+fn test_exec_failure() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .args(["--allow-writes", "--exec", "test -d /nonexistent"])
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("0 succeeded, 2 failed"))
+        .stderr(predicate::str::contains("failed: "));
+}
+
+#[cfg(all(unix, not(feature = "read-only")))]
+#[test]
+fn test_exec_non_interactive() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let repos = TestRepos::new();
+    let askpass = repos.path().join("askpass");
+    std::fs::write(&askpass, "#!/bin/sh\necho secret\n").unwrap();
+    std::fs::set_permissions(&askpass, std::fs::Permissions::from_mode(0o755)).unwrap();
+    // Asks for a username and password like a push over HTTPS would
+    let ask = "printf 'protocol=https\\nhost=example.com\\n\\n' \
+               | git -C {} -c credential.helper= credential fill";
+
+    nasty_boii(repos.data_dir())
+        .env("GIT_ASKPASS", &askpass)
+        .args(["--allow-writes", "--exec", ask])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("password=secret"))
+        .stderr(predicate::str::contains("2 succeeded, 0 failed"));
+
+    nasty_boii(repos.data_dir())
+        .env("GIT_ASKPASS", &askpass)
+        .args(["--allow-writes", "--non-interactive", "--exec", ask])
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("password=").not())
+        .stderr(predicate::str::contains("0 succeeded, 2 failed"));
+}
+
+#[cfg(all(unix, not(feature = "read-only")))]
+#[test]
+fn test_audit_log() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+
+    nasty_boii(data_dir.path())
+        .args(["--allow-writes", "--exec", "git -C {} push --quiet"])
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("1 succeeded, 1 failed"));
+
+    nasty_boii(data_dir.path())
+        .args(["audit-log", "show"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "nasty-repo-remote.git, 1 commits pushed",
+        ))
+        .stdout(predicate::str::contains("no-upstream-repo"))
+        .stdout(predicate::str::contains("exit 0"));
+}
+
+#[test]
+fn test_read_only() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .arg("--read-only")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nasty-repo"));
+    nasty_boii(repos.data_dir())
+        .args(["--read-only", "--exec", "git -C {} push"])
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    // Read-only is the default
+    #[cfg(not(feature = "read-only"))]
+    nasty_boii(repos.data_dir())
+        .args(["--exec", "git -C {} push"])
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Add --allow-writes"));
+    #[cfg(feature = "read-only")]
+    nasty_boii(repos.data_dir())
+        .args(["--allow-writes", "--exec", "git -C {} push"])
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "not available in read-only builds",
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_open_with() {
+    let repos = TestRepos::new();
+
+    // The opener inherits stdout, so its output is captured even though it isn't awaited
+    nasty_boii(repos.data_dir())
+        .args(["--open-with", "echo opening"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match("opening .*nasty-repo").unwrap());
+}
+
+#[cfg(windows)]
+#[test]
+fn test_junction_cycle_is_not_followed() {
+    let repos = TestRepos::new();
+    let status = std::process::Command::new("cmd")
+        .args(["/C", "mklink", "/J"])
+        .arg(repos.path().join("loop"))
+        .arg(repos.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    nasty_boii(repos.data_dir())
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nasty-repo"))
+        .stdout(predicate::str::contains("loop").not());
+}
+
+#[cfg(windows)]
+#[test]
+fn test_register_normalizes_drive_letter() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+    let path = repos.nasty_repo.to_str().unwrap();
+
+    for drive in [
+        path[..1].to_ascii_lowercase(),
+        path[..1].to_ascii_uppercase(),
+    ] {
+        nasty_boii(data_dir.path())
+            .arg("register")
+            .arg(format!("{drive}{}", &path[1..]))
+            .assert()
+            .success();
+    }
+
+    nasty_boii(data_dir.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(r"\\?\").not())
+        .stdout(format!(
+            "{}\n",
+            canonicalize(&repos.nasty_repo).unwrap().display()
+        ));
+}
+
+#[test]
+fn test_unscannable_paths_are_reported() {
+    let data_dir = tempfile::tempdir().unwrap();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let missing = temp_dir.path().join("missing");
+
+    nasty_boii(data_dir.path())
+        .arg(&missing)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("1 unscannable path(s):"))
+        .stderr(predicate::str::contains("missing"));
+
+    nasty_boii(data_dir.path())
+        .arg("--strict-walk")
+        .arg(&missing)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("1 path(s) could not be scanned"));
+}
+
+#[test]
+fn test_finds_linked_worktree() {
+    let repos = TestRepos::new();
+    std::process::Command::new("git")
+        .args(["worktree", "add", "-b", "feature", "../feature-worktree"])
+        .current_dir(&repos.clean_repo)
+        .output()
+        .unwrap();
+
+    // The new branch has no upstream, so the worktree is listed
+    nasty_boii(repos.data_dir())
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("feature-worktree"))
+        .stdout(predicate::str::contains("clean-repo-remote").not());
+}
+
+#[test]
+fn test_flag_shallow() {
+    let repos = TestRepos::new();
+    let remote = repos.path().join("clean-repo-remote.git");
+    std::process::Command::new("git")
+        .args(["clone", "--depth", "1"])
+        .arg(format!("file://{}", remote.display()))
+        .arg(repos.path().join("shallow-clone"))
+        .output()
+        .unwrap();
+
+    nasty_boii(repos.data_dir())
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("shallow-clone").not());
+
+    nasty_boii(repos.data_dir())
+        .args([
+            "--flag-shallow",
+            "--template",
+            "{path} {shallow}: {findings}",
+        ])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "shallow-clone yes: shallow: Shallow clone, history is incomplete",
+        ));
+}
+
+#[test]
+fn test_conflicted() {
+    let repos = TestRepos::new();
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args([
+                "-c",
+                "user.name=Test User",
+                "-c",
+                "user.email=test@example.com",
+            ])
+            .args(args)
+            .current_dir(&repos.clean_repo)
+            .output()
+            .unwrap();
+    };
+    git(&["checkout", "-b", "other"]);
+    std::fs::write(repos.clean_repo.join("conflict.txt"), "other\n").unwrap();
+    git(&["add", "conflict.txt"]);
+    git(&["commit", "-m", "Other side"]);
+    git(&["checkout", "-"]);
+    std::fs::write(repos.clean_repo.join("conflict.txt"), "ours\n").unwrap();
+    git(&["add", "conflict.txt"]);
+    git(&["commit", "-m", "Our side"]);
+    git(&["merge", "other"]);
+
+    nasty_boii(repos.data_dir())
+        .args([
+            "--no-history",
+            "--template",
+            "{path} {status} {conflicted}: {findings}",
+        ])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean-repo conflicted 1: "))
+        .stdout(predicate::str::contains("conflicts: 1 conflicted file(s)"));
+}
+
+#[test]
+fn test_sparse_checkout() {
+    let repos = TestRepos::new();
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args([
+                "-c",
+                "user.name=Test User",
+                "-c",
+                "user.email=test@example.com",
+            ])
+            .args(args)
+            .current_dir(&repos.clean_repo)
+            .output()
+            .unwrap();
+    };
+    for dir in ["inside", "outside"] {
+        std::fs::create_dir(repos.clean_repo.join(dir)).unwrap();
+        std::fs::write(repos.clean_repo.join(dir).join("file.txt"), dir).unwrap();
+    }
+    git(&["add", "."]);
+    git(&["commit", "-m", "Add directories"]);
+    git(&["push"]);
+    git(&["sparse-checkout", "set", "inside"]);
+    assert!(!repos.clean_repo.join("outside").exists());
+
+    // Files outside the sparse checkout are missing, not deleted
+    nasty_boii(repos.data_dir())
+        .args(["--checks", "dirty", "--explain"])
+        .arg(&repos.clean_repo)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("  dirty: passed"));
+
+    std::fs::write(repos.clean_repo.join("inside").join("file.txt"), "changed").unwrap();
+    nasty_boii(repos.data_dir())
+        .args([
+            "--no-history",
+            "--checks",
+            "dirty",
+            "--template",
+            "{path} {dirty} {sparse}",
+        ])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean-repo yes yes"));
+}
+
+#[test]
+fn test_queue_size() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .args(["--queue-size", "1", "--count"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout("2\n");
+    nasty_boii(repos.data_dir())
+        .args(["--queue-size", "1", "--any"])
+        .arg(repos.path())
+        .assert()
+        .code(1)
+        .stdout("");
+}
+
+#[test]
+fn test_priority() {
+    let repos = TestRepos::new();
+    let nasty_repo = repos.path().join("nasty-repo");
+    let no_upstream_repo = repos.path().join("no-upstream-repo");
+    // no-upstream-repo was worked on most recently
+    let later = std::time::SystemTime::now() + std::time::Duration::from_hours(1);
+    std::fs::File::options()
+        .write(true)
+        .open(no_upstream_repo.join(".git/index"))
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+
+    let expected = format!("{}\n{}\n", no_upstream_repo.display(), nasty_repo.display());
+    nasty_boii(repos.data_dir())
+        .args(["--threads", "1", "--priority", "mtime"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(expected);
+
+    let expected = format!("{}\n{}\n", nasty_repo.display(), no_upstream_repo.display());
+    nasty_boii(repos.data_dir())
+        .args(["--threads", "1", "--priority", "path"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(expected);
+}
+
+#[test]
+fn test_sample() {
+    let repos = TestRepos::new();
+
+    nasty_boii(&repos.path().join("data"))
+        .args(["--sample", "100%"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nasty-repo"))
+        .stderr(predicate::str::contains(
+            "Sampled 5 of 5 repos, 2 nasty. Estimated 2 nasty repos in total \
+             (95% confidence: 2 to 2)",
+        ));
+    // A sample says nothing about single repos, so it is not recorded
+    nasty_boii(&repos.path().join("data"))
+        .arg("digest")
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No scans"));
+
+    nasty_boii(repos.data_dir())
+        .args(["--sample", "0"])
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected a percentage"));
+}
+
+#[test]
+fn test_resume() {
+    let repos = TestRepos::new();
+    let data_dir = repos.path().join("data");
+    {
+        // As if a scan was interrupted after finding clean-repo nasty and checking
+        // nasty-repo, whose directory is the one it was still walking
+        let db = Db::open(&data_dir.join("nasty-boii.db")).unwrap();
+        let checkpoints = Checkpoints::new(&db);
+        let root = canonicalize(repos.path()).unwrap().display().to_string();
+        checkpoints
+            .add_repo(&root, Path::new("clean-repo"), true)
+            .unwrap();
+        checkpoints
+            .add_subtree(&root, Path::new("clean-repo"))
+            .unwrap();
+        checkpoints
+            .add_repo(&root, Path::new("nasty-repo"), false)
+            .unwrap();
+    }
+
+    // Scans without --resume leave the checkpoint alone
+    nasty_boii(&data_dir).arg(repos.path()).assert().success();
+
+    // Results of the interrupted scan are used instead of checking again
+    nasty_boii(&data_dir)
+        .arg("--resume")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean-repo"))
+        .stdout(predicate::str::contains("no-upstream-repo"))
+        .stdout(predicate::str::contains("nasty-repo\n").not());
+
+    // The completed scan removed its checkpoint
+    nasty_boii(&data_dir)
+        .arg("--resume")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean-repo").not())
+        .stdout(predicate::str::contains("nasty-repo\n"));
+}
+
+#[test]
+fn test_against() {
+    let repos = TestRepos::new();
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args([
+                "-c",
+                "user.name=Test User",
+                "-c",
+                "user.email=test@example.com",
+            ])
+            .args(args)
+            .current_dir(&repos.clean_repo)
+            .output()
+            .unwrap();
+    };
+    git(&["checkout", "-b", "feature"]);
+    git(&["commit", "--allow-empty", "-m", "Feature"]);
+    git(&["push", "-u", "origin", "feature"]);
+
+    nasty_boii(repos.data_dir())
+        .arg("--no-history")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean-repo").not());
+
+    nasty_boii(repos.data_dir())
+        .args([
+            "--no-history",
+            "--against",
+            "origin/main",
+            "--template",
+            "{path}: {findings}",
+        ])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "clean-repo: ahead: Branch feature is 1 commit(s) ahead of origin/main",
+        ))
+        .stdout(predicate::str::contains(
+            "no-upstream-repo: ahead: Branch main cannot be compared, origin/main does not exist",
+        ));
+}
+
+#[test]
+fn test_verify_on_host() {
+    let repos = TestRepos::new();
+    std::process::Command::new("git")
+        .args([
+            "remote",
+            "set-url",
+            "origin",
+            "https://github.com/owner/gone.git",
+        ])
+        .current_dir(&repos.clean_repo)
+        .output()
+        .unwrap();
+    // Fake GitHub API that has lost every commit
+    let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+    let api = format!("http://{}", server.server_addr());
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let status = if request.url().starts_with("/repos/owner/gone/commits/") {
+                422
+            } else {
+                500
+            };
+            let _ = request.respond(tiny_http::Response::empty(status));
+        }
+    });
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--verify-on-host", "--template", "{path}: {findings}"])
+        .arg(repos.path())
+        .env("GITHUB_API_URL", &api)
+        .env_remove("GITHUB_TOKEN")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(
+            "clean-repo: on-host: Upstream origin/main at [0-9a-f]{7} does not exist on github.com",
+        ).unwrap());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_skip_readonly() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // SAFETY: geteuid has no preconditions.
+    if unsafe { libc::geteuid() } == 0 {
+        // Permissions don't stop root, only read-only mounts do
+        return;
+    }
+    let repos = TestRepos::new();
+    std::fs::set_permissions(&repos.nasty_repo, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+    let assert = nasty_boii(repos.data_dir())
+        .args(["--no-history", "--skip-readonly"])
+        .arg(repos.path())
+        .assert();
+    std::fs::set_permissions(&repos.nasty_repo, std::fs::Permissions::from_mode(0o755)).unwrap();
+    assert
+        .success()
+        .stdout(predicate::str::contains("no-upstream-repo"))
+        .stdout(predicate::str::contains("nasty-repo\n").not());
+}
+
+#[test]
+fn test_health() {
+    let repos = TestRepos::new();
+    let sample = repos.clean_repo.join(".git/objects/17");
+    std::fs::create_dir_all(&sample).unwrap();
+    for i in 0..30 {
+        std::fs::write(sample.join(format!("{i:038x}")), "").unwrap();
+    }
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--health"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean-repo").not())
+        .stderr(predicate::str::contains(
+            "clean-repo: About 7680 loose objects, git gc would pack them",
+        ));
+}
+
+#[test]
+fn test_assume_push_default_current() {
+    let repos = TestRepos::new();
+    for args in [
+        &["checkout", "--quiet", "-b", "feature"][..],
+        &["push", "--quiet", "origin", "feature"],
+    ] {
+        std::process::Command::new("git")
+            .args(args)
+            .current_dir(&repos.clean_repo)
+            .output()
+            .unwrap();
+    }
+
+    nasty_boii(repos.data_dir())
+        .arg("--no-history")
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean-repo"));
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--assume-push-default", "current"])
+        .args(["--format", "json"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean-repo").not())
+        .stdout(predicate::str::contains("no-upstream-repo"));
+}
+
+#[test]
+fn test_push_remote() {
+    let repos = TestRepos::new();
+    let fork = repos.path().join("fork.git");
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(args)
+            .current_dir(&repos.clean_repo)
+            .output()
+            .unwrap();
+    };
+    std::process::Command::new("git")
+        .args(["init", "--quiet", "--bare"])
+        .arg(&fork)
+        .output()
+        .unwrap();
+    git(&["remote", "add", "fork", fork.to_str().unwrap()]);
+    git(&["config", "remote.pushDefault", "fork"]);
+
+    let scan = || {
+        nasty_boii(repos.data_dir())
+            .args([
+                "--no-history",
+                "--template",
+                "{path} {ahead} {push_remote} {push_ahead}: {findings}",
+            ])
+            .arg(repos.path())
+            .assert()
+            .success()
+    };
+    scan().stdout(predicate::str::contains(
+        "clean-repo 0 fork -: ahead: Branch main was never pushed to fork",
+    ));
+
+    git(&["push", "--quiet", "fork", "main"]);
+    scan().stdout(predicate::str::contains("clean-repo").not());
+}
+
+#[test]
+fn test_offline_audit() {
+    let repos = TestRepos::new();
+    let paths_file = repos.path().join("paths.txt");
+    // Paths as they were on the original machine, where the disk was mounted at /
+    std::fs::write(&paths_file, "/nasty-repo\n/clean-repo/.git\n").unwrap();
+
+    nasty_boii(repos.data_dir())
+        .args(["offline-audit", "--from"])
+        .arg(&paths_file)
+        .arg("--objects-dir")
+        .arg(repos.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::starts_with("/nasty-repo\n"))
+        .stdout(predicate::str::contains(
+            "Audited 2 repositories, 1 at risk",
+        ));
+}
+
+#[test]
+fn test_markdown_dates_follow_locale() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .env("LC_ALL", "de_DE.UTF-8")
+        .args(["--format", "markdown"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"\| \d{2}\.\d{2}\.\d{4} ").unwrap());
+
+    nasty_boii(repos.data_dir())
+        .env("LC_ALL", "de_DE.UTF-8")
+        .args(["--format", "markdown", "--utc", "--iso-dates"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"\| \d{4}-\d{2}-\d{2} ").unwrap());
+}
+
+#[test]
+fn test_log_format_json() {
+    let repos = TestRepos::new();
+
+    let output = nasty_boii(repos.data_dir())
+        .args(["--log-level", "debug", "--log-format", "json"])
+        .arg(repos.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let nasty = stdout
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .find(|log| {
+            log["message"] == "Repository is nasty"
+                && log["spans"][0]["path"]
+                    .as_str()
+                    .unwrap()
+                    .ends_with("nasty-repo")
+        })
+        .unwrap();
+    assert_eq!(nasty["level"], "DEBUG");
+    assert_eq!(nasty["fields"]["status"], "unpushed");
+    assert_eq!(nasty["spans"][0]["name"], "repo");
+    assert_eq!(nasty["spans"][0]["branch"], "main");
+    assert!(nasty["spans"][0]["duration_ms"].is_u64());
+}
+
+#[test]
+fn test_log_file() {
+    let repos = TestRepos::new();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let log_file = temp_dir.path().join("nasty-boii.log");
+
+    nasty_boii(repos.data_dir())
+        .arg("--verbose")
+        .arg("--log-file")
+        .arg(&log_file)
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nasty-repo"))
+        .stdout(predicate::str::contains("Starting repository scan").not());
+
+    let log = std::fs::read_to_string(&log_file).unwrap();
+    assert!(log.contains("Starting repository scan"));
+    // No color codes in files
+    assert!(!log.contains('\x1b'));
+}
+
+#[test]
+fn test_log_file_rotates() {
+    let repos = TestRepos::new();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let log_file = temp_dir.path().join("nasty-boii.log");
+
+    nasty_boii(repos.data_dir())
+        .args(["--log-level", "debug", "--log-max-size", "1K", "--log-file"])
+        .arg(&log_file)
+        .arg(repos.path())
+        .assert()
+        .success();
+
+    assert!(std::fs::metadata(&log_file).unwrap().len() < 2048);
+    assert!(temp_dir.path().join("nasty-boii.log.1").is_file());
+}
+
+#[test]
+fn test_status_file() {
+    let repos = TestRepos::new();
+    let status_file = repos.path().join("status");
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--status-file"])
+        .arg(&status_file)
+        .arg(&repos.nasty_repo)
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(&status_file).unwrap(),
+        format!("1\n{}\n", repos.nasty_repo.display())
+    );
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--status-format", "json", "--status-file"])
+        .arg(&status_file)
+        .arg(&repos.clean_repo)
+        .assert()
+        .success();
+    let status: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&status_file).unwrap()).unwrap();
+    assert_eq!(status["nasty"], 0);
+    assert_eq!(status["repos"], serde_json::json!([]));
+    assert!(!repos.path().join("status.tmp").exists());
+}
+
+#[test]
+fn test_stats() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--stats"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("walk:").not())
+        .stderr(predicate::str::is_match(r"Checked \d+ repos in \d+\.\d{3}s").unwrap())
+        .stderr(predicate::str::contains("walk:"))
+        .stderr(predicate::str::contains("checks:"))
+        .stderr(predicate::str::contains("output:"));
+
+    let exclude_file = repos.path().join(".exclude");
+    std::fs::write(&exclude_file, "nasty-repo/\n").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&repos.clean_repo, repos.path().join("link")).unwrap();
+    let output = nasty_boii(repos.data_dir())
+        .args([
+            "--no-history",
+            "--stats",
+            "--format",
+            "json",
+            "--exclude-from",
+        ])
+        .arg(&exclude_file)
+        .arg(repos.path())
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stats: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(stats["pruned"], 1);
+    assert_eq!(stats["unreadable"], 0);
+    assert!(stats["directories"].as_u64().unwrap() > 0);
+    #[cfg(unix)]
+    assert_eq!(stats["symlinks"], 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_interrupt_prints_partial_results() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::process::ExitStatusExt;
+
+    let repos = TestRepos::new();
+    let script_dir = tempfile::tempdir().unwrap();
+    let started = script_dir.path().join("started");
+    // Flag every repo, slowly enough to interrupt the scan after the first one
+    let script = script_dir.path().join("slow-check.sh");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\ntouch '{}'\nsleep 1\necho slow\nexit 1\n",
+            started.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let child = std::process::Command::new(env!("CARGO_BIN_EXE_nasty-boii"))
+        .args(["--no-history", "--threads", "1", "--hook-check"])
+        .arg(&script)
+        .arg(repos.path())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    while !started.exists() {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    // SAFETY: kill has no memory safety preconditions.
+    unsafe { libc::kill(child.id().cast_signed(), libc::SIGINT) };
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(
+        output.status.code(),
+        Some(130),
+        "{:?}",
+        output.status.signal()
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // Without the interrupt, all four repos with a HEAD would be flagged
+    assert!(!stdout.is_empty() && stdout.lines().count() < 4, "{stdout}");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Scan interrupted after checking"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_any() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--any"])
+        .arg(repos.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::is_empty());
+
+    nasty_boii(repos.data_dir())
+        .args(["--no-history", "--any"])
+        .arg(&repos.clean_repo)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+}
+
+#[test]
+fn test_report_history() {
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+
+    nasty_boii(data_dir.path())
+        .arg(repos.path())
+        .assert()
+        .success();
+
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    nasty_boii(data_dir.path())
+        .args(["report", "--history"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(format!("{today}    2 #")));
+
+    nasty_boii(data_dir.path())
+        .args(["report", "--history", "--format", "html"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("<!DOCTYPE html>"))
+        .stdout(predicate::str::contains(format!(
+            "<title>{today}: 2</title>"
+        )));
+}
+
+/// Writes an ssh stand-in that runs the remote command locally, failing for host `down`.
+#[cfg(unix)]
+fn fake_ssh(dir: &std::path::Path) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let ssh = dir.join("fake-ssh");
+    std::fs::write(
+        &ssh,
+        "#!/bin/sh\n[ \"$1\" = down ] && echo 'Connection refused' >&2 && exit 255\nshift\nexec sh -c \"$*\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&ssh, std::fs::Permissions::from_mode(0o755)).unwrap();
+    ssh
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fleet() {
+    let repos = TestRepos::new();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let hosts = temp_dir.path().join("hosts.txt");
+    std::fs::write(&hosts, "# lab\nlab-1\nlab-2\ndown\n").unwrap();
+
+    nasty_boii(temp_dir.path())
+        .args(["fleet", "--format", "json", "--hosts"])
+        .arg(&hosts)
+        .arg("--ssh")
+        .arg(fake_ssh(temp_dir.path()))
+        .args([
+            "--remote-command",
+            env!("CARGO_BIN_EXE_nasty-boii"),
+            "--root",
+        ])
+        .arg(repos.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(r#""host":"lab-1""#))
+        .stdout(predicate::str::contains(r#""host":"lab-2""#))
+        .stdout(predicate::str::contains("nasty-repo"))
+        .stderr(predicate::str::contains(
+            "2 hosts scanned, 1 failed, 4 nasty repos",
+        ))
+        .stderr(predicate::str::contains("failed: down"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fleet_upload() {
+    let repos = TestRepos::new();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let hosts = temp_dir.path().join("hosts.txt");
+    std::fs::write(&hosts, "lab-1\n").unwrap();
+
+    nasty_boii(temp_dir.path())
+        .args(["fleet", "--hosts"])
+        .arg(&hosts)
+        .arg("--ssh")
+        .arg(fake_ssh(temp_dir.path()))
+        .args(["--upload", env!("CARGO_BIN_EXE_nasty-boii"), "--root"])
+        .arg(&repos.clean_repo)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "1 hosts scanned, 0 failed, 0 nasty repos",
+        ));
+}
+
+/// Sends a GET request over plain HTTP and returns the response.
+fn http_get(addr: &str, path: &str) -> String {
+    use std::io::{Read, Write};
+
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {path} HTTP/1.0\r\nHost: {addr}\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn test_serve() {
+    use std::io::BufRead;
+
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_nasty-boii"))
+        .env("NASTY_BOII_DATA_DIR", data_dir.path())
+        .args(["serve", "--addr", "127.0.0.1:0"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    std::io::BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let addr = line.trim().strip_prefix("Listening on http://").unwrap();
+
+    let not_scanned = http_get(addr, "/last-report");
+    let root = repos.path().display().to_string().replace('/', "%2F");
+    let scan = http_get(addr, &format!("/scan?root={root}"));
+    let last_report = http_get(addr, "/last-report");
+    let missing_root = http_get(addr, "/scan");
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert!(not_scanned.starts_with("HTTP/1.0 404"), "{not_scanned}");
+    assert!(scan.starts_with("HTTP/1.0 200"), "{scan}");
+    assert!(scan.contains("application/json"), "{scan}");
+    assert!(scan.contains(r#""status":"unpushed""#), "{scan}");
+    assert!(last_report.starts_with("HTTP/1.0 200"), "{last_report}");
+    assert!(last_report.contains("nasty-repo"), "{last_report}");
+    assert!(missing_root.starts_with("HTTP/1.0 400"), "{missing_root}");
+}
+
+#[test]
+fn test_stdio_rpc() {
+    use std::io::{BufRead, Write};
+
+    let repos = TestRepos::new();
+    let data_dir = tempfile::tempdir().unwrap();
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_nasty-boii"))
+        .env("NASTY_BOII_DATA_DIR", data_dir.path())
+        .args(["--stdio-rpc", "--log-level", "debug"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let root = repos.path().display().to_string();
+    let nasty_repo = repos.nasty_repo.display().to_string();
+    for request in [
+        serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "status", "params": {"path": nasty_repo}}),
+        serde_json::json!({"jsonrpc": "2.0", "id": 2, "method": "scan", "params": {"root": root}}),
+        serde_json::json!({"jsonrpc": "2.0", "id": 3, "method": "subscribe", "params": {"root": root, "interval": 3600}}),
+        serde_json::json!({"jsonrpc": "2.0", "id": 4, "method": "push"}),
+    ] {
+        writeln!(stdin, "{request}").unwrap();
+    }
+
+    // Four responses and the first notification of the subscription
+    let mut messages: Vec<serde_json::Value> =
+        std::io::BufReader::new(child.stdout.take().unwrap())
+            .lines()
+            .take(5)
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect();
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+
+    let notification_index = messages
+        .iter()
+        .position(|m| m["method"] == "nasty")
+        .unwrap();
+    let notification = messages.remove(notification_index);
+    assert_eq!(notification["params"]["subscription"], 1);
+    assert!(notification["params"]["repos"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|repo| repo.as_str().unwrap().ends_with("nasty-repo")));
+
+    assert_eq!(messages[0]["id"], 1);
+    assert_eq!(messages[0]["result"]["status"], "unpushed");
+    assert_eq!(messages[1]["id"], 2);
+    assert!(messages[1]["result"].as_array().unwrap().len() >= 2);
+    assert_eq!(messages[2]["result"], 1);
+    assert_eq!(messages[3]["error"]["code"], -32601);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_discovery_locate() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let repos = TestRepos::new();
+    let bin_dir = tempfile::tempdir().unwrap();
+    let locate = bin_dir.path().join("locate");
+    // Stand-in for the locate database, including a repo that no longer exists
+    std::fs::write(
+        &locate,
+        format!(
+            "#!/bin/sh\nfind '{}' -name .git -print0\nprintf '%s\\0' '{}/deleted-repo/.git'\n",
+            canonicalize(repos.path()).unwrap().display(),
+            canonicalize(repos.path()).unwrap().display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&locate, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.path().display(),
+        std::env::var("PATH").unwrap()
+    );
+
+    let walked = nasty_boii(repos.data_dir())
+        .args(["--no-history"])
+        .arg(repos.path())
+        .output()
+        .unwrap();
+    let located = nasty_boii(repos.data_dir())
+        .env("PATH", path)
+        .args(["--no-history", "--discovery", "locate"])
+        .arg(repos.path())
+        .output()
+        .unwrap();
+
+    assert!(located.status.success());
+    assert!(!walked.stdout.is_empty());
+    let sorted = |stdout: Vec<u8>| {
+        let mut lines: Vec<String> = String::from_utf8(stdout)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        lines.sort();
+        lines
+    };
+    assert_eq!(sorted(located.stdout), sorted(walked.stdout));
+}
+
+#[test]
+fn test_discovery_falls_back_to_walking() {
+    let repos = TestRepos::new();
+
+    nasty_boii(repos.data_dir())
+        .env("PATH", "")
+        .args(["--no-history", "--discovery", "mdfind"])
+        .arg(repos.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "File index unavailable, walking instead",
+        ))
+        .stdout(predicate::str::contains("nasty-repo"));
+}
@@ -4,6 +4,8 @@ use tempfile::TempDir;
 
 pub struct TestRepos {
     pub temp_dir: TempDir,
+    /// Data directory for the scans of these repos, so tests keep out of the real one
+    data_dir: TempDir,
     #[allow(dead_code)]
     pub clean_repo: PathBuf,
     #[allow(dead_code)]
@@ -43,6 +45,7 @@ impl TestRepos {
 
         Self {
             temp_dir,
+            data_dir: tempfile::tempdir().unwrap(),
             clean_repo,
             nasty_repo,
             missing_head_repo,
@@ -55,6 +58,31 @@ impl TestRepos {
         self.temp_dir.path()
     }
 
+    #[allow(dead_code)]
+    pub fn data_dir(&self) -> &Path {
+        self.data_dir.path()
+    }
+
+    /// Clone nasty-repo into `clean-repo/vendor/inner` and commit to it, creating a
+    /// nested repo with unpushed changes.
+    #[allow(dead_code)]
+    pub fn add_nested_repo(&self) -> PathBuf {
+        let inner = self.clean_repo.join("vendor").join("inner");
+        std::fs::create_dir_all(inner.parent().unwrap()).unwrap();
+        Command::new("git")
+            .args(["clone"])
+            .arg(&self.nasty_repo)
+            .arg(&inner)
+            .output()
+            .unwrap();
+
+        Self::git_config(&inner, "user.name", "Test User");
+        Self::git_config(&inner, "user.email", "test@example.com");
+        std::fs::write(inner.join("README.md"), "# Nested Repo\n").unwrap();
+        Self::git_add_commit(&inner, "Nested change");
+        inner
+    }
+
     fn create_clean_repo(path: &Path) {
         // Create bare remote
         let remote_path = path.parent().unwrap().join("clean-repo-remote.git");